@@ -1,4 +1,4 @@
-// Шейдер для эффекта Bloom
+// Шейдер для эффекта Bloom: выделение ярких участков и размытие по Гауссу в половинном разрешении
 
 struct VertexOutput {
   @builtin(position) position: vec4<f32>,
@@ -8,8 +8,8 @@ struct VertexOutput {
 @vertex
 fn vertex_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
   var out: VertexOutput;
-  // Создаем полноэкранный квадрат
-  let pos = array<vec2<f32>, 6>(
+  // Создаем полноэкранный квадрат; `var`, потому что `let`-массивы нельзя индексировать переменной
+  var pos = array<vec2<f32>, 6>(
     vec2<f32>(-1.0, -1.0),
     vec2<f32>( 1.0, -1.0),
     vec2<f32>(-1.0,  1.0),
@@ -17,18 +17,10 @@ fn vertex_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
     vec2<f32>( 1.0,  1.0),
     vec2<f32>(-1.0,  1.0)
   );
-  
-  let uv = array<vec2<f32>, 6>(
-    vec2<f32>(0.0, 0.0),
-    vec2<f32>(1.0, 0.0),
-    vec2<f32>(0.0, 1.0),
-    vec2<f32>(1.0, 0.0),
-    vec2<f32>(1.0, 1.0),
-    vec2<f32>(0.0, 1.0)
-  );
-  
-  out.position = vec4<f32>(pos[vertex_index], 0.0, 1.0);
-  out.tex_coords = uv[vertex_index];
+  let p = pos[vertex_index];
+  out.position = vec4<f32>(p, 0.0, 1.0);
+  // texture rows run top to bottom
+  out.tex_coords = vec2<f32>(p.x * 0.5 + 0.5, 0.5 - p.y * 0.5);
   return out;
 }
 
@@ -37,25 +29,41 @@ fn vertex_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
 @group(0) @binding(1) var input_sampler: sampler;
 
 // Параметры bloom
-@group(0) @binding(2) var<uniform> bloom_params: vec3<f32>; // x: threshold, y: intensity, z: unused
+struct BloomParams {
+  threshold: f32,
+  intensity: f32, // applied by the composite in postprocess.wgsl
+  radius: f32,
+  _pad: f32,
+  direction: vec2<f32>, // blur axis in texels of the input
+  _pad2: vec2<f32>,
+};
+@group(0) @binding(2) var<uniform> bloom_params: BloomParams;
 
 fn luminance(color: vec3<f32>) -> f32 {
   return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
 }
 
+// Bright-pass: keeps what lies above the threshold, downsampling by the linear filter
 @fragment
 fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
   let color = textureSample(input_texture, input_sampler, in.tex_coords).rgb;
-  
+
   // Применяем порог яркости для выделения самых ярких частей
-  let brightness = max(luminance(color) - bloom_params.x, 0.0);
-  let contribution = brightness / (brightness + 1.0);
-  
-  // Создаем bloom эффект
-  let bloom = color * contribution * bloom_params.y;
-  
-  // Комбинируем оригинальное изображение с bloom
-  let final_color = color + bloom;
-  
-  return vec4<f32>(final_color, 1.0);
-}
\ No newline at end of file
+  let lum = luminance(color);
+  let contribution = max(lum - bloom_params.threshold, 0.0) / max(lum, 1e-4);
+  return vec4<f32>(color * contribution, 1.0);
+}
+
+// One axis of a 9-tap Gaussian blur
+@fragment
+fn blur_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  var weights = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+  let step = bloom_params.direction * bloom_params.radius;
+  var sum = textureSample(input_texture, input_sampler, in.tex_coords).rgb * weights[0];
+  for (var i = 1; i < 5; i = i + 1) {
+    let offset = step * f32(i);
+    sum += textureSample(input_texture, input_sampler, in.tex_coords + offset).rgb * weights[i];
+    sum += textureSample(input_texture, input_sampler, in.tex_coords - offset).rgb * weights[i];
+  }
+  return vec4<f32>(sum, 1.0);
+}
@@ -1,24 +1,11 @@
-// 3D boxes with per-instance model, global viewProj, texture support and dynamic lighting
+// 3D boxes with per-instance model, global viewProj, texture support and dynamic lighting;
+// compiled after shadow_sample.wgsl and lights.wgsl, which provide shadowFactor and the lights
 
 struct Camera {
   viewProj: mat4x4<f32>,
-};
-@group(0) @binding(0) var<uniform> uCamera: Camera;
-
-// Lighting data
-struct Light {
   position: vec3<f32>,
-  color: vec3<f32>,
-  intensity: f32,
-  kind: u32, // 0 = point, 1 = directional
-};
-
-struct Lights {
-  count: u32,
-  lights: array<Light, 16>, // Максимум 16 источников света
 };
-
-@group(2) @binding(0) var<uniform> uLights: Lights;
+@group(0) @binding(0) var<uniform> uCamera: Camera;
 
 // Texture support
 @group(1) @binding(0) var tex: texture_2d<f32>;
@@ -28,10 +15,11 @@ struct VSIn {
   @location(0) pos: vec3<f32>,
   @location(1) normal: vec3<f32>,
   @location(2) tex_coords: vec2<f32>,
-  @location(3) i_pos: vec3<f32>,
-  @location(4) i_scale: vec3<f32>,
-  @location(5) i_rotY: f32,
-  @location(6) i_color: vec3<f32>,
+  @location(3) i_model0: vec4<f32>,
+  @location(4) i_model1: vec4<f32>,
+  @location(5) i_model2: vec4<f32>,
+  @location(6) i_model3: vec4<f32>,
+  @location(7) i_color: vec3<f32>,
 };
 
 struct VSOut {
@@ -42,37 +30,15 @@ struct VSOut {
   @location(3) tex_coords: vec2<f32>,
 };
 
-fn rotationY(a: f32) -> mat4x4<f32> {
-  let c = cos(a);
-  let s = sin(a);
-  return mat4x4<f32>(
-    vec4<f32>( c, 0.0, s, 0.0),
-    vec4<f32>( 0.0, 1.0, 0.0, 0.0),
-    vec4<f32>(-s, 0.0, c, 0.0),
-    vec4<f32>( 0.0, 0.0, 0.0, 1.0)
-  );
-}
-
 @vertex
 fn vs_main(input: VSIn) -> VSOut {
   var out: VSOut;
-  let modelScale = mat4x4<f32>(
-    vec4<f32>(input.i_scale.x, 0.0, 0.0, 0.0),
-    vec4<f32>(0.0, input.i_scale.y, 0.0, 0.0),
-    vec4<f32>(0.0, 0.0, input.i_scale.z, 0.0),
-    vec4<f32>(0.0, 0.0, 0.0, 1.0)
-  );
-  let modelRot = rotationY(input.i_rotY);
-  let modelTrans = mat4x4<f32>(
-    vec4<f32>(1.0, 0.0, 0.0, 0.0),
-    vec4<f32>(0.0, 1.0, 0.0, 0.0),
-    vec4<f32>(0.0, 0.0, 1.0, 0.0),
-    vec4<f32>(input.i_pos, 1.0)
-  );
-  let model = modelTrans * modelRot * modelScale;
+  let model = mat4x4<f32>(input.i_model0, input.i_model1, input.i_model2, input.i_model3);
 
   let worldPos = (model * vec4<f32>(input.pos, 1.0)).xyz;
-  let worldNormal = (modelRot * vec4<f32>(input.normal, 0.0)).xyz;
+  // cofactor of the upper 3x3: the inverse transpose up to scale, so non-uniform scale keeps normals right
+  let normalMat = mat3x3<f32>(cross(model[1].xyz, model[2].xyz), cross(model[2].xyz, model[0].xyz), cross(model[0].xyz, model[1].xyz));
+  let worldNormal = normalMat * input.normal;
 
   out.position = uCamera.viewProj * vec4<f32>(worldPos, 1.0);
   out.worldPos = worldPos;
@@ -93,24 +59,14 @@ fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
   // Ambient light
   var light = vec3<f32>(0.1, 0.1, 0.1);
   
-  // Process lights
-  for (var i: u32 = 0u; i < uLights.count && i < 16u; i = i + 1u) {
-    let lightData = uLights.lights[i];
-    
-    if (lightData.kind == 0u) { // Point light
-      let lightDir = lightData.position - in.worldPos;
-      let distance = length(lightDir);
-      let normalizedDir = lightDir / distance;
-      
-      let diff = max(dot(n, normalizedDir), 0.0);
-      let attenuation = 1.0 / (1.0 + 0.09 * distance + 0.032 * distance * distance);
-      
-      light += lightData.color * diff * lightData.intensity * attenuation;
-    } else if (lightData.kind == 1u) { // Directional light
-      let lightDir = normalize(-lightData.position);
-      let diff = max(dot(n, lightDir), 0.0);
-      light += lightData.color * diff * lightData.intensity;
-    }
+  // Process the lights of this fragment's cluster
+  let cluster = clusterAt(in.position.xy, in.worldPos);
+  for (var k: u32 = 0u; k < lightCount(cluster); k = k + 1u) {
+    let lightData = lightAt(cluster, k);
+    let incidence = lightIncidence(lightData, in.worldPos);
+    let diff = max(dot(n, incidence.xyz), 0.0);
+    let shadow = shadowFactor(lightData.shadow, lightData.kind, lightData.position, in.worldPos, n, uCamera.position);
+    light += lightData.color * diff * lightData.intensity * incidence.w * shadow;
   }
   
   // Combine vertex color with texture and lighting
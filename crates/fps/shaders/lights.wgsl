@@ -0,0 +1,67 @@
+// Clustered light lists; the renderer compiles this file after shadow_sample.wgsl and before
+// lighting_simple.wgsl or pbr.wgsl
+
+struct Light {
+  position: vec3<f32>,
+  range: f32,
+  color: vec3<f32>,
+  intensity: f32,
+  direction: vec3<f32>, // spot axis, or where a directional light shines
+  kind: u32,            // 0 = point, 1 = directional, 2 = spot
+  cosInner: f32,
+  cosOuter: f32,
+  shadow: i32,          // first shadow slot, -1 without shadows
+  _pad: u32,
+};
+
+struct LightGrid {
+  view: mat4x4<f32>,
+  tileSize: vec2<f32>, // pixels
+  near: f32,
+  sliceScale: f32,     // depth slices per unit of ln(depth / near)
+  dims: vec3<u32>,
+  globalCount: u32,    // directional lights, listed first and shared by every cluster
+};
+
+@group(2) @binding(0) var<uniform> uLightGrid: LightGrid;
+@group(2) @binding(1) var<storage, read> uLights: array<Light>;
+@group(2) @binding(2) var<storage, read> uClusters: array<vec2<u32>>; // first index and count
+@group(2) @binding(3) var<storage, read> uLightIndices: array<u32>;
+
+// The cluster a fragment falls in
+fn clusterAt(fragCoord: vec2<f32>, worldPos: vec3<f32>) -> vec2<u32> {
+  let depth = -(uLightGrid.view * vec4<f32>(worldPos, 1.0)).z;
+  let slice = clamp(floor(log(max(depth / uLightGrid.near, 1.0)) * uLightGrid.sliceScale), 0.0, f32(uLightGrid.dims.z - 1u));
+  let tile = min(vec2<u32>(fragCoord / uLightGrid.tileSize), uLightGrid.dims.xy - vec2<u32>(1u));
+  return uClusters[(u32(slice) * uLightGrid.dims.y + tile.y) * uLightGrid.dims.x + tile.x];
+}
+
+// Lights that may reach fragments of `cluster`
+fn lightCount(cluster: vec2<u32>) -> u32 {
+  return uLightGrid.globalCount + cluster.y;
+}
+
+// The `k`th of them: the directional lights, then the cluster's own
+fn lightAt(cluster: vec2<u32>, k: u32) -> Light {
+  if (k < uLightGrid.globalCount) {
+    return uLights[uLightIndices[k]];
+  }
+  return uLights[uLightIndices[cluster.x + k - uLightGrid.globalCount]];
+}
+
+// Direction toward the light in xyz, and the part of its intensity that arrives in w, shadows aside
+fn lightIncidence(light: Light, worldPos: vec3<f32>) -> vec4<f32> {
+  if (light.kind == 1u) {
+    return vec4<f32>(-light.direction, 1.0);
+  }
+  let toLight = light.position - worldPos;
+  let d = length(toLight);
+  let L = toLight / max(d, 1e-4);
+  // the usual falloff, windowed so it reaches zero at the light's range
+  let window = clamp(1.0 - pow(d / light.range, 4.0), 0.0, 1.0);
+  var amount = window * window / (1.0 + 0.09 * d + 0.032 * d * d);
+  if (light.kind == 2u) {
+    amount *= smoothstep(light.cosOuter, light.cosInner, dot(-L, light.direction));
+  }
+  return vec4<f32>(L, amount);
+}
@@ -1,15 +1,17 @@
-// Шейдер для рендеринга частиц
+// Шейдер для рендеринга частиц: билборды, повёрнутые к камере, с аддитивным смешиванием
 
 struct Camera {
   viewProj: mat4x4<f32>,
+  position: vec3<f32>,
 };
 @group(0) @binding(0) var<uniform> uCamera: Camera;
 
 struct VSIn {
-  @location(0) pos: vec2<f32>, // Billboard quad vertex
+  @location(0) pos: vec2<f32>, // Billboard quad vertex, -1..1
   @location(1) particlePos: vec3<f32>, // Particle position
-  @location(2) particleColor: vec3<f32>, // Particle color
-  @location(3) particleLifetime: f32, // Particle lifetime
+  @location(2) particleSize: f32, // Half-width in world units
+  @location(3) particleColor: vec3<f32>, // Particle color
+  @location(4) particleLifetime: f32, // Fraction of the lifetime left
 };
 
 struct VSOut {
@@ -21,10 +23,15 @@ struct VSOut {
 @vertex
 fn vs_main(input: VSIn) -> VSOut {
   var out: VSOut;
-  // Преобразуем вершину билборда в мировые координаты
-  let worldPos = input.particlePos + vec3<f32>(input.pos.x, input.pos.y, 0.0);
+  // Разворачиваем квад к камере; при взгляде строго сверху или снизу берём другую опорную ось
+  let toCam = normalize(uCamera.position - input.particlePos);
+  let helper = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(toCam.y) > 0.99);
+  let right = normalize(cross(helper, toCam));
+  let up = cross(toCam, right);
+  let worldPos = input.particlePos + (right * input.pos.x + up * input.pos.y) * input.particleSize;
   out.position = uCamera.viewProj * vec4<f32>(worldPos, 1.0);
-  out.color = input.particleColor;
+  // fading the color is enough with additive blending
+  out.color = input.particleColor * input.particleLifetime;
   out.tex_coords = input.pos * 0.5 + 0.5; // Преобразуем из [-1,1] в [0,1]
   return out;
 }
@@ -36,5 +43,5 @@ fn vs_main(input: VSIn) -> VSOut {
 @fragment
 fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
   let texColor = textureSample(tex, samp, in.tex_coords);
-  return vec4<f32>(in.color * texColor.rgb, texColor.a);
-}
\ No newline at end of file
+  return vec4<f32>(in.color * texColor.rgb * texColor.a, texColor.a);
+}
@@ -1,4 +1,8 @@
-// Шейдер для рендеринга mesh-объектов с PBR
+// Metallic/roughness PBR for boxes and meshes with a material; shares the bind groups of lighting_simple.wgsl
+// and, like it, is compiled after shadow_sample.wgsl and lights.wgsl
+
+// Set by the renderer per pipeline
+override TONEMAP: bool = true;
 
 struct Camera {
   viewProj: mat4x4<f32>,
@@ -6,13 +10,20 @@ struct Camera {
 };
 @group(0) @binding(0) var<uniform> uCamera: Camera;
 
+// Base color texture, white when the surface has none
+@group(1) @binding(0) var baseColorTexture: texture_2d<f32>;
+@group(1) @binding(1) var baseColorSampler: sampler;
+
 struct VSIn {
   @location(0) pos: vec3<f32>,
   @location(1) normal: vec3<f32>,
   @location(2) tex_coords: vec2<f32>,
-  @location(3) i_pos: vec3<f32>,
-  @location(4) i_scale: vec3<f32>,
-  @location(5) i_rotY: f32,
+  @location(3) i_model0: vec4<f32>,
+  @location(4) i_model1: vec4<f32>,
+  @location(5) i_model2: vec4<f32>,
+  @location(6) i_model3: vec4<f32>,
+  @location(7) i_color: vec3<f32>,
+  @location(8) i_material: vec2<f32>, // metallic, roughness
 };
 
 struct VSOut {
@@ -20,79 +31,29 @@ struct VSOut {
   @location(0) worldPos: vec3<f32>,
   @location(1) normal: vec3<f32>,
   @location(2) tex_coords: vec2<f32>,
+  @location(3) color: vec3<f32>,
+  @location(4) material: vec2<f32>,
 };
 
-fn rotationY(a: f32) -> mat4x4<f32> {
-  let c = cos(a);
-  let s = sin(a);
-  return mat4x4<f32>(
-    vec4<f32>( c, 0.0, s, 0.0),
-    vec4<f32>( 0.0, 1.0, 0.0, 0.0),
-    vec4<f32>(-s, 0.0, c, 0.0),
-    vec4<f32>( 0.0, 0.0, 0.0, 1.0)
-  );
-}
-
 @vertex
 fn vs_main(input: VSIn) -> VSOut {
   var out: VSOut;
-  let modelScale = mat4x4<f32>(
-    vec4<f32>(input.i_scale.x, 0.0, 0.0, 0.0),
-    vec4<f32>(0.0, input.i_scale.y, 0.0, 0.0),
-    vec4<f32>(0.0, 0.0, input.i_scale.z, 0.0),
-    vec4<f32>(0.0, 0.0, 0.0, 1.0)
-  );
-  let modelRot = rotationY(input.i_rotY);
-  let modelTrans = mat4x4<f32>(
-    vec4<f32>(1.0, 0.0, 0.0, 0.0),
-    vec4<f32>(0.0, 1.0, 0.0, 0.0),
-    vec4<f32>(0.0, 0.0, 1.0, 0.0),
-    vec4<f32>(input.i_pos, 1.0)
-  );
-  let model = modelTrans * modelRot * modelScale;
+  let model = mat4x4<f32>(input.i_model0, input.i_model1, input.i_model2, input.i_model3);
 
   let worldPos = (model * vec4<f32>(input.pos, 1.0)).xyz;
-  let worldNormal = (modelRot * vec4<f32>(input.normal, 0.0)).xyz;
+  // cofactor of the upper 3x3: the inverse transpose up to scale, so non-uniform scale keeps normals right
+  let normalMat = mat3x3<f32>(cross(model[1].xyz, model[2].xyz), cross(model[2].xyz, model[0].xyz), cross(model[0].xyz, model[1].xyz));
+  let worldNormal = normalMat * input.normal;
 
   out.position = uCamera.viewProj * vec4<f32>(worldPos, 1.0);
   out.worldPos = worldPos;
   out.normal = worldNormal;
   out.tex_coords = input.tex_coords;
+  out.color = input.i_color;
+  out.material = input.i_material;
   return out;
 }
 
-// Textures
-@group(1) @binding(0) var baseColorTexture: texture_2d<f32>;
-@group(1) @binding(1) var baseColorSampler: sampler;
-@group(1) @binding(2) var normalTexture: texture_2d<f32>;
-@group(1) @binding(3) var normalSampler: sampler;
-@group(1) @binding(4) var metallicTexture: texture_2d<f32>;
-@group(1) @binding(5) var metallicSampler: sampler;
-@group(1) @binding(6) var roughnessTexture: texture_2d<f32>;
-@group(1) @binding(7) var roughnessSampler: sampler;
-
-// Material properties
-struct Material {
-  baseColorFactor: vec4<f32>,
-  metallicFactor: f32,
-  roughnessFactor: f32,
-};
-@group(2) @binding(0) var<uniform> uMaterial: Material;
-
-// Lighting
-struct Light {
-  position: vec3<f32>,
-  color: vec3<f32>,
-  intensity: f32,
-  kind: u32, // 0 = point, 1 = directional
-};
-
-struct Lights {
-  count: u32,
-  lights: array<Light, 16>,
-};
-@group(3) @binding(0) var<uniform> uLights: Lights;
-
 // PBR functions
 fn fresnelSchlick(cosTheta: f32, F0: vec3<f32>) -> vec3<f32> {
   return F0 + (1.0 - F0) * pow(1.0 - cosTheta, 5.0);
@@ -141,8 +102,7 @@ fn calculatePBR(N: vec3<f32>, V: vec3<f32>, L: vec3<f32>, lightColor: vec3<f32>,
   let F = fresnelSchlick(max(dot(H, V), 0.0), F0);
   
   let kS = F;
-  let kD = vec3<f32>(1.0) - kS;
-  kD = kD * (1.0 - metallic);
+  let kD = (vec3<f32>(1.0) - kS) * (1.0 - metallic);
   
   let numerator = NDF * G * F;
   let denominator = 4.0 * max(dot(N, V), 0.0) * max(dot(N, L), 0.0);
@@ -158,42 +118,30 @@ fn calculatePBR(N: vec3<f32>, V: vec3<f32>, L: vec3<f32>, lightColor: vec3<f32>,
 fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
   let N = normalize(in.normal);
   let V = normalize(uCamera.position - in.worldPos);
-  
-  // Sample textures
-  let baseColor = textureSample(baseColorTexture, baseColorSampler, in.tex_coords) * uMaterial.baseColorFactor;
-  let normalMap = textureSample(normalTexture, normalSampler, in.tex_coords);
-  let metallic = textureSample(metallicTexture, metallicSampler, in.tex_coords).r * uMaterial.metallicFactor;
-  let roughness = textureSample(roughnessTexture, roughnessSampler, in.tex_coords).g * uMaterial.roughnessFactor;
-  
-  let albedo = baseColor.rgb;
-  
+
+  let baseColor = textureSample(baseColorTexture, baseColorSampler, in.tex_coords);
+  let albedo = baseColor.rgb * in.color;
+  let metallic = clamp(in.material.x, 0.0, 1.0);
+  // fully smooth surfaces make the GGX lobe a singularity
+  let roughness = clamp(in.material.y, 0.04, 1.0);
+
   // Ambient lighting
   var Lo = albedo * 0.03;
-  
-  // Process lights
-  for (var i: u32 = 0u; i < uLights.count && i < 16u; i = i + 1u) {
-    let light = uLights.lights[i];
-    
-    if (light.kind == 0u) { // Point light
-      let lightDir = light.position - in.worldPos;
-      let distance = length(lightDir);
-      let L = normalize(lightDir);
-      
-      let attenuation = 1.0 / (1.0 + 0.09 * distance + 0.032 * distance * distance);
-      let lightIntensity = light.intensity * attenuation;
-      
-      Lo += calculatePBR(N, V, L, light.color, lightIntensity, albedo, metallic, roughness);
-    } else if (light.kind == 1u) { // Directional light
-      let L = normalize(-light.position);
-      Lo += calculatePBR(N, V, L, light.color, light.intensity, albedo, metallic, roughness);
-    }
+
+  // Process the lights of this fragment's cluster
+  let cluster = clusterAt(in.position.xy, in.worldPos);
+  for (var k: u32 = 0u; k < lightCount(cluster); k = k + 1u) {
+    let light = lightAt(cluster, k);
+    let incidence = lightIncidence(light, in.worldPos);
+    let shadow = shadowFactor(light.shadow, light.kind, light.position, in.worldPos, N, uCamera.position);
+    Lo += calculatePBR(N, V, incidence.xyz, light.color, light.intensity * incidence.w * shadow, albedo, metallic, roughness);
   }
-  
-  // HDR tonemapping
-  Lo = Lo / (Lo + vec3<f32>(1.0));
-  
-  // Gamma correction
-  Lo = pow(Lo, vec3<f32>(1.0 / 2.2));
-  
+
+  // Reinhard tonemapping; the sRGB target applies the gamma. Off when a post-processing chain
+  // tonemaps the HDR target instead
+  if (TONEMAP) {
+    Lo = Lo / (Lo + vec3<f32>(1.0));
+  }
+
   return vec4<f32>(Lo, baseColor.a);
-}
\ No newline at end of file
+}
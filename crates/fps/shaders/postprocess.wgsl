@@ -1,4 +1,4 @@
-// Шейдер для пост-обработки (простой проход через экран)
+// Шейдер для пост-обработки: bloom, экспозиция, тонмаппинг, гамма и виньетка
 
 struct VertexOutput {
   @builtin(position) position: vec4<f32>,
@@ -8,8 +8,8 @@ struct VertexOutput {
 @vertex
 fn vertex_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
   var out: VertexOutput;
-  // Создаем полноэкранный квадрат
-  let pos = array<vec2<f32>, 6>(
+  // Создаем полноэкранный квадрат; `var`, потому что `let`-массивы нельзя индексировать переменной
+  var pos = array<vec2<f32>, 6>(
     vec2<f32>(-1.0, -1.0),
     vec2<f32>( 1.0, -1.0),
     vec2<f32>(-1.0,  1.0),
@@ -17,27 +17,60 @@ fn vertex_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
     vec2<f32>( 1.0,  1.0),
     vec2<f32>(-1.0,  1.0)
   );
-  
-  let uv = array<vec2<f32>, 6>(
-    vec2<f32>(0.0, 0.0),
-    vec2<f32>(1.0, 0.0),
-    vec2<f32>(0.0, 1.0),
-    vec2<f32>(1.0, 0.0),
-    vec2<f32>(1.0, 1.0),
-    vec2<f32>(0.0, 1.0)
-  );
-  
-  out.position = vec4<f32>(pos[vertex_index], 0.0, 1.0);
-  out.tex_coords = uv[vertex_index];
+  let p = pos[vertex_index];
+  out.position = vec4<f32>(p, 0.0, 1.0);
+  // texture rows run top to bottom
+  out.tex_coords = vec2<f32>(p.x * 0.5 + 0.5, 0.5 - p.y * 0.5);
   return out;
 }
 
-// Текстура входного изображения
+// Текстура входного изображения (HDR) и размытые яркие участки
 @group(0) @binding(0) var input_texture: texture_2d<f32>;
 @group(0) @binding(1) var input_sampler: sampler;
+@group(0) @binding(2) var bloom_texture: texture_2d<f32>;
+
+struct PostParams {
+  exposure: f32,
+  gamma: f32,
+  vignette: f32,
+  bloom_intensity: f32,
+  tonemap: u32,     // 0 = ACES, 1 = Reinhard
+  srgb_target: u32, // the target encodes to sRGB on write
+  _pad: vec2<u32>,
+};
+@group(0) @binding(3) var<uniform> params: PostParams;
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+  return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+  let low = c / 12.92;
+  let high = pow((c + 0.055) / 1.055, vec3<f32>(2.4));
+  return select(high, low, c <= vec3<f32>(0.04045));
+}
 
 @fragment
 fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
-  // Простой проход через экран без эффектов
-  return textureSample(input_texture, input_sampler, in.tex_coords);
-}
\ No newline at end of file
+  var color = textureSample(input_texture, input_sampler, in.tex_coords).rgb;
+  color += textureSample(bloom_texture, input_sampler, in.tex_coords).rgb * params.bloom_intensity;
+  color *= params.exposure;
+
+  if (params.tonemap == 0u) {
+    color = aces(color);
+  } else {
+    color = color / (color + vec3<f32>(1.0));
+  }
+
+  // darken toward the corners
+  let d = length(in.tex_coords - vec2<f32>(0.5)) * 1.41421356;
+  color *= 1.0 - params.vignette * d * d;
+
+  color = pow(max(color, vec3<f32>(0.0)), vec3<f32>(1.0 / params.gamma));
+  // an sRGB target would encode a second time, so hand it the value that encodes to ours
+  if (params.srgb_target != 0u) {
+    color = srgb_to_linear(color);
+  }
+  return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,74 @@
+// Shadow map lookups; the renderer prepends this file to lights.wgsl and the lighting shaders
+
+struct ShadowSlot {
+  viewProj: mat4x4<f32>,
+  uvScale: f32,      // part of the layer the map covers
+  texel: f32,        // one texel of the layer in UV units
+  normalOffset: f32, // world units, per unit of distance for perspective slots
+  perspective: f32,
+};
+
+struct Shadows {
+  slots: array<ShadowSlot, 28>,
+  cascadeFar: vec4<f32>,
+  cascadeCount: u32,
+};
+
+@group(3) @binding(0) var<uniform> uShadows: Shadows;
+@group(3) @binding(1) var shadowMaps: texture_depth_2d_array;
+@group(3) @binding(2) var shadowSampler: sampler_comparison;
+
+// 3x3 PCF over one slot; 1 is fully lit
+fn shadowPcf(slot: u32, worldPos: vec3<f32>, normal: vec3<f32>, dist: f32) -> f32 {
+  let s = uShadows.slots[slot];
+  let offset = s.normalOffset * mix(1.0, dist, s.perspective);
+  let clip = s.viewProj * vec4<f32>(worldPos + normal * offset, 1.0);
+  if (clip.w <= 0.0) {
+    return 1.0;
+  }
+  let ndc = clip.xyz / clip.w;
+  if (ndc.z > 1.0 || abs(ndc.x) > 1.0 || abs(ndc.y) > 1.0) {
+    return 1.0;
+  }
+  let uv = (ndc.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5)) * s.uvScale;
+  // stay inside this light's corner of the layer
+  let lo = vec2<f32>(0.5 * s.texel);
+  let hi = vec2<f32>(s.uvScale - 0.5 * s.texel);
+  var lit = 0.0;
+  for (var y = -1; y <= 1; y = y + 1) {
+    for (var x = -1; x <= 1; x = x + 1) {
+      let p = clamp(uv + vec2<f32>(f32(x), f32(y)) * s.texel, lo, hi);
+      lit += textureSampleCompareLevel(shadowMaps, shadowSampler, p, slot, ndc.z);
+    }
+  }
+  return lit / 9.0;
+}
+
+// Fraction of a light with shadow slots from `first` on, -1 for none, that reaches `worldPos`
+fn shadowFactor(first: i32, kind: u32, lightPos: vec3<f32>, worldPos: vec3<f32>, normal: vec3<f32>, camPos: vec3<f32>) -> f32 {
+  if (first < 0) {
+    return 1.0;
+  }
+  if (kind == 1u) {
+    // directional: the first cascade that still covers this distance
+    let d = distance(worldPos, camPos);
+    for (var c = 0u; c < uShadows.cascadeCount; c = c + 1u) {
+      if (d < uShadows.cascadeFar[c]) {
+        return shadowPcf(u32(first) + c, worldPos, normal, 0.0);
+      }
+    }
+    return 1.0;
+  }
+  // point: the face along the major axis, ordered +X -X +Y -Y +Z -Z
+  let toFrag = worldPos - lightPos;
+  let a = abs(toFrag);
+  var face = 0u;
+  if (a.x >= a.y && a.x >= a.z) {
+    face = select(1u, 0u, toFrag.x > 0.0);
+  } else if (a.y >= a.z) {
+    face = select(3u, 2u, toFrag.y > 0.0);
+  } else {
+    face = select(5u, 4u, toFrag.z > 0.0);
+  }
+  return shadowPcf(u32(first) + face, worldPos, normal, length(toFrag));
+}
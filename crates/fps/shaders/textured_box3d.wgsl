@@ -13,10 +13,11 @@ struct VSIn {
   @location(0) pos: vec3<f32>,
   @location(1) normal: vec3<f32>,
   @location(2) tex_coords: vec2<f32>,
-  @location(3) i_pos: vec3<f32>,
-  @location(4) i_scale: vec3<f32>,
-  @location(5) i_rotY: f32,
-  @location(6) i_color: vec3<f32>,
+  @location(3) i_model0: vec4<f32>,
+  @location(4) i_model1: vec4<f32>,
+  @location(5) i_model2: vec4<f32>,
+  @location(6) i_model3: vec4<f32>,
+  @location(7) i_color: vec3<f32>,
 };
 
 struct VSOut {
@@ -26,37 +27,15 @@ struct VSOut {
   @location(2) tex_coords: vec2<f32>,
 };
 
-fn rotationY(a: f32) -> mat4x4<f32> {
-  let c = cos(a);
-  let s = sin(a);
-  return mat4x4<f32>(
-    vec4<f32>( c, 0.0, s, 0.0),
-    vec4<f32>( 0.0, 1.0, 0.0, 0.0),
-    vec4<f32>(-s, 0.0, c, 0.0),
-    vec4<f32>( 0.0, 0.0, 0.0, 1.0)
-  );
-}
-
 @vertex
 fn vs_main(input: VSIn) -> VSOut {
   var out: VSOut;
-  let modelScale = mat4x4<f32>(
-    vec4<f32>(input.i_scale.x, 0.0, 0.0, 0.0),
-    vec4<f32>(0.0, input.i_scale.y, 0.0, 0.0),
-    vec4<f32>(0.0, 0.0, input.i_scale.z, 0.0),
-    vec4<f32>(0.0, 0.0, 0.0, 1.0)
-  );
-  let modelRot = rotationY(input.i_rotY);
-  let modelTrans = mat4x4<f32>(
-    vec4<f32>(1.0, 0.0, 0.0, 0.0),
-    vec4<f32>(0.0, 1.0, 0.0, 0.0),
-    vec4<f32>(0.0, 0.0, 1.0, 0.0),
-    vec4<f32>(input.i_pos, 1.0)
-  );
-  let model = modelTrans * modelRot * modelScale;
+  let model = mat4x4<f32>(input.i_model0, input.i_model1, input.i_model2, input.i_model3);
 
   let worldPos = (model * vec4<f32>(input.pos, 1.0)).xyz;
-  let worldNormal = (modelRot * vec4<f32>(input.normal, 0.0)).xyz;
+  // cofactor of the upper 3x3: the inverse transpose up to scale, so non-uniform scale keeps normals right
+  let normalMat = mat3x3<f32>(cross(model[1].xyz, model[2].xyz), cross(model[2].xyz, model[0].xyz), cross(model[0].xyz, model[1].xyz));
+  let worldNormal = normalMat * input.normal;
 
   out.position = uCamera.viewProj * vec4<f32>(worldPos, 1.0);
   out.color = input.i_color;
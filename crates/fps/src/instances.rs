@@ -0,0 +1,242 @@
+//! Box instances that can be added, removed and changed after the renderer is built.
+//!
+//! All instances share one buffer in which every batch stays contiguous: inserting or removing
+//! moves at most one instance per later batch. Changes accumulate into a single dirty range that
+//! the renderer uploads before the next frame. Each instance also keeps its world bounds, so the
+//! renderer can cull batches down to the boxes in view.
+
+use crate::{box_texture, Aabb, Batch, Instance, Obb};
+use kengaai_scene_fps::BoxDef;
+use std::ops::Range;
+
+/// Names a box added to an [`crate::FpsRenderer`]. Handles of removed boxes stay invalid even
+/// after their slot is reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BoxHandle {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug)]
+struct Slot {
+    def: Option<BoxDef>,
+    generation: u32,
+    /// Position of the box's instance in the buffer while `def` is set
+    instance: u32,
+}
+
+/// Shading and texture a box is batched by
+fn batch_key(b: &BoxDef) -> (bool, Option<String>) {
+    (b.material.is_some(), box_texture(b).cloned())
+}
+
+#[derive(Debug)]
+pub(crate) struct BoxInstances {
+    slots: Vec<Slot>,
+    /// Slots of removed boxes, reused before new ones are added
+    free: Vec<u32>,
+    pub instances: Vec<Instance>,
+    /// World bounds of each instance
+    volumes: Vec<Aabb>,
+    /// Slot of each instance
+    owners: Vec<u32>,
+    pub batches: Vec<Batch>,
+    dirty: Option<Range<usize>>,
+    /// Set when boxes were added, removed or moved since the last [`BoxInstances::take_changes`]
+    moved: bool,
+}
+
+impl BoxInstances {
+    /// Boxes ordered so that those with the same shading and texture are contiguous. Handles are
+    /// given out in the order of `defs`.
+    pub fn new(defs: Vec<BoxDef>) -> Self {
+        let mut order: Vec<usize> = (0..defs.len()).collect();
+        order.sort_by_key(|&i| batch_key(&defs[i]));
+        let mut batches: Vec<Batch> = Vec::new();
+        let mut instances = Vec::with_capacity(defs.len());
+        let mut volumes = Vec::with_capacity(defs.len());
+        let mut slots: Vec<Slot> = defs.into_iter().map(|def| Slot { def: Some(def), generation: 0, instance: 0 }).collect();
+        for (n, &i) in order.iter().enumerate() {
+            let def = slots[i].def.as_ref().unwrap();
+            let (pbr, texture) = batch_key(def);
+            instances.push(Instance::from(def));
+            volumes.push(Obb::from(def).aabb());
+            slots[i].instance = n as u32;
+            match batches.last_mut() {
+                Some(last) if last.pbr == pbr && last.texture == texture => last.instances.end = n as u32 + 1,
+                _ => batches.push(Batch { texture, pbr, instances: n as u32..n as u32 + 1 }),
+            }
+        }
+        let owners = order.into_iter().map(|i| i as u32).collect();
+        Self { slots, free: Vec::new(), instances, volumes, owners, batches, dirty: None, moved: false }
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    pub fn get(&self, handle: BoxHandle) -> Option<&BoxDef> {
+        self.slots.get(handle.index as usize).filter(|s| s.generation == handle.generation)?.def.as_ref()
+    }
+
+    /// Live boxes in handle order
+    pub fn iter(&self) -> impl Iterator<Item = (BoxHandle, &BoxDef)> {
+        self.slots.iter().enumerate().filter_map(|(i, s)| {
+            s.def.as_ref().map(|def| (BoxHandle { index: i as u32, generation: s.generation }, def))
+        })
+    }
+
+    /// Box around every live box
+    pub fn bounds(&self) -> Aabb {
+        self.volumes.iter().fold(Aabb::EMPTY, |b, v| b.union(v))
+    }
+
+    /// Instances that pass `inside`, batch by batch, and the batches they form. Both outputs are
+    /// cleared first so their allocations can be reused from frame to frame.
+    pub fn visible(&self, inside: impl Fn(&Aabb) -> bool, instances: &mut Vec<Instance>, batches: &mut Vec<Batch>) {
+        instances.clear();
+        batches.clear();
+        for batch in &self.batches {
+            let start = instances.len() as u32;
+            let range = batch.instances.start as usize..batch.instances.end as usize;
+            for (instance, volume) in self.instances[range.clone()].iter().zip(&self.volumes[range]) {
+                if inside(volume) {
+                    instances.push(*instance);
+                }
+            }
+            let end = instances.len() as u32;
+            if end > start {
+                batches.push(Batch { texture: batch.texture.clone(), pbr: batch.pbr, instances: start..end });
+            }
+        }
+    }
+
+    pub fn insert(&mut self, def: BoxDef) -> BoxHandle {
+        let (pbr, texture) = batch_key(&def);
+        let b = match self.batches.iter().position(|b| b.pbr == pbr && b.texture == texture) {
+            Some(b) => b,
+            None => {
+                let end = self.instances.len() as u32;
+                self.batches.push(Batch { texture, pbr, instances: end..end });
+                self.batches.len() - 1
+            }
+        };
+        // open a gap at the end of batch `b`: from the back, every later batch moves its first
+        // instance into the free place just past its end
+        let len = self.instances.len();
+        self.instances.push(Instance::from(&def));
+        self.volumes.push(Aabb::EMPTY);
+        self.owners.push(0);
+        for later in (b + 1..self.batches.len()).rev() {
+            let range = self.batches[later].instances.clone();
+            if !range.is_empty() {
+                self.move_instance(range.start as usize, range.end as usize);
+            }
+            self.batches[later].instances = range.start + 1..range.end + 1;
+        }
+        let at = self.batches[b].instances.end;
+        self.batches[b].instances.end += 1;
+
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot { def: None, generation: 0, instance: 0 });
+                self.slots.len() as u32 - 1
+            }
+        };
+        self.instances[at as usize] = Instance::from(&def);
+        self.volumes[at as usize] = Obb::from(&def).aabb();
+        self.owners[at as usize] = index;
+        let slot = &mut self.slots[index as usize];
+        slot.instance = at;
+        slot.def = Some(def);
+        let handle = BoxHandle { index, generation: slot.generation };
+        self.mark(at as usize..len + 1);
+        self.moved = true;
+        handle
+    }
+
+    pub fn remove(&mut self, handle: BoxHandle) -> Option<BoxDef> {
+        self.get(handle)?;
+        let slot = &mut self.slots[handle.index as usize];
+        let def = slot.def.take();
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        let at = slot.instance as usize;
+
+        // fill the hole from the end of its batch, then let every later batch move its last
+        // instance into the place freed just before its start
+        let b = self.batches.iter().position(|b| b.instances.contains(&(at as u32))).expect("instance outside every batch");
+        let end = self.batches[b].instances.end as usize;
+        self.move_instance(end - 1, at);
+        self.batches[b].instances.end -= 1;
+        for later in b + 1..self.batches.len() {
+            let range = self.batches[later].instances.clone();
+            if !range.is_empty() {
+                self.move_instance(range.end as usize - 1, range.start as usize - 1);
+            }
+            self.batches[later].instances = range.start - 1..range.end - 1;
+        }
+        self.instances.pop();
+        self.volumes.pop();
+        self.owners.pop();
+        self.batches.retain(|b| !b.instances.is_empty());
+        let len = self.instances.len();
+        if at < len {
+            self.mark(at..len);
+        }
+        self.moved = true;
+        def
+    }
+
+    /// Change a box without changing what it is batched by; `false` for stale handles
+    pub fn modify(&mut self, handle: BoxHandle, f: impl FnOnce(&mut BoxDef)) -> bool {
+        if self.get(handle).is_none() {
+            return false;
+        }
+        let slot = &mut self.slots[handle.index as usize];
+        let def = slot.def.as_mut().unwrap();
+        let (key, obb) = (batch_key(def), Obb::from(&*def));
+        f(def);
+        debug_assert_eq!(key, batch_key(def), "modify must not change the batch of a box");
+        let at = slot.instance as usize;
+        if Obb::from(&*def) != obb {
+            self.volumes[at] = Obb::from(&*def).aabb();
+            self.moved = true;
+        }
+        self.instances[at] = Instance::from(&*def);
+        self.mark(at..at + 1);
+        true
+    }
+
+    /// Instances to upload since the last call, and whether the boxes' bounds may have changed
+    pub fn take_changes(&mut self) -> (Option<Range<usize>>, bool) {
+        // removals after an edit may have cut the buffer short of where the edit was
+        let len = self.instances.len();
+        let dirty = self.dirty.take().map(|d| d.start.min(len)..d.end.min(len)).filter(|d| !d.is_empty());
+        (dirty, std::mem::take(&mut self.moved))
+    }
+
+    /// Move the instance at `from` to `to`, which must be free
+    fn move_instance(&mut self, from: usize, to: usize) {
+        if from == to {
+            return;
+        }
+        self.instances[to] = self.instances[from];
+        self.volumes[to] = self.volumes[from];
+        let owner = self.owners[from];
+        self.owners[to] = owner;
+        self.slots[owner as usize].instance = to as u32;
+    }
+
+    fn mark(&mut self, range: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(d) => d.start.min(range.start)..d.end.max(range.end),
+            None => range,
+        });
+    }
+}
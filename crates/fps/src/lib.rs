@@ -1,11 +1,29 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use bytemuck::{Pod, Zeroable};
-use glam::{Mat4, Vec2, Vec3};
-use kengaai_scene_fps::{BoxDef, FpsScene};
+use glam::{Mat4, Quat, Vec2, Vec3};
+use kengaai_scene_fps::{BoxDef, FpsScene, Light, MeshDef, PostProcess, Rotation, Shading, DEFAULT_MATERIAL_COLOR};
 use log::info;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use wgpu::util::DeviceExt;
 use winit::window::Window;
 
+mod instances;
+pub use instances::BoxHandle;
+use instances::BoxInstances;
+mod lights;
+use lights::LightClusters;
+mod mesh;
+pub use mesh::{load_mesh, MeshAsset, MeshPrimitive};
+mod particles;
+pub use particles::{Burst, Particle, ParticleSim, DEFAULT_LIFETIME, DEFAULT_PARTICLE_SIZE, MAX_PARTICLES};
+mod post;
+use post::{PostChain, HDR_FORMAT};
+mod shadow;
+use shadow::ShadowMaps;
+mod spatial;
+pub use spatial::{Aabb, Frustum, NearestPoint, Obb, QueryFilter, RayHit, SceneBvh, Volume};
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
 struct Vertex {
@@ -60,55 +78,201 @@ fn cube_vertices() -> Vec<Vertex> {
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
 struct Instance {
+    model: [[f32; 4]; 4],
+    color: [f32; 3],
+    metallic: f32,
+    roughness: f32,
+    _pad: [f32; 3],
+}
+
+/// One billboard for `particles.wgsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct ParticleInstance {
     pos: [f32; 3],
-    scale: [f32; 3],
-    rot_y: f32,
+    size: f32,
     color: [f32; 3],
-    _pad: f32,
+    /// Fraction of the lifetime left, which fades the particle out
+    remaining: f32,
+}
+
+impl From<&Particle> for ParticleInstance {
+    fn from(p: &Particle) -> Self {
+        Self { pos: p.pos.to_array(), size: p.size, color: p.color, remaining: p.remaining() }
+    }
+}
+
+/// A run of instances that share a texture and shading and are drawn with one call
+#[derive(Debug, Clone)]
+struct Batch {
+    /// Name in `textures`; `None` and names that were never loaded use the white default
+    texture: Option<String>,
+    /// Boxes with a material, drawn by the PBR pipeline when the scene asks for it
+    pbr: bool,
+    instances: std::ops::Range<u32>,
+}
+
+/// Texture a box is drawn with; a material's texture wins over the box's own
+fn box_texture(b: &BoxDef) -> Option<&String> {
+    b.material.as_ref().and_then(|m| m.texture.as_ref()).or(b.texture.as_ref())
 }
 
 impl From<&BoxDef> for Instance {
     fn from(b: &BoxDef) -> Self {
+        // the unit cube spans -1..1, so scaling by the half-extents gives the box's full size
+        let model = Mat4::from_scale_rotation_translation(Vec3::from(b.size), Quat::from_array(b.orientation()), Vec3::from(b.pos));
+        let (color, metallic, roughness) = match &b.material {
+            Some(m) => (m.color.unwrap_or(DEFAULT_MATERIAL_COLOR), m.metallic, m.roughness),
+            None => (b.color, 0.0, 1.0),
+        };
         Self {
-            pos: b.pos,
-            scale: b.size,
-            rot_y: b.rot_y,
-            color: b.color,
-            _pad: 0.0,
+            model: model.to_cols_array_2d(),
+            color,
+            metallic,
+            roughness,
+            _pad: [0.0; 3],
+        }
+    }
+}
+
+/// GPU buffers of one [`MeshPrimitive`], with the material values the file gave it
+struct GpuPrimitive {
+    vbo: wgpu::Buffer,
+    ibo: wgpu::Buffer,
+    index_count: u32,
+    base_color: [f32; 4],
+    metallic: f32,
+    roughness: f32,
+    /// Texture embedded in or referenced by the mesh file
+    texture: Option<(wgpu::Texture, wgpu::BindGroup)>,
+    /// Bounds in the mesh file's coordinates
+    bounds: Aabb,
+}
+
+/// One primitive of a placed mesh, drawn with the instance at `instance`
+#[derive(Debug, Clone)]
+struct MeshDraw {
+    file: String,
+    primitive: usize,
+    /// Texture name from the scene material, preferred over the file's own texture
+    texture: Option<String>,
+    /// Placed with a scene material, so the PBR pipeline may draw it
+    pbr: bool,
+    instance: u32,
+    /// World bounds, for culling
+    bounds: Aabb,
+}
+
+/// World bounds of every placed mesh whose file is loaded
+fn mesh_bounds(defs: &[MeshDef], meshes: &HashMap<String, Vec<GpuPrimitive>>) -> Aabb {
+    let mut bounds = Aabb::EMPTY;
+    for def in defs {
+        let Some(prims) = meshes.get(&def.file) else { continue };
+        let model = Mat4::from_scale_rotation_translation(Vec3::from(def.scale), Quat::from_array(def.orientation()), Vec3::from(def.pos));
+        for p in prims {
+            bounds = bounds.union(&p.bounds.transformed(model));
         }
     }
+    bounds
+}
+
+/// Instances and draws for every primitive of every placed mesh whose file is loaded
+fn mesh_instances(defs: &[MeshDef], meshes: &HashMap<String, Vec<GpuPrimitive>>) -> (Vec<Instance>, Vec<MeshDraw>) {
+    let mut instances = Vec::new();
+    let mut draws = Vec::new();
+    for def in defs {
+        let Some(prims) = meshes.get(&def.file) else { continue };
+        let model = Mat4::from_scale_rotation_translation(Vec3::from(def.scale), Quat::from_array(def.orientation()), Vec3::from(def.pos));
+        for (i, p) in prims.iter().enumerate() {
+            // the scene material tints the file's base color and overrides its surface values
+            let (tint, metallic, roughness) = match &def.material {
+                Some(m) => (m.color.unwrap_or(DEFAULT_MATERIAL_COLOR), m.metallic, m.roughness),
+                None => ([1.0; 3], p.metallic, p.roughness),
+            };
+            draws.push(MeshDraw {
+                file: def.file.clone(),
+                primitive: i,
+                texture: def.material.as_ref().and_then(|m| m.texture.clone()),
+                pbr: def.material.is_some(),
+                instance: instances.len() as u32,
+                bounds: p.bounds.transformed(model),
+            });
+            instances.push(Instance {
+                model: model.to_cols_array_2d(),
+                color: [0, 1, 2].map(|c| tint[c] * p.base_color[c]),
+                metallic,
+                roughness,
+                _pad: [0.0; 3],
+            });
+        }
+    }
+    (instances, draws)
 }
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
 struct CameraUBO {
     view_proj: [[f32; 4]; 4],
+    /// World-space eye position for view-dependent shading
+    position: [f32; 3],
+    _pad: f32,
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, Debug, Pod, Zeroable)]
-struct LightRaw {
-    position: [f32; 3],
-    _pad0: f32,
-    color: [f32; 3],
-    intensity: f32,
-    kind: u32,
-    _pad1: [u32; 3],
+impl CameraUBO {
+    fn new(camera: &Camera, aspect: f32) -> Self {
+        let vp = camera.proj(aspect) * camera.view();
+        Self { view_proj: vp.to_cols_array_2d(), position: camera.pos.to_array(), _pad: 0.0 }
+    }
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, Debug, Pod, Zeroable)]
-struct LightsUBO {
-    count: u32,
-    _pad: [u32; 3],
-    lights: [LightRaw; 16], // Максимум 16 источников света
+/// Color format of headless render targets, matching the sRGB formats picked for windows
+const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Where frames end up: a window's swapchain, or a texture that can be read back
+enum Target<'w> {
+    Window { surface: wgpu::Surface<'w>, config: wgpu::SurfaceConfiguration },
+    Offscreen { texture: wgpu::Texture },
+}
+
+/// Size and adapter choice for [`FpsRenderer::new_headless`]
+#[derive(Debug, Clone, Copy)]
+pub struct HeadlessOptions {
+    pub width: u32,
+    pub height: u32,
+    /// Use wgpu's software adapter even when a GPU is available, e.g. for reproducible images
+    pub force_fallback_adapter: bool,
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        Self { width: 640, height: 360, force_fallback_adapter: false }
+    }
+}
+
+/// What the last [`FpsRenderer::render`] drew and how long each stage took on the CPU
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderStats {
+    /// Box and mesh instances drawn by the main pass
+    pub drawn_instances: u32,
+    /// Box and mesh instances left out of the main pass for lying outside the view. Shadow maps
+    /// still draw them, since they may cast shadows into it.
+    pub culled_instances: u32,
+    /// Draw calls of the shadow and main passes; post-processing isn't counted
+    pub draw_calls: u32,
+    /// Writing changed boxes and the particles to the GPU
+    pub upload_time: Duration,
+    /// Frustum culling and uploading the instances that survive it
+    pub cull_time: Duration,
+    /// Recording the passes
+    pub encode_time: Duration,
+    /// Submitting the commands and presenting the frame
+    pub submit_time: Duration,
 }
 
 pub struct FpsRenderer<'w> {
-    surface: wgpu::Surface<'w>,
+    target: Target<'w>,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
     color: wgpu::Color,
 
@@ -116,19 +280,59 @@ pub struct FpsRenderer<'w> {
     depth_view: wgpu::TextureView,
 
     pipeline: wgpu::RenderPipeline,
+    pbr_pipeline: wgpu::RenderPipeline,
+    shading: Shading,
     vbo: wgpu::Buffer,
     cam_buf: wgpu::Buffer,
     cam_bind: wgpu::BindGroup,
-    lights_buf: wgpu::Buffer,
-    lights_bind: wgpu::BindGroup,
+    clusters: LightClusters,
 
-    instances: Vec<Instance>,
+    boxes: BoxInstances,
+    /// Instances of `boxes`; grows as needed
     inst_buf: wgpu::Buffer,
+    /// `proj * view` as last uploaded, which culling tests against
+    view_proj: Mat4,
+    culling: bool,
+    /// Boxes in view at the last frame and the batches they form, drawn from `visible_buf`
+    visible_boxes: Vec<Instance>,
+    visible_batches: Vec<Batch>,
+    visible_buf: wgpu::Buffer,
+    /// Indices into `mesh_draws` in view at the last frame
+    visible_meshes: Vec<usize>,
+    stats: RenderStats,
+
+    // Mesh support
+    mesh_defs: Vec<MeshDef>,
+    meshes: HashMap<String, Vec<GpuPrimitive>>,
+    mesh_draws: Vec<MeshDraw>,
+    mesh_inst_buf: Option<wgpu::Buffer>,
 
     // Texture support
     texture_bind_group_layout: wgpu::BindGroupLayout,
-    textures: std::collections::HashMap<String, (wgpu::Texture, wgpu::BindGroup)>,
+    textures: HashMap<String, (wgpu::Texture, wgpu::BindGroup)>,
     texture_sampler: wgpu::Sampler,
+    /// 1×1 white texture bound when no other texture applies
+    default_texture: (wgpu::Texture, wgpu::BindGroup),
+
+    particles: ParticleSim,
+    particle_pipeline: wgpu::RenderPipeline,
+    /// Two triangles spanning -1..1, expanded into billboards by the shader
+    particle_quad: wgpu::Buffer,
+    /// Instances of the particles alive at the last upload; grows as needed
+    particle_buf: wgpu::Buffer,
+    particle_count: u32,
+    /// Soft round sprite every particle is drawn with
+    particle_texture: (wgpu::Texture, wgpu::BindGroup),
+
+    /// HDR chain the main pass renders through, for scenes with `render.postProcess`
+    post: Option<PostChain>,
+
+    shadows: ShadowMaps,
+    /// Lights as last uploaded, for planning shadow maps
+    lights: Vec<Light>,
+    box_bounds: Aabb,
+    /// Boxes and loaded meshes, which shadow maps must cover
+    bounds: Aabb,
 
     pub camera: Camera,
 }
@@ -159,6 +363,37 @@ impl Camera {
     }
 }
 
+impl FpsRenderer<'static> {
+    /// Renderer that draws into an offscreen texture, for tests, tools and servers without a
+    /// display. Falls back to wgpu's software adapter when no GPU adapter is available.
+    pub fn new_headless(scene: &FpsScene, opts: &HeadlessOptions) -> Result<Self> {
+        pollster::block_on(Self::new_headless_async(scene, opts))
+    }
+
+    async fn new_headless_async(scene: &FpsScene, opts: &HeadlessOptions) -> Result<Self> {
+        let instance = wgpu::Instance::default();
+        let mut adapter = None;
+        for force_fallback_adapter in [opts.force_fallback_adapter, true] {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    compatible_surface: None,
+                    force_fallback_adapter,
+                })
+                .await;
+            if adapter.is_some() {
+                break;
+            }
+        }
+        let adapter = adapter.ok_or_else(|| anyhow!("no GPU or fallback adapter available"))?;
+        info!("Headless adapter: {:?}", adapter.get_info());
+        let (device, queue) = request_device(&adapter).await?;
+        let size = winit::dpi::PhysicalSize::new(opts.width.max(1), opts.height.max(1));
+        let texture = create_offscreen(&device, size.width, size.height);
+        Self::with_target(device, queue, Target::Offscreen { texture }, OFFSCREEN_FORMAT, size, scene)
+    }
+}
+
 impl<'w> FpsRenderer<'w> {
     pub fn new(window: &'w Window, scene: &FpsScene) -> Result<Self> {
         pollster::block_on(Self::new_async(window, scene))
@@ -176,23 +411,13 @@ impl<'w> FpsRenderer<'w> {
             })
             .await
             .expect("No adapter");
-        let required_limits = adapter.limits();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: Some("device"),
-                    required_features: wgpu::Features::empty(),
-                    required_limits,
-                },
-                None,
-            )
-            .await?;
+        let (device, queue) = request_device(&adapter).await?;
         let caps = surface.get_capabilities(&adapter);
         let format = caps.formats.iter().copied().find(|f| f.is_srgb()).unwrap_or(caps.formats[0]);
         let present_mode = wgpu::PresentMode::Fifo;
         let alpha_mode = caps.alpha_modes[0];
 
-        let mut config = wgpu::SurfaceConfiguration {
+        let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format,
             width: size.width.max(1),
@@ -203,14 +428,34 @@ impl<'w> FpsRenderer<'w> {
             desired_maximum_frame_latency: 2,
         };
         surface.configure(&device, &config);
+        let size = winit::dpi::PhysicalSize::new(config.width, config.height);
+        Self::with_target(device, queue, Target::Window { surface, config }, format, size, scene)
+    }
+
+    /// Setup shared by window and headless renderers once the device and target exist
+    fn with_target(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        target: Target<'w>,
+        format: wgpu::TextureFormat,
+        size: winit::dpi::PhysicalSize<u32>,
+        scene: &FpsScene,
+    ) -> Result<Self> {
+        let flat;
+        let scene = if scene.has_prefabs() || !scene.level.groups.is_empty() {
+            flat = scene.flatten()?;
+            &flat
+        } else {
+            scene
+        };
 
         // depth
-        let (depth_tex, depth_view) = create_depth(&device, config.width, config.height);
+        let (depth_tex, depth_view) = create_depth(&device, size.width, size.height);
 
         // pipeline
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("lighting"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/lighting_simple.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(concat!(include_str!("../shaders/shadow_sample.wgsl"), include_str!("../shaders/lights.wgsl"), include_str!("../shaders/lighting_simple.wgsl")).into()),
         });
 
         let v_layout = wgpu::VertexBufferLayout {
@@ -223,10 +468,12 @@ impl<'w> FpsRenderer<'w> {
             array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Instance,
             attributes: &wgpu::vertex_attr_array![
-                3=>Float32x3, // pos
-                4=>Float32x3, // scale
-                5=>Float32,   // rotY
-                6=>Float32x3  // color
+                3=>Float32x4, // model column 0
+                4=>Float32x4, // model column 1
+                5=>Float32x4, // model column 2
+                6=>Float32x4, // model column 3
+                7=>Float32x3, // color
+                8=>Float32x2  // metallic, roughness
             ],
         };
 
@@ -234,7 +481,7 @@ impl<'w> FpsRenderer<'w> {
             label: Some("cam-layout"),
             entries: &[wgpu::BindGroupLayoutEntry{
                 binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Buffer{
                     ty: wgpu::BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -279,60 +526,27 @@ impl<'w> FpsRenderer<'w> {
             ..Default::default()
         });
 
-        // Create lights bind group layout
-        let lights_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("lights-bind-group-layout"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-        });
+        let mut clusters = LightClusters::new(&device);
+        let mut shadows = ShadowMaps::new(&device, &[v_layout.clone(), i_layout.clone()]);
 
-        // Create pipeline layout with camera, texture and lights bind group layouts
+        // Create pipeline layout with camera, texture, lights and shadow bind group layouts
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("pipeline-layout"),
-            bind_group_layouts: &[&cam_layout, &texture_bind_group_layout, &lights_bind_group_layout],
+            bind_group_layouts: &[&cam_layout, &texture_bind_group_layout, &clusters.bind_layout, &shadows.bind_layout],
             push_constant_ranges: &[],
         });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[v_layout, i_layout],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState{
-                    format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: Some(wgpu::DepthStencilState{
-                format: wgpu::TextureFormat::Depth24Plus,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
+        // with post-processing the scene goes to an HDR target and the chain tonemaps it
+        let scene_format = if scene.render.post_process.is_some() { HDR_FORMAT } else { format };
+        let pipeline = create_pipeline(&device, &pipeline_layout, &shader, "pipeline", scene_format, &[v_layout.clone(), i_layout.clone()], &HashMap::new());
+        let pbr_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("pbr"),
+            source: wgpu::ShaderSource::Wgsl(concat!(include_str!("../shaders/shadow_sample.wgsl"), include_str!("../shaders/lights.wgsl"), include_str!("../shaders/pbr.wgsl")).into()),
         });
+        let pbr_constants = HashMap::from([("TONEMAP".to_string(), if scene.render.post_process.is_some() { 0.0 } else { 1.0 })]);
+        let pbr_pipeline = create_pipeline(&device, &pipeline_layout, &pbr_shader, "pbr-pipeline", scene_format, &[v_layout, i_layout], &pbr_constants);
+        let particle_pipeline = create_particle_pipeline(&device, &cam_layout, &texture_bind_group_layout, scene_format);
+        let post = scene.render.post_process.as_ref().map(|p| PostChain::new(&device, &queue, format, size.width, size.height, p));
 
         // buffers
         let verts = cube_vertices();
@@ -342,12 +556,10 @@ impl<'w> FpsRenderer<'w> {
             usage: wgpu::BufferUsages::VERTEX
         });
 
-        let instances: Vec<Instance> = scene.level.boxes.iter().map(Instance::from).collect();
-        let inst_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
-            label: Some("inst"),
-            contents: bytemuck::cast_slice(&instances),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST
-        });
+        let boxes = BoxInstances::new(scene.level.world_boxes());
+        let inst_buf = create_instance_buffer(&device, boxes.len().max(64).next_power_of_two());
+        queue.write_buffer(&inst_buf, 0, bytemuck::cast_slice(&boxes.instances));
+        let visible_buf = create_instance_buffer(&device, boxes.len().max(64).next_power_of_two());
 
         // camera
         let camera = Camera {
@@ -358,10 +570,10 @@ impl<'w> FpsRenderer<'w> {
             z_near: 0.1,
             z_far: 200.0,
         };
-        let vp = camera.proj(config.width as f32 / config.height as f32) * camera.view();
+        let cam_ubo = CameraUBO::new(&camera, size.width as f32 / size.height as f32);
         let cam_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
             label: Some("cam-ubo"),
-            contents: bytemuck::bytes_of(&CameraUBO{ view_proj: vp.to_cols_array_2d() }),
+            contents: bytemuck::bytes_of(&cam_ubo),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
         });
         let cam_bind = device.create_bind_group(&wgpu::BindGroupDescriptor{
@@ -371,56 +583,32 @@ impl<'w> FpsRenderer<'w> {
         });
 
         // lights
-        let mut lights_raw = LightsUBO {
-            count: 0,
-            _pad: [0; 3],
-            lights: [LightRaw {
-                position: [0.0; 3],
-                _pad0: 0.0,
-                color: [0.0; 3],
-                intensity: 0.0,
-                kind: 0,
-                _pad1: [0; 3],
-            }; 16],
-        };
-        
-        // Fill lights from scene
-        for (i, light) in scene.lights.iter().enumerate() {
-            if i >= 16 {
-                break;
-            }
-            lights_raw.count += 1;
-            lights_raw.lights[i] = LightRaw {
-                position: light.position,
-                _pad0: 0.0,
-                color: light.color,
-                intensity: light.intensity,
-                kind: if light.kind == "point" { 0 } else { 1 }, // 0 = point, 1 = directional
-                _pad1: [0; 3],
-            };
-        }
-        
-        let lights_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
-            label: Some("lights-ubo"),
-            contents: bytemuck::bytes_of(&lights_raw),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
-        });
-        let lights_bind = device.create_bind_group(&wgpu::BindGroupDescriptor{
-            label: Some("lights-bind"),
-            layout: &lights_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry{ binding:0, resource: lights_buf.as_entire_binding() }],
-        });
+        clusters.set_lights(&device, &queue, &scene.lights);
+        clusters.update(&device, &queue, &camera, size.width, size.height);
 
         // Initialize textures map
-        let textures = std::collections::HashMap::new();
+        let textures = HashMap::new();
+        let default_texture = create_texture_bind(&device, &queue, &texture_bind_group_layout, &texture_sampler, "white", 1, 1, &[255; 4]);
 
-        info!("FPS renderer ready: {}x{}", config.width, config.height);
+        // particles
+        let particle_quad = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
+            label: Some("particle-quad"),
+            contents: bytemuck::cast_slice(&[[-1.0f32, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]]),
+            usage: wgpu::BufferUsages::VERTEX
+        });
+        let particle_buf = create_particle_buffer(&device, 256);
+        let sprite = particle_sprite(32);
+        let particle_texture = create_texture_bind(&device, &queue, &texture_bind_group_layout, &texture_sampler, "particle", 32, 32, &sprite);
+
+        let box_bounds = boxes.bounds();
+        shadows.update(&device, &queue, &scene.lights, &camera, size.width as f32 / size.height as f32, &box_bounds);
+
+        info!("FPS renderer ready: {}x{}", size.width, size.height);
 
         Ok(Self{
-            surface,
+            target,
             device,
             queue,
-            config,
             size,
             color: wgpu::Color{
                 r: scene.render.clear_color[0] as f64,
@@ -431,16 +619,40 @@ impl<'w> FpsRenderer<'w> {
             depth_tex,
             depth_view,
             pipeline,
+            pbr_pipeline,
+            shading: scene.render.shading,
             vbo,
             cam_buf,
             cam_bind,
-            lights_buf,
-            lights_bind,
-            instances,
+            clusters,
+            boxes,
             inst_buf,
+            view_proj: Mat4::from_cols_array_2d(&cam_ubo.view_proj),
+            culling: true,
+            visible_boxes: Vec::new(),
+            visible_batches: Vec::new(),
+            visible_buf,
+            visible_meshes: Vec::new(),
+            stats: RenderStats::default(),
+            mesh_defs: scene.level.world_meshes(),
+            meshes: HashMap::new(),
+            mesh_draws: Vec::new(),
+            mesh_inst_buf: None,
             texture_bind_group_layout,
             textures,
             texture_sampler,
+            default_texture,
+            particles: ParticleSim::new(&scene.particles, 0),
+            particle_pipeline,
+            particle_quad,
+            particle_buf,
+            particle_count: 0,
+            particle_texture,
+            post,
+            shadows,
+            lights: scene.lights.clone(),
+            box_bounds,
+            bounds: box_bounds,
             camera,
         })
     }
@@ -448,73 +660,254 @@ impl<'w> FpsRenderer<'w> {
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width == 0 || new_size.height == 0 { return; }
         self.size = new_size;
-        self.config.width = new_size.width;
-        self.config.height = new_size.height;
-        self.surface.configure(&self.device, &self.config);
-        let (dt, view) = create_depth(&self.device, self.config.width, self.config.height);
+        match &mut self.target {
+            Target::Window { surface, config } => {
+                config.width = new_size.width;
+                config.height = new_size.height;
+                surface.configure(&self.device, config);
+            }
+            Target::Offscreen { texture } => *texture = create_offscreen(&self.device, new_size.width, new_size.height),
+        }
+        let (dt, view) = create_depth(&self.device, new_size.width, new_size.height);
         self.depth_tex = dt;
         self.depth_view = view;
+        if let Some(post) = &mut self.post {
+            post.resize(&self.device, &self.queue, new_size.width, new_size.height);
+        }
+        self.update_shadows();
+        self.update_clusters();
     }
 
     pub fn update_camera(&mut self) {
-        let vp = self.camera.proj(self.config.width as f32 / self.config.height as f32) * self.camera.view();
-        self.queue.write_buffer(&self.cam_buf, 0, bytemuck::bytes_of(&CameraUBO{ view_proj: vp.to_cols_array_2d() }));
+        let ubo = CameraUBO::new(&self.camera, self.size.width as f32 / self.size.height as f32);
+        self.queue.write_buffer(&self.cam_buf, 0, bytemuck::bytes_of(&ubo));
+        self.view_proj = Mat4::from_cols_array_2d(&ubo.view_proj);
+        // cascades and clusters follow the view
+        self.update_shadows();
+        self.update_clusters();
+    }
+
+    fn update_shadows(&mut self) {
+        let aspect = self.size.width as f32 / self.size.height as f32;
+        self.shadows.update(&self.device, &self.queue, &self.lights, &self.camera, aspect, &self.bounds);
+    }
+
+    fn update_clusters(&mut self) {
+        self.clusters.update(&self.device, &self.queue, &self.camera, self.size.width, self.size.height);
     }
 
     pub fn update_lights(&mut self, lights: &[kengaai_scene_fps::Light]) {
-        let mut lights_raw = LightsUBO {
-            count: 0,
-            _pad: [0; 3],
-            lights: [LightRaw {
-                position: [0.0; 3],
-                _pad0: 0.0,
-                color: [0.0; 3],
-                intensity: 0.0,
-                kind: 0,
-                _pad1: [0; 3],
-            }; 16],
-        };
-        
-        // Fill lights from scene
-        for (i, light) in lights.iter().enumerate() {
-            if i >= 16 {
-                break;
-            }
-            lights_raw.count += 1;
-            lights_raw.lights[i] = LightRaw {
-                position: light.position,
-                _pad0: 0.0,
-                color: light.color,
-                intensity: light.intensity,
-                kind: if light.kind == "point" { 0 } else { 1 }, // 0 = point, 1 = directional
-                _pad1: [0; 3],
-            };
-        }
-        
-        self.queue.write_buffer(&self.lights_buf, 0, bytemuck::bytes_of(&lights_raw));
+        self.clusters.set_lights(&self.device, &self.queue, lights);
+        self.lights = lights.to_vec();
+        self.update_shadows();
+        self.update_clusters();
     }
 
     pub fn render(&mut self) -> Result<()> {
         info!("Начало отрисовки кадра");
-        
-        let frame = match self.surface.get_current_texture() {
-            Ok(f) => f,
-            Err(_) => {
-                self.surface.configure(&self.device, &self.config);
-                self.surface.get_current_texture()?
+        let mut stats = RenderStats::default();
+        let start = Instant::now();
+        self.upload_particles();
+        self.flush_boxes();
+        stats.upload_time = start.elapsed();
+        let start = Instant::now();
+        self.cull();
+        stats.cull_time = start.elapsed();
+
+        match &self.target {
+            Target::Window { surface, config } => {
+                let frame = match surface.get_current_texture() {
+                    Ok(f) => f,
+                    Err(_) => {
+                        surface.configure(&self.device, config);
+                        surface.get_current_texture()?
+                    }
+                };
+                info!("Получен кадровый буфер");
+                self.draw(&frame.texture.create_view(&wgpu::TextureViewDescriptor::default()), &mut stats);
+                let start = Instant::now();
+                frame.present();
+                stats.submit_time += start.elapsed();
             }
-        };
-        
-        info!("Получен кадровый буфер");
-        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{ label: Some("encoder") });
+            Target::Offscreen { texture } => self.draw(&texture.create_view(&wgpu::TextureViewDescriptor::default()), &mut stats),
+        }
+        self.stats = stats;
+
+        info!("Кадр отрисован успешно");
+        Ok(())
+    }
+
+    /// Particles from the scene's emitters and from bursts, as they will be drawn
+    pub fn particles(&self) -> &ParticleSim {
+        &self.particles
+    }
+
+    /// Step the simulation or spawn bursts here; changes show up from the next [`FpsRenderer::render`]
+    pub fn particles_mut(&mut self) -> &mut ParticleSim {
+        &mut self.particles
+    }
+
+    fn upload_particles(&mut self) {
+        let instances: Vec<ParticleInstance> = self.particles.particles().iter().map(ParticleInstance::from).collect();
+        let needed = std::mem::size_of_val(instances.as_slice()) as u64;
+        if needed > self.particle_buf.size() {
+            let capacity = instances.len().next_power_of_two();
+            self.particle_buf = create_particle_buffer(&self.device, capacity);
+        }
+        if !instances.is_empty() {
+            self.queue.write_buffer(&self.particle_buf, 0, bytemuck::cast_slice(&instances));
+        }
+        self.particle_count = instances.len() as u32;
+    }
+
+    /// Add a box to the scene; it shows up from the next [`FpsRenderer::render`]
+    pub fn add_box(&mut self, def: BoxDef) -> BoxHandle {
+        self.boxes.insert(def)
+    }
+
+    /// Remove a box, returning it, or `None` if the handle is stale
+    pub fn remove_box(&mut self, handle: BoxHandle) -> Option<BoxDef> {
+        self.boxes.remove(handle)
+    }
+
+    /// Move, turn and resize a box; `rotation` replaces its `rotY`
+    pub fn set_transform(&mut self, handle: BoxHandle, pos: [f32; 3], rotation: Rotation, size: [f32; 3]) -> Result<()> {
+        let found = self.boxes.modify(handle, |def| {
+            def.pos = pos;
+            def.rot_y = 0.0;
+            def.rotation = Some(rotation);
+            def.size = size;
+        });
+        if !found {
+            bail!("no box for {:?}", handle);
+        }
+        Ok(())
+    }
+
+    /// Recolor a box: its material's color if it has one, else its own
+    pub fn set_color(&mut self, handle: BoxHandle, color: [f32; 3]) -> Result<()> {
+        let found = self.boxes.modify(handle, |def| match &mut def.material {
+            Some(material) => material.color = Some(color),
+            None => def.color = color,
+        });
+        if !found {
+            bail!("no box for {:?}", handle);
+        }
+        Ok(())
+    }
+
+    /// The box a handle names, unless it was removed
+    pub fn box_def(&self, handle: BoxHandle) -> Option<&BoxDef> {
+        self.boxes.get(handle)
+    }
+
+    /// Every box with its handle. The scene's boxes come first, in `level.world_boxes()` order.
+    pub fn boxes(&self) -> impl Iterator<Item = (BoxHandle, &BoxDef)> {
+        self.boxes.iter()
+    }
 
+    /// Upload the instances changed since the last frame and refit shadows to boxes that moved
+    fn flush_boxes(&mut self) {
+        let (dirty, moved) = self.boxes.take_changes();
+        let stride = std::mem::size_of::<Instance>();
+        if (self.boxes.len() * stride) as u64 > self.inst_buf.size() {
+            self.inst_buf = create_instance_buffer(&self.device, self.boxes.len().next_power_of_two());
+            self.queue.write_buffer(&self.inst_buf, 0, bytemuck::cast_slice(&self.boxes.instances));
+        } else if let Some(range) = dirty {
+            self.queue.write_buffer(&self.inst_buf, (range.start * stride) as u64, bytemuck::cast_slice(&self.boxes.instances[range]));
+        }
+        if moved {
+            self.box_bounds = self.boxes.bounds();
+            self.bounds = self.box_bounds.union(&mesh_bounds(&self.mesh_defs, &self.meshes));
+            self.update_shadows();
+        }
+    }
+
+    /// Counts and timings of the last frame
+    pub fn stats(&self) -> &RenderStats {
+        &self.stats
+    }
+
+    /// Leave boxes and meshes outside the view out of the main pass; on by default
+    pub fn set_culling(&mut self, culling: bool) {
+        self.culling = culling;
+    }
+
+    pub fn culling(&self) -> bool {
+        self.culling
+    }
+
+    /// Collect the boxes and meshes in view and upload the boxes among them
+    fn cull(&mut self) {
+        let frustum = Frustum::from_view_proj(self.view_proj);
+        let inside = |b: &Aabb| !self.culling || frustum.intersects_aabb(b);
+        self.boxes.visible(inside, &mut self.visible_boxes, &mut self.visible_batches);
+        self.visible_meshes.clear();
+        self.visible_meshes.extend((0..self.mesh_draws.len()).filter(|&i| inside(&self.mesh_draws[i].bounds)));
+
+        if std::mem::size_of_val(self.visible_boxes.as_slice()) as u64 > self.visible_buf.size() {
+            self.visible_buf = create_instance_buffer(&self.device, self.visible_boxes.len().next_power_of_two());
+        }
+        if !self.visible_boxes.is_empty() {
+            self.queue.write_buffer(&self.visible_buf, 0, bytemuck::cast_slice(&self.visible_boxes));
+        }
+    }
+
+    /// Pipeline for geometry that does (`pbr`) or doesn't carry a material under the current shading
+    fn pipeline_for(&self, pbr: bool) -> &wgpu::RenderPipeline {
+        if pbr && self.shading == Shading::Pbr { &self.pbr_pipeline } else { &self.pipeline }
+    }
+
+    /// Bind the geometry buffers and draw every box and mesh, without touching pipelines or
+    /// textures. Returns the number of draw calls.
+    fn draw_casters<'a>(&'a self, rp: &mut wgpu::RenderPass<'a>) -> u32 {
+        let mut calls = 0;
+        if !self.boxes.is_empty() {
+            rp.set_vertex_buffer(0, self.vbo.slice(..));
+            rp.set_vertex_buffer(1, self.inst_buf.slice(..));
+            rp.draw(0..36, 0..self.boxes.len() as u32);
+            calls += 1;
+        }
+        if let Some(mesh_inst_buf) = &self.mesh_inst_buf {
+            rp.set_vertex_buffer(1, mesh_inst_buf.slice(..));
+            for draw in &self.mesh_draws {
+                let prim = &self.meshes[&draw.file][draw.primitive];
+                rp.set_vertex_buffer(0, prim.vbo.slice(..));
+                rp.set_index_buffer(prim.ibo.slice(..), wgpu::IndexFormat::Uint32);
+                rp.draw_indexed(0..prim.index_count, 0, draw.instance..draw.instance + 1);
+            }
+            calls += self.mesh_draws.len() as u32;
+        }
+        calls
+    }
+
+    fn draw(&self, view: &wgpu::TextureView, stats: &mut RenderStats) {
+        let start = Instant::now();
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{ label: Some("encoder") });
+        for (i, slot) in self.shadows.slots.iter().enumerate() {
+            let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("shadow-pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.shadows.layer_views[i],
+                    depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(1.0), store: wgpu::StoreOp::Store }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            let res = slot.resolution as f32;
+            rp.set_viewport(0.0, 0.0, res, res, 0.0, 1.0);
+            rp.set_pipeline(&self.shadows.pipeline);
+            rp.set_bind_group(0, &self.shadows.pass_bind, &[ShadowMaps::pass_offset(i)]);
+            stats.draw_calls += self.draw_casters(&mut rp);
+        }
         {
             info!("Начало рендер-пасса");
             let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor{
                 label: Some("main-pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment{
-                    view: &view,
+                    view: self.post.as_ref().map_or(view, |post| &post.hdr_view),
                     resolve_target: None,
                     ops: wgpu::Operations{ load: wgpu::LoadOp::Clear(self.color), store: wgpu::StoreOp::Store },
                 })],
@@ -528,25 +921,136 @@ impl<'w> FpsRenderer<'w> {
             });
             
             info!("Установка pipeline и bind groups");
-            rp.set_pipeline(&self.pipeline);
             rp.set_bind_group(0, &self.cam_bind, &[]);
-            // Set default texture bind group (white texture) if no textures are loaded
-            if let Some((_, ref bind_group)) = self.textures.values().next() {
-                rp.set_bind_group(1, bind_group, &[]);
-            }
-            rp.set_bind_group(2, &self.lights_bind, &[]);
+            rp.set_bind_group(2, &self.clusters.bind, &[]);
+            rp.set_bind_group(3, &self.shadows.bind, &[]);
             
-            info!("Отрисовка геометрии: {} инстансов", self.instances.len());
+            info!("Отрисовка геометрии: {} инстансов, {} батчей", self.visible_boxes.len(), self.visible_batches.len());
             rp.set_vertex_buffer(0, self.vbo.slice(..));
-            rp.set_vertex_buffer(1, self.inst_buf.slice(..));
-            rp.draw(0..36, 0..self.instances.len() as u32);
+            rp.set_vertex_buffer(1, self.visible_buf.slice(..));
+            for batch in &self.visible_batches {
+                let texture = batch.texture.as_ref().and_then(|name| self.textures.get(name)).unwrap_or(&self.default_texture);
+                rp.set_pipeline(self.pipeline_for(batch.pbr));
+                rp.set_bind_group(1, &texture.1, &[]);
+                rp.draw(0..36, batch.instances.clone());
+            }
+            let drawn = (self.visible_boxes.len() + self.visible_meshes.len()) as u32;
+            stats.drawn_instances = drawn;
+            stats.culled_instances = (self.boxes.len() + self.mesh_draws.len()) as u32 - drawn;
+            stats.draw_calls += (self.visible_batches.len() + self.visible_meshes.len()) as u32;
+
+            if let Some(mesh_inst_buf) = &self.mesh_inst_buf {
+                rp.set_vertex_buffer(1, mesh_inst_buf.slice(..));
+                for draw in self.visible_meshes.iter().map(|&i| &self.mesh_draws[i]) {
+                    let prim = &self.meshes[&draw.file][draw.primitive];
+                    let texture = draw
+                        .texture
+                        .as_ref()
+                        .and_then(|name| self.textures.get(name))
+                        .or(prim.texture.as_ref())
+                        .unwrap_or(&self.default_texture);
+                    rp.set_pipeline(self.pipeline_for(draw.pbr));
+                    rp.set_bind_group(1, &texture.1, &[]);
+                    rp.set_vertex_buffer(0, prim.vbo.slice(..));
+                    rp.set_index_buffer(prim.ibo.slice(..), wgpu::IndexFormat::Uint32);
+                    rp.draw_indexed(0..prim.index_count, 0, draw.instance..draw.instance + 1);
+                }
+            }
+
+            // particles last, tested against the scene's depth but not writing it
+            if self.particle_count > 0 {
+                rp.set_pipeline(&self.particle_pipeline);
+                rp.set_bind_group(1, &self.particle_texture.1, &[]);
+                rp.set_vertex_buffer(0, self.particle_quad.slice(..));
+                rp.set_vertex_buffer(1, self.particle_buf.slice(..));
+                rp.draw(0..6, 0..self.particle_count);
+                stats.draw_calls += 1;
+            }
         }
 
+        if let Some(post) = &self.post {
+            post.run(&mut encoder, view);
+        }
+
+        let commands = encoder.finish();
+        stats.encode_time = start.elapsed();
+
         info!("Отправка команд и представление кадра");
+        let start = Instant::now();
+        self.queue.submit([commands]);
+        stats.submit_time = start.elapsed();
+    }
+
+    /// Copy the last rendered frame to the CPU as sRGB-encoded RGBA. Only renderers created with
+    /// [`FpsRenderer::new_headless`] can be read back.
+    pub fn read_frame(&self) -> Result<image::RgbaImage> {
+        let Target::Offscreen { texture } = &self.target else {
+            bail!("frame readback needs a renderer created with `FpsRenderer::new_headless`");
+        };
+        let (width, height) = (self.size.width, self.size.height);
+        // rows in the staging buffer are padded to the copy alignment
+        let row = 4 * width;
+        let padded_row = row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: padded_row as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("readback") });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &staging,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(padded_row), rows_per_image: Some(height) },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
         self.queue.submit([encoder.finish()]);
-        frame.present();
-        
-        info!("Кадр отрисован успешно");
+
+        let slice = staging.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |r| {
+            let _ = tx.send(r);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        rx.recv()??;
+
+        let mut pixels = Vec::with_capacity((row * height) as usize);
+        for padded in slice.get_mapped_range().chunks(padded_row as usize) {
+            pixels.extend_from_slice(&padded[..row as usize]);
+        }
+        staging.unmap();
+        image::RgbaImage::from_raw(width, height, pixels).ok_or_else(|| anyhow!("readback size mismatch"))
+    }
+
+    /// Render a frame and read it back, for thumbnails and image comparisons
+    pub fn render_to_image(&mut self) -> Result<image::RgbaImage> {
+        self.render()?;
+        self.read_frame()
+    }
+
+    /// Switch between the simple and PBR paths; starts out as the scene's `render.shading`
+    pub fn set_shading(&mut self, shading: Shading) {
+        self.shading = shading;
+    }
+
+    pub fn shading(&self) -> Shading {
+        self.shading
+    }
+
+    /// Post-processing parameters, if the renderer was created from a scene with `render.postProcess`
+    pub fn post_process(&self) -> Option<&PostProcess> {
+        self.post.as_ref().map(PostChain::settings)
+    }
+
+    /// Change the post-processing parameters. The HDR chain itself is set up from the scene the
+    /// renderer was created with, so scenes without `render.postProcess` can't turn it on later.
+    pub fn set_post_process(&mut self, post: &PostProcess) -> Result<()> {
+        let Some(chain) = &mut self.post else {
+            bail!("post-processing needs a renderer created from a scene with `render.postProcess`");
+        };
+        chain.set(&self.queue, post);
         Ok(())
     }
 
@@ -561,59 +1065,17 @@ impl<'w> FpsRenderer<'w> {
             return Ok(());
         }
 
-        // Create texture
-        let texture_size = wgpu::Extent3d {
+        let (texture, bind_group) = create_texture_bind(
+            &self.device,
+            &self.queue,
+            &self.texture_bind_group_layout,
+            &self.texture_sampler,
+            &name,
             width,
             height,
-            depth_or_array_layers: 1,
-        };
-        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
-            label: Some(&format!("texture_{}", name)),
-            size: texture_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-
-        // Upload texture data
-        self.queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
             rgba_data,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * width),
-                rows_per_image: Some(height),
-            },
-            texture_size,
         );
 
-        // Create texture view
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-        // Create bind group
-        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some(&format!("bind_group_{}", name)),
-            layout: &self.texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.texture_sampler),
-                },
-            ],
-        });
-
         // Store texture and bind group
         self.textures.insert(name, (texture, bind_group));
         Ok(())
@@ -633,6 +1095,282 @@ impl<'w> FpsRenderer<'w> {
         // Load texture
         self.load_texture(name, dimensions.0, dimensions.1, &img)
     }
+
+    /// Upload a mesh for the `level.meshes` entries whose `file` is `name`
+    pub fn load_mesh_asset(&mut self, name: String, asset: &MeshAsset) -> Result<()> {
+        if self.meshes.contains_key(&name) {
+            return Ok(());
+        }
+
+        let mut prims = Vec::with_capacity(asset.primitives.len());
+        for (i, p) in asset.primitives.iter().enumerate() {
+            let verts: Vec<Vertex> = (0..p.positions.len())
+                .map(|v| Vertex { pos: p.positions[v], normal: p.normals[v], tex_coords: p.uvs[v] })
+                .collect();
+            let label = format!("mesh_{}_{}", name, i);
+            let vbo = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&label),
+                contents: bytemuck::cast_slice(&verts),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+            let ibo = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&label),
+                contents: bytemuck::cast_slice(&p.indices),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+            let texture = p.texture.as_ref().map(|img| {
+                create_texture_bind(
+                    &self.device,
+                    &self.queue,
+                    &self.texture_bind_group_layout,
+                    &self.texture_sampler,
+                    &label,
+                    img.width(),
+                    img.height(),
+                    img,
+                )
+            });
+            prims.push(GpuPrimitive {
+                vbo,
+                ibo,
+                index_count: p.indices.len() as u32,
+                base_color: p.base_color,
+                metallic: p.metallic,
+                roughness: p.roughness,
+                texture,
+                bounds: Aabb::from_points(p.positions.iter().map(|&v| Vec3::from(v))),
+            });
+        }
+        self.meshes.insert(name, prims);
+
+        // placements of files that aren't loaded yet are skipped, so rebuild as each file arrives
+        let (instances, draws) = mesh_instances(&self.mesh_defs, &self.meshes);
+        self.mesh_inst_buf = (!instances.is_empty()).then(|| {
+            self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("mesh-inst"),
+                contents: bytemuck::cast_slice(&instances),
+                usage: wgpu::BufferUsages::VERTEX,
+            })
+        });
+        self.mesh_draws = draws;
+        self.bounds = self.box_bounds.union(&mesh_bounds(&self.mesh_defs, &self.meshes));
+        self.update_shadows();
+        Ok(())
+    }
+
+    /// Load an OBJ or glTF file with [`load_mesh`] and upload it under `name`
+    pub fn load_mesh_file<P: AsRef<std::path::Path>>(&mut self, name: String, path: P) -> Result<()> {
+        if self.meshes.contains_key(&name) {
+            return Ok(());
+        }
+        let asset = load_mesh(path)?;
+        self.load_mesh_asset(name, &asset)
+    }
+}
+
+/// Upload RGBA pixels and create the bind group the box shader samples from
+#[allow(clippy::too_many_arguments)]
+fn create_texture_bind(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    name: &str,
+    width: u32,
+    height: u32,
+    rgba_data: &[u8],
+) -> (wgpu::Texture, wgpu::BindGroup) {
+    // Create texture
+    let texture_size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(&format!("texture_{}", name)),
+        size: texture_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    // Upload texture data
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        rgba_data,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * width),
+            rows_per_image: Some(height),
+        },
+        texture_size,
+    );
+
+    // Create texture view
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    // Create bind group
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(&format!("bind_group_{}", name)),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    });
+    (texture, bind_group)
+}
+
+/// Pipeline drawing the vertex and instance layouts shared by boxes and meshes with `shader`
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    label: &str,
+    format: wgpu::TextureFormat,
+    buffers: &[wgpu::VertexBufferLayout],
+    constants: &HashMap<String, f64>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers,
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState{
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions { constants, ..Default::default() },
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState{
+            format: wgpu::TextureFormat::Depth24Plus,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Additive billboard pipeline for `particles.wgsl`
+fn create_particle_pipeline(
+    device: &wgpu::Device,
+    cam_layout: &wgpu::BindGroupLayout,
+    texture_layout: &wgpu::BindGroupLayout,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("particles"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/particles.wgsl").into()),
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("particle-pipeline-layout"),
+        bind_group_layouts: &[cam_layout, texture_layout],
+        push_constant_ranges: &[],
+    });
+    let additive = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("particle-pipeline"),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[
+                wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0=>Float32x2],
+                },
+                wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ParticleInstance>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![1=>Float32x3, 2=>Float32, 3=>Float32x3, 4=>Float32],
+                },
+            ],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                // alpha of the target is left as it was
+                blend: Some(wgpu::BlendState { color: additive, alpha: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::Zero, ..additive } }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth24Plus,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("inst"),
+        size: (capacity * std::mem::size_of::<Instance>()) as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_particle_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("particle-instances"),
+        size: (capacity * std::mem::size_of::<ParticleInstance>()) as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// White `size`² RGBA sprite whose alpha falls off smoothly from the center to the edge
+fn particle_sprite(size: u32) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let d = Vec2::new(x as f32 + 0.5, y as f32 + 0.5) / size as f32 * 2.0 - Vec2::ONE;
+            let a = (1.0 - d.length()).clamp(0.0, 1.0);
+            rgba.extend_from_slice(&[255, 255, 255, (a * a * 255.0).round() as u8]);
+        }
+    }
+    rgba
 }
 
 fn create_depth(device: &wgpu::Device, width: u32, height: u32) -> (wgpu::Texture, wgpu::TextureView) {
@@ -701,3 +1439,31 @@ impl FpsController {
         cam.pos += wish * dt;
     }
 }
+
+fn create_offscreen(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("offscreen"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: OFFSCREEN_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue)> {
+    let required_limits = adapter.limits();
+    let device = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("device"),
+                required_features: wgpu::Features::empty(),
+                required_limits,
+            },
+            None,
+        )
+        .await?;
+    Ok(device)
+}
@@ -0,0 +1,277 @@
+//! Clustered forward lighting. Lights live in a storage buffer, and the view is split into a grid
+//! of clusters: screen tiles cut into slices of exponentially growing depth. Whenever the camera or
+//! the lights change, the CPU lists the point and spot lights whose range reaches each cluster, so
+//! a fragment only evaluates the lights of its own cluster, plus the directional lights, which
+//! reach every cluster and are listed once at the start of the index list.
+
+use crate::shadow;
+use crate::Camera;
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+use kengaai_scene_fps::{Light, LightKind, DEFAULT_LIGHT_RANGE, DEFAULT_SPOT_INNER_ANGLE, DEFAULT_SPOT_OUTER_ANGLE, MAX_LIGHTS};
+
+/// Clusters across the screen, down it and in depth
+const GRID: [u32; 3] = [16, 9, 24];
+const CLUSTERS: usize = (GRID[0] * GRID[1] * GRID[2]) as usize;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct LightRaw {
+    position: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    intensity: f32,
+    /// Axis of a spot light, or the direction a directional light shines in
+    direction: [f32; 3],
+    kind: u32,
+    cos_inner: f32,
+    cos_outer: f32,
+    /// First shadow slot, -1 for lights without shadows
+    shadow: i32,
+    _pad: u32,
+}
+
+impl LightRaw {
+    fn new(light: &Light, shadow: Option<usize>) -> Self {
+        let kind = match light.kind {
+            LightKind::Point => 0,
+            LightKind::Directional => 1,
+            LightKind::Spot => 2,
+        };
+        // directional lights keep their direction in `position`
+        let direction = match light.kind {
+            LightKind::Directional => Some(light.position),
+            _ => light.direction,
+        };
+        let direction = direction.and_then(|d| Vec3::from(d).try_normalize()).unwrap_or(Vec3::NEG_Y);
+        let cos_outer = light.outer_angle.unwrap_or(DEFAULT_SPOT_OUTER_ANGLE).cos();
+        // smoothstep between equal edges is undefined
+        let cos_inner = light.inner_angle.unwrap_or(DEFAULT_SPOT_INNER_ANGLE).cos().max(cos_outer + 1e-4);
+        Self {
+            position: light.position,
+            range: light.range.unwrap_or(DEFAULT_LIGHT_RANGE),
+            color: light.color,
+            intensity: light.intensity,
+            direction: direction.to_array(),
+            kind,
+            cos_inner,
+            cos_outer,
+            shadow: shadow.map_or(-1, |s| s as i32),
+            _pad: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct GridUBO {
+    view: [[f32; 4]; 4],
+    /// Size of a screen tile in pixels
+    tile_size: [f32; 2],
+    near: f32,
+    /// Depth slices per unit of `ln(depth / near)`
+    slice_scale: f32,
+    dims: [u32; 3],
+    /// Directional lights at the start of the index list, shared by every cluster
+    global_count: u32,
+}
+
+/// GPU side of the lights: the storage buffers the lighting shaders read through bind group 2
+pub(crate) struct LightClusters {
+    pub bind_layout: wgpu::BindGroupLayout,
+    pub bind: wgpu::BindGroup,
+    grid_buf: wgpu::Buffer,
+    lights_buf: wgpu::Buffer,
+    clusters_buf: wgpu::Buffer,
+    indices_buf: wgpu::Buffer,
+    /// Lights as last uploaded
+    lights: Vec<LightRaw>,
+    /// Cluster and light of every pair where the light reaches the cluster, reused between updates
+    pairs: Vec<(u32, u32)>,
+    /// First index and count of each cluster's lights
+    clusters: Vec<[u32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl LightClusters {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer { ty, has_dynamic_offset: false, min_binding_size: None },
+            count: None,
+        };
+        let storage = wgpu::BufferBindingType::Storage { read_only: true };
+        let bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lights-bind-group-layout"),
+            entries: &[entry(0, wgpu::BufferBindingType::Uniform), entry(1, storage), entry(2, storage), entry(3, storage)],
+        });
+        let grid_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light-grid"),
+            size: std::mem::size_of::<GridUBO>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let lights_buf = create_storage(device, "lights", 16 * std::mem::size_of::<LightRaw>());
+        let clusters_buf = create_storage(device, "light-clusters", CLUSTERS * 8);
+        let indices_buf = create_storage(device, "light-indices", 1024 * 4);
+        let bind = create_bind(device, &bind_layout, [&grid_buf, &lights_buf, &clusters_buf, &indices_buf]);
+        Self {
+            bind_layout,
+            bind,
+            grid_buf,
+            lights_buf,
+            clusters_buf,
+            indices_buf,
+            lights: Vec::new(),
+            pairs: Vec::new(),
+            clusters: vec![[0; 2]; CLUSTERS],
+            indices: Vec::new(),
+        }
+    }
+
+    /// Upload the first [`MAX_LIGHTS`] of `lights`; takes effect with the next [`LightClusters::update`]
+    pub fn set_lights(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lights: &[Light]) {
+        let slots = shadow::light_slots(lights);
+        self.lights = lights.iter().zip(slots).take(MAX_LIGHTS).map(|(l, s)| LightRaw::new(l, s)).collect();
+        let size = std::mem::size_of_val(self.lights.as_slice());
+        if size as u64 > self.lights_buf.size() {
+            self.lights_buf = create_storage(device, "lights", size.next_power_of_two());
+            self.rebind(device);
+        }
+        if size > 0 {
+            queue.write_buffer(&self.lights_buf, 0, bytemuck::cast_slice(&self.lights));
+        }
+    }
+
+    /// Assign the lights to the clusters of `camera`'s view of a `width`×`height` target
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera: &Camera, width: u32, height: u32) {
+        let view = camera.view();
+        let (near, far) = (camera.z_near, camera.z_far.max(camera.z_near * 1.001));
+        let tan_y = (camera.fov_y.to_radians() * 0.5).tan();
+        let tan_x = tan_y * width as f32 / height as f32;
+        let slice_scale = GRID[2] as f32 / (far / near).ln();
+        let slice_depth = |k: u32| near * (far / near).powf(k as f32 / GRID[2] as f32);
+        let slice_of = |d: f32| (((d / near).ln() * slice_scale).floor().max(0.0) as u32).min(GRID[2] - 1);
+        // tiles whose NDC span reaches `lo..hi`, along an axis with `n` tiles
+        let tiles = |lo: f32, hi: f32, n: u32| {
+            let tile = |v: f32| (((v * 0.5 + 0.5) * n as f32).floor().max(0.0) as u32).min(n - 1);
+            (tile(lo), tile(hi))
+        };
+
+        self.pairs.clear();
+        self.indices.clear();
+        for (i, light) in self.lights.iter().enumerate() {
+            if light.kind == 1 {
+                self.indices.push(i as u32);
+                continue;
+            }
+            let c = view.transform_point3(Vec3::from(light.position));
+            let (d, r) = (-c.z, light.range);
+            if d + r < near || d - r > far {
+                continue;
+            }
+            let (k0, k1) = (slice_of((d - r).max(near)), slice_of((d + r).min(far)));
+            // the sphere's box projects inside the span of its corners; a box reaching the near
+            // plane may cover any tile
+            let ((x0, x1), (y0, y1)) = if d - r <= near {
+                ((0, GRID[0] - 1), (0, GRID[1] - 1))
+            } else {
+                let span = |v: f32, tan: f32| {
+                    let ends = [(v - r) / (d - r), (v - r) / (d + r), (v + r) / (d - r), (v + r) / (d + r)].map(|e| e / tan);
+                    (ends.into_iter().fold(f32::INFINITY, f32::min), ends.into_iter().fold(f32::NEG_INFINITY, f32::max))
+                };
+                let (xl, xh) = span(c.x, tan_x);
+                let (yl, yh) = span(c.y, tan_y);
+                // rows count down from the top of the screen
+                let (rows_lo, rows_hi) = tiles(-yh, -yl, GRID[1]);
+                (tiles(xl, xh, GRID[0]), (rows_lo, rows_hi))
+            };
+            for k in k0..=k1 {
+                let (z0, z1) = (slice_depth(k), slice_depth(k + 1));
+                for y in y0..=y1 {
+                    // NDC span of the row, top edge first
+                    let (top, bottom) = (1.0 - 2.0 * y as f32 / GRID[1] as f32, 1.0 - 2.0 * (y + 1) as f32 / GRID[1] as f32);
+                    for x in x0..=x1 {
+                        let (left, right) = (-1.0 + 2.0 * x as f32 / GRID[0] as f32, -1.0 + 2.0 * (x + 1) as f32 / GRID[0] as f32);
+                        // the cluster's view-space box: its NDC edges at both ends of the slice
+                        let min = Vec3::new(
+                            (left * tan_x * z0).min(left * tan_x * z1),
+                            (bottom * tan_y * z0).min(bottom * tan_y * z1),
+                            -z1,
+                        );
+                        let max = Vec3::new(
+                            (right * tan_x * z0).max(right * tan_x * z1),
+                            (top * tan_y * z0).max(top * tan_y * z1),
+                            -z0,
+                        );
+                        if c.clamp(min, max).distance_squared(c) <= r * r {
+                            let cluster = (k * GRID[1] + y) * GRID[0] + x;
+                            self.pairs.push((cluster, i as u32));
+                        }
+                    }
+                }
+            }
+        }
+
+        // counting sort of the pairs by cluster, after the shared directional lights
+        let global_count = self.indices.len() as u32;
+        self.clusters.fill([0; 2]);
+        for &(cluster, _) in &self.pairs {
+            self.clusters[cluster as usize][1] += 1;
+        }
+        let mut next = global_count;
+        for c in &mut self.clusters {
+            c[0] = next;
+            next += c[1];
+        }
+        self.indices.resize(next as usize, 0);
+        let mut fill: Vec<u32> = self.clusters.iter().map(|c| c[0]).collect();
+        for &(cluster, light) in &self.pairs {
+            let at = &mut fill[cluster as usize];
+            self.indices[*at as usize] = light;
+            *at += 1;
+        }
+
+        let size = std::mem::size_of_val(self.indices.as_slice());
+        if size as u64 > self.indices_buf.size() {
+            self.indices_buf = create_storage(device, "light-indices", size.next_power_of_two());
+            self.rebind(device);
+        }
+        if size > 0 {
+            queue.write_buffer(&self.indices_buf, 0, bytemuck::cast_slice(&self.indices));
+        }
+        queue.write_buffer(&self.clusters_buf, 0, bytemuck::cast_slice(&self.clusters));
+        let grid = GridUBO {
+            view: view.to_cols_array_2d(),
+            tile_size: [width as f32 / GRID[0] as f32, height as f32 / GRID[1] as f32],
+            near,
+            slice_scale,
+            dims: GRID,
+            global_count,
+        };
+        queue.write_buffer(&self.grid_buf, 0, bytemuck::bytes_of(&grid));
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device) {
+        self.bind = create_bind(device, &self.bind_layout, [&self.grid_buf, &self.lights_buf, &self.clusters_buf, &self.indices_buf]);
+    }
+}
+
+fn create_storage(device: &wgpu::Device, label: &str, size: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: size as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_bind(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, buffers: [&wgpu::Buffer; 4]) -> wgpu::BindGroup {
+    let entries: Vec<wgpu::BindGroupEntry> = buffers
+        .iter()
+        .enumerate()
+        .map(|(i, buf)| wgpu::BindGroupEntry { binding: i as u32, resource: buf.as_entire_binding() })
+        .collect();
+    device.create_bind_group(&wgpu::BindGroupDescriptor { label: Some("lights-bind"), layout, entries: &entries })
+}
@@ -0,0 +1,228 @@
+//! Loading of `level.meshes` files: Wavefront OBJ (with MTL materials) and glTF 2.0.
+//!
+//! Files are flattened into [`MeshPrimitive`]s on the CPU: one per OBJ model or glTF primitive,
+//! with node transforms already applied and every primitive indexed.
+
+use anyhow::{anyhow, bail, Context, Result};
+use glam::{Mat3, Mat4, Vec3};
+use std::path::Path;
+
+/// One drawable part of a mesh file with a single material
+#[derive(Debug, Clone)]
+pub struct MeshPrimitive {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+    /// Linear RGBA multiplier from the file's material
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Base color texture from the file, embedded or external
+    pub texture: Option<image::RgbaImage>,
+}
+
+impl MeshPrimitive {
+    fn new(positions: Vec<[f32; 3]>, normals: Vec<[f32; 3]>, uvs: Vec<[f32; 2]>, indices: Vec<u32>) -> Self {
+        let mut p = Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            base_color: [1.0; 4],
+            metallic: 0.0,
+            roughness: 1.0,
+            texture: None,
+        };
+        if p.indices.is_empty() {
+            p.indices = (0..p.positions.len() as u32).collect();
+        }
+        if p.normals.len() != p.positions.len() {
+            p.normals = smooth_normals(&p.positions, &p.indices);
+        }
+        if p.uvs.len() != p.positions.len() {
+            p.uvs = vec![[0.0; 2]; p.positions.len()];
+        }
+        p
+    }
+}
+
+/// Contents of a mesh file
+#[derive(Debug, Clone, Default)]
+pub struct MeshAsset {
+    pub primitives: Vec<MeshPrimitive>,
+}
+
+impl MeshAsset {
+    pub fn vertex_count(&self) -> usize {
+        self.primitives.iter().map(|p| p.positions.len()).sum()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.primitives.iter().map(|p| p.indices.len() / 3).sum()
+    }
+}
+
+/// Load an `.obj`, `.gltf` or `.glb` file. Relative texture and buffer paths resolve against the
+/// file's directory.
+pub fn load_mesh<P: AsRef<Path>>(path: P) -> Result<MeshAsset> {
+    let path = path.as_ref();
+    let ext = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+    let asset = match ext.as_deref() {
+        Some("obj") => load_obj(path),
+        Some("gltf" | "glb") => load_gltf(path),
+        _ => bail!("unsupported mesh format: {}", path.display()),
+    }
+    .with_context(|| format!("loading mesh {}", path.display()))?;
+    if asset.primitives.is_empty() {
+        bail!("{} contains no triangles", path.display());
+    }
+    for p in &asset.primitives {
+        if p.indices.len() % 3 != 0 || p.indices.iter().any(|&i| i as usize >= p.positions.len()) {
+            bail!("{} has indices that don't form triangles over its vertices", path.display());
+        }
+    }
+    Ok(asset)
+}
+
+fn load_obj(path: &Path) -> Result<MeshAsset> {
+    let opts = tobj::LoadOptions { single_index: true, triangulate: true, ignore_points: true, ignore_lines: true };
+    let (models, materials) = tobj::load_obj(path, &opts)?;
+    // a missing or broken MTL file leaves the geometry usable
+    let materials = materials.unwrap_or_else(|e| {
+        log::warn!("{}: {e}", path.display());
+        Vec::new()
+    });
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    let mut out = MeshAsset::default();
+    for model in models {
+        let m = model.mesh;
+        let mut prim = MeshPrimitive::new(
+            m.positions.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+            m.normals.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+            // OBJ puts v = 0 at the bottom of the image, wgpu at the top
+            m.texcoords.chunks_exact(2).map(|c| [c[0], 1.0 - c[1]]).collect(),
+            m.indices,
+        );
+        if let Some(mat) = m.material_id.and_then(|i| materials.get(i)) {
+            if let Some([r, g, b]) = mat.diffuse {
+                prim.base_color = [r, g, b, mat.dissolve.unwrap_or(1.0)];
+            }
+            if let Some(tex) = &mat.diffuse_texture {
+                let file = dir.join(tex);
+                prim.texture = Some(image::open(&file).with_context(|| format!("texture {}", file.display()))?.to_rgba8());
+            }
+        }
+        out.primitives.push(prim);
+    }
+    Ok(out)
+}
+
+fn load_gltf(path: &Path) -> Result<MeshAsset> {
+    let (doc, buffers, images) = gltf::import(path)?;
+    let mut out = MeshAsset::default();
+    match doc.default_scene().or_else(|| doc.scenes().next()) {
+        Some(scene) => {
+            for node in scene.nodes() {
+                gltf_node(&node, Mat4::IDENTITY, &buffers, &images, &mut out)?;
+            }
+        }
+        // a file without scenes is still a bag of meshes
+        None => {
+            for mesh in doc.meshes() {
+                gltf_mesh(&mesh, Mat4::IDENTITY, &buffers, &images, &mut out)?;
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn gltf_node(
+    node: &gltf::Node,
+    parent: Mat4,
+    buffers: &[gltf::buffer::Data],
+    images: &[gltf::image::Data],
+    out: &mut MeshAsset,
+) -> Result<()> {
+    let xf = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        gltf_mesh(&mesh, xf, buffers, images, out)?;
+    }
+    for child in node.children() {
+        gltf_node(&child, xf, buffers, images, out)?;
+    }
+    Ok(())
+}
+
+fn gltf_mesh(
+    mesh: &gltf::Mesh,
+    xf: Mat4,
+    buffers: &[gltf::buffer::Data],
+    images: &[gltf::image::Data],
+    out: &mut MeshAsset,
+) -> Result<()> {
+    let normal_xf = Mat3::from_mat4(xf).inverse().transpose();
+    for prim in mesh.primitives() {
+        if prim.mode() != gltf::mesh::Mode::Triangles {
+            log::warn!("skipping non-triangle primitive in mesh {:?}", mesh.name());
+            continue;
+        }
+        let reader = prim.reader(|b| buffers.get(b.index()).map(|d| &d.0[..]));
+        let Some(positions) = reader.read_positions() else { continue };
+        let positions: Vec<[f32; 3]> = positions.map(|p| xf.transform_point3(Vec3::from(p)).to_array()).collect();
+        let normals: Vec<[f32; 3]> = reader
+            .read_normals()
+            .map(|n| n.map(|n| (normal_xf * Vec3::from(n)).normalize_or_zero().to_array()).collect())
+            .unwrap_or_default();
+        let uvs = reader.read_tex_coords(0).map(|t| t.into_f32().collect()).unwrap_or_default();
+        let indices = reader.read_indices().map(|i| i.into_u32().collect()).unwrap_or_default();
+        let mut p = MeshPrimitive::new(positions, normals, uvs, indices);
+
+        let pbr = prim.material().pbr_metallic_roughness();
+        p.base_color = pbr.base_color_factor();
+        p.metallic = pbr.metallic_factor();
+        p.roughness = pbr.roughness_factor();
+        if let Some(info) = pbr.base_color_texture() {
+            let index = info.texture().source().index();
+            let data = images.get(index).ok_or_else(|| anyhow!("missing image {index}"))?;
+            p.texture = Some(gltf_rgba(data)?);
+        }
+        out.primitives.push(p);
+    }
+    Ok(())
+}
+
+/// Expand decoded glTF image data to 8-bit RGBA
+fn gltf_rgba(data: &gltf::image::Data) -> Result<image::RgbaImage> {
+    use gltf::image::Format;
+    let px = &data.pixels;
+    let rgba: Vec<u8> = match data.format {
+        Format::R8 => px.iter().flat_map(|&r| [r, r, r, 255]).collect(),
+        Format::R8G8 => px.chunks_exact(2).flat_map(|c| [c[0], c[1], 0, 255]).collect(),
+        Format::R8G8B8 => px.chunks_exact(3).flat_map(|c| [c[0], c[1], c[2], 255]).collect(),
+        Format::R8G8B8A8 => px.clone(),
+        // keep the high byte of little-endian 16-bit channels
+        Format::R16G16B16 => px.chunks_exact(6).flat_map(|c| [c[1], c[3], c[5], 255]).collect(),
+        Format::R16G16B16A16 => px.chunks_exact(8).flat_map(|c| [c[1], c[3], c[5], c[7]]).collect(),
+        other => bail!("unsupported texture format {other:?}"),
+    };
+    image::RgbaImage::from_raw(data.width, data.height, rgba).ok_or_else(|| anyhow!("texture size mismatch"))
+}
+
+/// Area-weighted vertex normals for meshes that don't carry their own
+fn smooth_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut acc = vec![Vec3::ZERO; positions.len()];
+    for tri in indices.chunks_exact(3) {
+        let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| i as usize);
+        if a.max(b).max(c) >= positions.len() {
+            continue;
+        }
+        let [pa, pb, pc] = [a, b, c].map(|i| Vec3::from(positions[i]));
+        let n = (pb - pa).cross(pc - pa);
+        for i in [a, b, c] {
+            acc[i] += n;
+        }
+    }
+    acc.into_iter().map(|n| n.normalize_or_zero().to_array()).collect()
+}
@@ -0,0 +1,220 @@
+//! CPU particle simulation for the scene's `particles` emitters and for bursts spawned at runtime.
+//!
+//! The simulation is deterministic: the same seed, steps and bursts always give the same
+//! particles, so it can be tested without a GPU. The renderer draws the result as camera-facing
+//! billboards with additive blending.
+
+use glam::Vec3;
+use kengaai_scene_fps::ParticleSystem;
+
+/// Lifetime in seconds of particles from emitters that leave `lifetime` unset
+pub const DEFAULT_LIFETIME: f32 = 1.0;
+/// Most particles alive at once; emitters and bursts stop spawning at the limit
+pub const MAX_PARTICLES: usize = 65_536;
+/// Half-width of a billboard in world units
+pub const DEFAULT_PARTICLE_SIZE: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Particle {
+    pub pos: Vec3,
+    pub vel: Vec3,
+    pub color: [f32; 3],
+    /// Half-width of the billboard in world units
+    pub size: f32,
+    /// Seconds since the particle spawned
+    pub age: f32,
+    pub lifetime: f32,
+    /// Index of the scene emitter that spawned it; `None` for bursts
+    pub emitter: Option<usize>,
+}
+
+impl Particle {
+    /// Fraction of the lifetime still ahead, 1 at spawn and 0 at death
+    pub fn remaining(&self) -> f32 {
+        (1.0 - self.age / self.lifetime).clamp(0.0, 1.0)
+    }
+}
+
+/// A one-off spray of particles from gameplay code, e.g. a muzzle flash or a bullet impact
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Burst {
+    pub position: Vec3,
+    /// Axis of the cone the particles leave in; need not be normalized
+    pub direction: Vec3,
+    pub color: [f32; 3],
+    pub count: u32,
+    pub lifetime: f32,
+    pub speed: f32,
+    /// Half-angle of the cone in radians; π or more sprays in every direction
+    pub spread: f32,
+    pub size: f32,
+}
+
+impl Default for Burst {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            direction: Vec3::Y,
+            color: [1.0; 3],
+            count: 16,
+            lifetime: 0.5,
+            speed: 2.0,
+            spread: std::f32::consts::PI,
+            size: DEFAULT_PARTICLE_SIZE,
+        }
+    }
+}
+
+/// A scene emitter: keeps up to `count` particles alive, spawning them upwards in a cone of
+/// half-angle `spread` radians at a steady rate of `count / lifetime` per second
+#[derive(Debug, Clone)]
+struct Emitter {
+    def: ParticleSystem,
+    /// Spawns owed from earlier steps that didn't add up to a whole particle
+    pending: f32,
+    alive: u32,
+}
+
+impl Emitter {
+    fn lifetime(&self) -> f32 {
+        if self.def.lifetime > 0.0 { self.def.lifetime } else { DEFAULT_LIFETIME }
+    }
+}
+
+/// All live particles, the scene emitters that feed them and the random state they draw from
+#[derive(Debug, Clone)]
+pub struct ParticleSim {
+    particles: Vec<Particle>,
+    emitters: Vec<Emitter>,
+    rng: Rng,
+}
+
+impl ParticleSim {
+    /// Simulation with one emitter per scene entry. Emitters start empty and fill up over their
+    /// first lifetime.
+    pub fn new(systems: &[ParticleSystem], seed: u64) -> Self {
+        let emitters = systems.iter().map(|def| Emitter { def: def.clone(), pending: 0.0, alive: 0 }).collect();
+        Self { particles: Vec::new(), emitters, rng: Rng::new(seed) }
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Drop every particle, leaving the emitters to refill from scratch
+    pub fn clear(&mut self) {
+        self.particles.clear();
+        for e in &mut self.emitters {
+            e.pending = 0.0;
+            e.alive = 0;
+        }
+    }
+
+    /// Advance by `dt` seconds: age and move particles, remove the expired ones, then let the
+    /// emitters spawn what they owe
+    pub fn step(&mut self, dt: f32) {
+        if dt <= 0.0 || !dt.is_finite() {
+            return;
+        }
+        let emitters = &mut self.emitters;
+        self.particles.retain_mut(|p| {
+            p.age += dt;
+            if p.age >= p.lifetime {
+                if let Some(e) = p.emitter {
+                    emitters[e].alive -= 1;
+                }
+                return false;
+            }
+            p.pos += p.vel * dt;
+            true
+        });
+
+        for i in 0..self.emitters.len() {
+            let e = &mut self.emitters[i];
+            let lifetime = e.lifetime();
+            e.pending += e.def.count as f32 / lifetime * dt;
+            let room = e.def.count.saturating_sub(e.alive).min((MAX_PARTICLES - self.particles.len()) as u32);
+            let n = (e.pending.floor() as u32).min(room);
+            // spawns that found no room are dropped rather than saved up for a burst later
+            e.pending = (e.pending - n as f32).min(1.0);
+            e.alive += n;
+            let (origin, color, speed, spread) = (Vec3::from(e.def.position), e.def.color, e.def.speed, e.def.spread);
+            for _ in 0..n {
+                let vel = self.rng.cone(Vec3::Y, spread) * speed;
+                self.particles.push(Particle {
+                    pos: origin,
+                    vel,
+                    color,
+                    size: DEFAULT_PARTICLE_SIZE,
+                    age: 0.0,
+                    lifetime,
+                    emitter: Some(i),
+                });
+            }
+        }
+    }
+
+    /// Spawn `burst.count` particles at once, fewer if that would pass [`MAX_PARTICLES`].
+    /// Returns how many were spawned.
+    pub fn burst(&mut self, burst: &Burst) -> usize {
+        if burst.lifetime <= 0.0 {
+            return 0;
+        }
+        let n = (burst.count as usize).min(MAX_PARTICLES - self.particles.len());
+        let axis = burst.direction.try_normalize().unwrap_or(Vec3::Y);
+        for _ in 0..n {
+            let vel = self.rng.cone(axis, burst.spread) * burst.speed;
+            self.particles.push(Particle {
+                pos: burst.position,
+                vel,
+                color: burst.color,
+                size: burst.size,
+                age: 0.0,
+                lifetime: burst.lifetime,
+                emitter: None,
+            });
+        }
+        n
+    }
+}
+
+/// SplitMix64, so runs don't depend on a platform or crate version
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`
+    fn unit(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Unit vector uniformly distributed over the cap of half-angle `spread` around `axis`
+    fn cone(&mut self, axis: Vec3, spread: f32) -> Vec3 {
+        let cos_max = spread.clamp(0.0, std::f32::consts::PI).cos();
+        let cos_t = 1.0 - self.unit() * (1.0 - cos_max);
+        let sin_t = (1.0 - cos_t * cos_t).max(0.0).sqrt();
+        let (sin_p, cos_p) = (std::f32::consts::TAU * self.unit()).sin_cos();
+        let (u, v) = axis.any_orthonormal_pair();
+        axis * cos_t + (u * cos_p + v * sin_p) * sin_t
+    }
+}
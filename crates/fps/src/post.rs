@@ -0,0 +1,353 @@
+//! HDR post-processing for scenes with `render.postProcess`: the scene is drawn into a float
+//! target, its bright parts are extracted and blurred at half resolution, and a final pass adds
+//! the bloom, applies exposure, tonemapping, gamma and vignette, and writes the frame.
+
+use bytemuck::{Pod, Zeroable};
+use kengaai_scene_fps::{
+    PostProcess, Tonemap, DEFAULT_BLOOM_INTENSITY, DEFAULT_BLOOM_RADIUS, DEFAULT_BLOOM_THRESHOLD, DEFAULT_EXPOSURE,
+    DEFAULT_GAMMA,
+};
+
+/// Format of the target the scene is rendered into before the chain runs
+pub(crate) const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Uniform offsets of the bright, horizontal and vertical blur passes, a multiple of every
+/// adapter's uniform offset alignment
+const BLOOM_STRIDE: u64 = 256;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct BloomUBO {
+    threshold: f32,
+    intensity: f32,
+    radius: f32,
+    _pad: f32,
+    /// One texel of the pass input along the blur axis, zero for the bright-pass
+    direction: [f32; 2],
+    _pad2: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct PostUBO {
+    exposure: f32,
+    gamma: f32,
+    vignette: f32,
+    bloom_intensity: f32,
+    tonemap: u32,
+    srgb_target: u32,
+    _pad: [u32; 2],
+}
+
+/// Render targets, pipelines and parameters of the chain
+pub(crate) struct PostChain {
+    settings: PostProcess,
+    target_format: wgpu::TextureFormat,
+    size: (u32, u32),
+    pub hdr_view: wgpu::TextureView,
+    /// Ping-pong targets of the bloom, at half resolution
+    bloom_views: [wgpu::TextureView; 2],
+    sampler: wgpu::Sampler,
+    bloom_layout: wgpu::BindGroupLayout,
+    composite_layout: wgpu::BindGroupLayout,
+    bright_pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    bloom_ubo: wgpu::Buffer,
+    post_ubo: wgpu::Buffer,
+    /// Bright-pass, horizontal and vertical blur
+    bloom_binds: [wgpu::BindGroup; 3],
+    composite_bind: wgpu::BindGroup,
+}
+
+impl PostChain {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target_format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        settings: &PostProcess,
+    ) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler_entry = wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bloom_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bloom-layout"),
+            entries: &[texture_entry(0), sampler_entry, uniform_entry(2)],
+        });
+        let composite_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post-layout"),
+            entries: &[texture_entry(0), sampler_entry, texture_entry(2), uniform_entry(3)],
+        });
+
+        let bloom_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("bloom"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/bloom.wgsl").into()),
+        });
+        let post_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("postprocess"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/postprocess.wgsl").into()),
+        });
+        let bright_pipeline = fullscreen_pipeline(device, &bloom_layout, &bloom_shader, "fragment_main", HDR_FORMAT, "bloom-bright");
+        let blur_pipeline = fullscreen_pipeline(device, &bloom_layout, &bloom_shader, "blur_main", HDR_FORMAT, "bloom-blur");
+        let composite_pipeline = fullscreen_pipeline(device, &composite_layout, &post_shader, "fragment_main", target_format, "post-composite");
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post-sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bloom_ubo = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bloom-ubo"),
+            size: BLOOM_STRIDE * 3,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let post_ubo = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("post-ubo"),
+            size: std::mem::size_of::<PostUBO>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (hdr_view, bloom_views) = create_targets(device, width, height);
+        let (bloom_binds, composite_bind) =
+            create_binds(device, &bloom_layout, &composite_layout, &sampler, &bloom_ubo, &post_ubo, &hdr_view, &bloom_views);
+        let mut chain = Self {
+            settings: settings.clone(),
+            target_format,
+            size: (width, height),
+            hdr_view,
+            bloom_views,
+            sampler,
+            bloom_layout,
+            composite_layout,
+            bright_pipeline,
+            blur_pipeline,
+            composite_pipeline,
+            bloom_ubo,
+            post_ubo,
+            bloom_binds,
+            composite_bind,
+        };
+        chain.set(queue, settings);
+        chain
+    }
+
+    pub fn settings(&self) -> &PostProcess {
+        &self.settings
+    }
+
+    /// Upload new parameters; the targets stay as they are
+    pub fn set(&mut self, queue: &wgpu::Queue, settings: &PostProcess) {
+        self.settings = settings.clone();
+        let bloom = settings.bloom.clone().unwrap_or_default();
+        let (bw, bh) = bloom_size(self.size.0, self.size.1);
+        let pass = |direction| BloomUBO {
+            threshold: bloom.threshold.unwrap_or(DEFAULT_BLOOM_THRESHOLD),
+            intensity: bloom.intensity.unwrap_or(DEFAULT_BLOOM_INTENSITY),
+            radius: bloom.radius.unwrap_or(DEFAULT_BLOOM_RADIUS),
+            _pad: 0.0,
+            direction,
+            _pad2: [0.0; 2],
+        };
+        for (i, direction) in [[0.0, 0.0], [1.0 / bw as f32, 0.0], [0.0, 1.0 / bh as f32]].into_iter().enumerate() {
+            queue.write_buffer(&self.bloom_ubo, i as u64 * BLOOM_STRIDE, bytemuck::bytes_of(&pass(direction)));
+        }
+        let ubo = PostUBO {
+            exposure: settings.exposure.unwrap_or(DEFAULT_EXPOSURE),
+            gamma: settings.gamma.unwrap_or(DEFAULT_GAMMA),
+            vignette: settings.vignette,
+            // without bloom the half-resolution target is never drawn and holds black
+            bloom_intensity: if settings.bloom.is_some() { bloom.intensity.unwrap_or(DEFAULT_BLOOM_INTENSITY) } else { 0.0 },
+            tonemap: match settings.tonemap {
+                Tonemap::Aces => 0,
+                Tonemap::Reinhard => 1,
+            },
+            srgb_target: self.target_format.is_srgb() as u32,
+            _pad: [0; 2],
+        };
+        queue.write_buffer(&self.post_ubo, 0, bytemuck::bytes_of(&ubo));
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.size = (width, height);
+        (self.hdr_view, self.bloom_views) = create_targets(device, width, height);
+        (self.bloom_binds, self.composite_bind) = create_binds(
+            device,
+            &self.bloom_layout,
+            &self.composite_layout,
+            &self.sampler,
+            &self.bloom_ubo,
+            &self.post_ubo,
+            &self.hdr_view,
+            &self.bloom_views,
+        );
+        // blur steps are in texels of the new size
+        let settings = self.settings.clone();
+        self.set(queue, &settings);
+    }
+
+    /// Record the chain from the HDR target into `view`
+    pub fn run(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        if self.settings.bloom.is_some() {
+            // bright-pass into the first target, blur across into the second and back
+            let passes = [(&self.bright_pipeline, 0, 0), (&self.blur_pipeline, 1, 1), (&self.blur_pipeline, 2, 0)];
+            for (pipeline, bind, target) in passes {
+                fullscreen_pass(encoder, "bloom-pass", &self.bloom_views[target], pipeline, &self.bloom_binds[bind]);
+            }
+        }
+        fullscreen_pass(encoder, "post-pass", view, &self.composite_pipeline, &self.composite_bind);
+    }
+}
+
+fn bloom_size(width: u32, height: u32) -> (u32, u32) {
+    ((width / 2).max(1), (height / 2).max(1))
+}
+
+fn create_targets(device: &wgpu::Device, width: u32, height: u32) -> (wgpu::TextureView, [wgpu::TextureView; 2]) {
+    let target = |label, width, height| {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HDR_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    };
+    let (bw, bh) = bloom_size(width, height);
+    (target("hdr", width, height), [target("bloom-a", bw, bh), target("bloom-b", bw, bh)])
+}
+
+/// Bind groups of the bloom passes, each reading the previous pass's output, and of the composite
+#[allow(clippy::too_many_arguments)]
+fn create_binds(
+    device: &wgpu::Device,
+    bloom_layout: &wgpu::BindGroupLayout,
+    composite_layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    bloom_ubo: &wgpu::Buffer,
+    post_ubo: &wgpu::Buffer,
+    hdr_view: &wgpu::TextureView,
+    bloom_views: &[wgpu::TextureView; 2],
+) -> ([wgpu::BindGroup; 3], wgpu::BindGroup) {
+    let inputs = [hdr_view, &bloom_views[0], &bloom_views[1]];
+    let bloom_binds = std::array::from_fn(|i| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bloom-bind"),
+            layout: bloom_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(inputs[i]) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(sampler) },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: bloom_ubo,
+                        offset: i as u64 * BLOOM_STRIDE,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<BloomUBO>() as u64),
+                    }),
+                },
+            ],
+        })
+    });
+    let composite_bind = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("post-bind"),
+        layout: composite_layout,
+        entries: &[
+            wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(hdr_view) },
+            wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(sampler) },
+            wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&bloom_views[0]) },
+            wgpu::BindGroupEntry { binding: 3, resource: post_ubo.as_entire_binding() },
+        ],
+    });
+    (bloom_binds, composite_bind)
+}
+
+fn fullscreen_pipeline(
+    device: &wgpu::Device,
+    bind_layout: &wgpu::BindGroupLayout,
+    shader: &wgpu::ShaderModule,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+    label: &str,
+) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: &[bind_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vertex_main",
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point,
+            targets: &[Some(wgpu::ColorTargetState { format, blend: None, write_mask: wgpu::ColorWrites::ALL })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+fn fullscreen_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    view: &wgpu::TextureView,
+    pipeline: &wgpu::RenderPipeline,
+    bind: &wgpu::BindGroup,
+) {
+    let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: wgpu::StoreOp::Store },
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+    rp.set_pipeline(pipeline);
+    rp.set_bind_group(0, bind, &[]);
+    rp.draw(0..6, 0..1);
+}
@@ -0,0 +1,418 @@
+//! Shadow maps for lights with `castShadows`: cascaded orthographic maps for directional lights
+//! and six perspective faces for point lights, filtered with 3×3 PCF in the lighting shaders.
+//!
+//! Every map is a layer of one depth texture array sized for the largest requested resolution;
+//! lights asking for less render into the top-left corner of their layers.
+
+use crate::{Aabb, Camera};
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+use kengaai_scene_fps::{
+    Light, LightKind, DEFAULT_SHADOW_RESOLUTION, MAX_LIGHTS, MAX_SHADOWED_DIRECTIONAL_LIGHTS,
+    MAX_SHADOWED_POINT_LIGHTS, MAX_SHADOW_RESOLUTION,
+};
+
+/// Most cascades a directional light is split into
+pub(crate) const MAX_CASCADES: usize = 4;
+const MAX_SLOTS: usize = MAX_CASCADES * MAX_SHADOWED_DIRECTIONAL_LIGHTS + 6 * MAX_SHADOWED_POINT_LIGHTS;
+/// Camera distance one cascade covers before the shadow range is split further
+const CASCADE_SPAN: f32 = 25.0;
+/// Near plane of point light shadow faces
+const POINT_NEAR: f32 = 0.05;
+/// Stride of the per-pass view-projection matrices, a multiple of every adapter's uniform offset alignment
+const PASS_STRIDE: u64 = 256;
+pub(crate) const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Look direction and up vector of the point light faces, in the order the shader picks them
+const CUBE_FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::NEG_Y),
+    (Vec3::NEG_X, Vec3::NEG_Y),
+    (Vec3::Y, Vec3::Z),
+    (Vec3::NEG_Y, Vec3::NEG_Z),
+    (Vec3::Z, Vec3::NEG_Y),
+    (Vec3::NEG_Z, Vec3::NEG_Y),
+];
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct SlotRaw {
+    view_proj: [[f32; 4]; 4],
+    /// Fraction of the layer the map covers
+    uv_scale: f32,
+    /// One texel of the layer in UV units
+    texel: f32,
+    /// World-space normal offset against acne; per unit of distance for perspective slots
+    normal_offset: f32,
+    perspective: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct ShadowsUBO {
+    slots: [SlotRaw; MAX_SLOTS],
+    /// Camera distance where each cascade ends
+    cascade_far: [f32; 4],
+    cascade_count: u32,
+    _pad: [u32; 3],
+}
+
+/// One map to render: a layer of the atlas seen from a light
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ShadowSlot {
+    pub view_proj: Mat4,
+    pub resolution: u32,
+    perspective: bool,
+}
+
+/// Which maps the current lights and camera need
+#[derive(Debug, Clone)]
+pub(crate) struct ShadowPlan {
+    pub slots: Vec<ShadowSlot>,
+    pub cascade_far: [f32; MAX_CASCADES],
+    pub cascade_count: usize,
+}
+
+/// Layers the atlas needs for `lights`, which doesn't change as the camera moves
+fn layer_count(lights: &[Light]) -> usize {
+    let casters = |kind| lights.iter().take(MAX_LIGHTS).filter(|l| l.cast_shadows && l.kind == kind).count();
+    let layers = casters(LightKind::Directional).min(MAX_SHADOWED_DIRECTIONAL_LIGHTS) * MAX_CASCADES
+        + casters(LightKind::Point).min(MAX_SHADOWED_POINT_LIGHTS) * 6;
+    // some Vulkan drivers make square arrays of 6n layers cube compatible and then sample them
+    // as a 2D array wrongly, so such counts get a spare layer
+    if layers % 6 == 0 { layers + 1 } else { layers }
+}
+
+/// First slot of each light that gets shadow maps: the shadowed directional and point lights
+/// among the first [`MAX_LIGHTS`], up to the limits for each kind
+pub(crate) fn light_slots(lights: &[Light]) -> Vec<Option<usize>> {
+    let (mut directional, mut point, mut next) = (0, 0, 0);
+    let mut slots = vec![None; lights.len()];
+    for (slot, light) in slots.iter_mut().zip(lights).take(MAX_LIGHTS) {
+        if !light.cast_shadows {
+            continue;
+        }
+        match light.kind {
+            LightKind::Directional if directional < MAX_SHADOWED_DIRECTIONAL_LIGHTS => {
+                directional += 1;
+                *slot = Some(next);
+                next += MAX_CASCADES;
+            }
+            LightKind::Point if point < MAX_SHADOWED_POINT_LIGHTS => {
+                point += 1;
+                *slot = Some(next);
+                next += 6;
+            }
+            _ => {}
+        }
+    }
+    slots
+}
+
+impl ShadowPlan {
+    /// Maps for the shadowed lights among the first [`MAX_LIGHTS`], covering the part of `bounds`
+    /// the camera can see. Resolutions are clamped to `max_size`.
+    pub fn new(lights: &[Light], camera: &Camera, aspect: f32, bounds: &Aabb, max_size: u32) -> Self {
+        let mut plan = Self { slots: Vec::new(), cascade_far: [0.0; MAX_CASCADES], cascade_count: 0 };
+        for (light, first) in lights.iter().zip(light_slots(lights)) {
+            let Some(first) = first else { continue };
+            debug_assert_eq!(first, plan.slots.len());
+            let resolution = light.shadow_resolution.unwrap_or(DEFAULT_SHADOW_RESOLUTION).clamp(1, max_size);
+            match light.kind {
+                LightKind::Directional => plan.cascades(Vec3::from(light.position), resolution, camera, aspect, bounds),
+                LightKind::Point => {
+                    let pos = Vec3::from(light.position);
+                    // the faces only need to reach the farthest geometry
+                    let far = if bounds.is_empty() { 1.0 } else { bounds.corners().iter().map(|c| c.distance(pos)).fold(1.0, f32::max) };
+                    let proj = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, POINT_NEAR, far);
+                    for (dir, up) in CUBE_FACES {
+                        let view_proj = proj * Mat4::look_to_rh(pos, dir, up);
+                        plan.slots.push(ShadowSlot { view_proj, resolution, perspective: true });
+                    }
+                }
+                LightKind::Spot => unreachable!("spot lights get no shadow slots"),
+            }
+        }
+        plan
+    }
+
+    /// Split the visible range into cascades, each an orthographic map around a slice of the view
+    fn cascades(&mut self, dir: Vec3, resolution: u32, camera: &Camera, aspect: f32, bounds: &Aabb) {
+        let dir = dir.try_normalize().unwrap_or(Vec3::NEG_Y);
+        let near = camera.z_near;
+        let extent = if bounds.is_empty() { 0.0 } else { 2.0 * bounds.half_extents().length() };
+        let far = camera.z_far.min(extent).max(near + 1.0);
+        let count = ((far / CASCADE_SPAN).ceil() as usize).clamp(1, MAX_CASCADES);
+        self.cascade_count = count;
+
+        let forward = Camera::dir(camera.yaw, camera.pitch);
+        let right = forward.cross(Vec3::Y).try_normalize().unwrap_or(Vec3::X);
+        let up = right.cross(forward);
+        let tan_y = (camera.fov_y.to_radians() * 0.5).tan();
+        let up_hint = if dir.y.abs() > 0.99 { Vec3::X } else { Vec3::Y };
+
+        let mut start = near;
+        for c in 0..MAX_CASCADES {
+            if c >= count {
+                // unused cascades keep a valid layer with nothing in range
+                self.slots.push(ShadowSlot { view_proj: Mat4::ZERO, resolution, perspective: false });
+                continue;
+            }
+            // halfway between uniform and logarithmic splits
+            let t = (c + 1) as f32 / count as f32;
+            let end = 0.5 * (near + (far - near) * t) + 0.5 * near * (far / near).powf(t);
+            self.cascade_far[c] = end;
+
+            let corners = [start, end].map(|d| {
+                let center = camera.pos + forward * d;
+                let (h, w) = (tan_y * d, tan_y * d * aspect);
+                [center + up * h + right * w, center + up * h - right * w, center - up * h + right * w, center - up * h - right * w]
+            });
+            let corners = corners.as_flattened();
+            let center = corners.iter().copied().sum::<Vec3>() / corners.len() as f32;
+            // a sphere keeps the map size fixed as the camera turns, so shadow edges don't swim
+            let radius = corners.iter().map(|p| p.distance(center)).fold(0.0, f32::max);
+            let radius = (radius * 16.0).ceil() / 16.0;
+            // casters outside the slice but between it and the light must still be drawn
+            let reach = if bounds.is_empty() { 0.0 } else { bounds.half_extents().length() + bounds.center().distance(center) };
+            let back = radius.max(reach);
+
+            let view = Mat4::look_to_rh(center - dir * back, dir, up_hint);
+            let proj = Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, back + radius.max(reach));
+            let mut view_proj = proj * view;
+            // snap the world origin to whole texels
+            let half_res = resolution as f32 * 0.5;
+            let origin = view_proj.project_point3(Vec3::ZERO);
+            let snapped = (origin.truncate() * half_res).round() / half_res;
+            let shift = snapped - origin.truncate();
+            view_proj = Mat4::from_translation(shift.extend(0.0)) * view_proj;
+
+            self.slots.push(ShadowSlot { view_proj, resolution, perspective: false });
+            start = end;
+        }
+    }
+}
+
+/// GPU side of the shadow maps: the depth atlas, its sampling bindings and the depth-only pipeline
+pub(crate) struct ShadowMaps {
+    pub bind_layout: wgpu::BindGroupLayout,
+    pub bind: wgpu::BindGroup,
+    pub pipeline: wgpu::RenderPipeline,
+    pub pass_bind: wgpu::BindGroup,
+    ubo: wgpu::Buffer,
+    pass_buf: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    atlas: wgpu::Texture,
+    /// One render target per layer
+    pub layer_views: Vec<wgpu::TextureView>,
+    /// Size of the atlas layers
+    size: u32,
+    pub slots: Vec<ShadowSlot>,
+}
+
+impl ShadowMaps {
+    pub fn new(device: &wgpu::Device, buffers: &[wgpu::VertexBufferLayout]) -> Self {
+        let bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("shadow-layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+        });
+        let pass_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("shadow-pass-layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(64),
+                },
+                count: None,
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shadow"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/shadow.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("shadow-pipeline-layout"),
+            bind_group_layouts: &[&pass_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("shadow-pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers,
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: SHADOW_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState { constant: 2, slope_scale: 2.0, clamp: 0.0 },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let ubo = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("shadow-ubo"),
+            size: std::mem::size_of::<ShadowsUBO>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let pass_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("shadow-pass"),
+            size: PASS_STRIDE * MAX_SLOTS as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let pass_bind = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("shadow-pass-bind"),
+            layout: &pass_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &pass_buf,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(64),
+                }),
+            }],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("shadow-sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let (atlas, layer_views, bind) = create_atlas(device, &bind_layout, &ubo, &sampler, 1, 1);
+        Self { bind_layout, bind, pipeline, pass_bind, ubo, pass_buf, sampler, atlas, layer_views, size: 1, slots: Vec::new() }
+    }
+
+    /// Offset of slot `i`'s matrix in the pass buffer, for the dynamic binding
+    pub fn pass_offset(i: usize) -> u32 {
+        (i as u64 * PASS_STRIDE) as u32
+    }
+
+    /// Re-plan the maps for the current lights and camera, growing the atlas when needed
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lights: &[Light], camera: &Camera, aspect: f32, bounds: &Aabb) {
+        let max_size = MAX_SHADOW_RESOLUTION.min(device.limits().max_texture_dimension_2d);
+        let plan = ShadowPlan::new(lights, camera, aspect, bounds, max_size);
+        let size = plan.slots.iter().map(|s| s.resolution).max().unwrap_or(1);
+        let layers = layer_count(lights) as u32;
+        if size != self.size || layers != self.layer_views.len() as u32 {
+            let (atlas, views, bind) = create_atlas(device, &self.bind_layout, &self.ubo, &self.sampler, size, layers);
+            (self.atlas, self.layer_views, self.bind, self.size) = (atlas, views, bind, size);
+        }
+
+        let mut ubo = ShadowsUBO::zeroed();
+        for (raw, slot) in ubo.slots.iter_mut().zip(&plan.slots) {
+            let uv_scale = slot.resolution as f32 / size as f32;
+            let texel_world = if slot.perspective {
+                2.0 / slot.resolution as f32
+            } else {
+                // an orthographic map spans 2 / m00 world units
+                2.0 / (slot.view_proj.x_axis.truncate().length().max(f32::EPSILON) * slot.resolution as f32)
+            };
+            *raw = SlotRaw {
+                view_proj: slot.view_proj.to_cols_array_2d(),
+                uv_scale,
+                texel: 1.0 / size as f32,
+                normal_offset: 1.5 * texel_world,
+                perspective: if slot.perspective { 1.0 } else { 0.0 },
+            };
+        }
+        ubo.cascade_far = plan.cascade_far;
+        ubo.cascade_count = plan.cascade_count as u32;
+        queue.write_buffer(&self.ubo, 0, bytemuck::bytes_of(&ubo));
+
+        let mut passes = vec![0u8; PASS_STRIDE as usize * plan.slots.len()];
+        for (chunk, slot) in passes.chunks_mut(PASS_STRIDE as usize).zip(&plan.slots) {
+            chunk[..64].copy_from_slice(bytemuck::bytes_of(&slot.view_proj.to_cols_array_2d()));
+        }
+        queue.write_buffer(&self.pass_buf, 0, &passes);
+        self.slots = plan.slots;
+    }
+}
+
+/// Depth texture array with `layers` layers of `size`², a render view per layer and the
+/// sampling bind group
+fn create_atlas(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    ubo: &wgpu::Buffer,
+    sampler: &wgpu::Sampler,
+    size: u32,
+    layers: u32,
+) -> (wgpu::Texture, Vec<wgpu::TextureView>, wgpu::BindGroup) {
+    let atlas = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("shadow-atlas"),
+        size: wgpu::Extent3d { width: size, height: size, depth_or_array_layers: layers },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: SHADOW_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let views = (0..layers)
+        .map(|layer| {
+            atlas.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("shadow-layer"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            })
+        })
+        .collect();
+    let array_view = atlas.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("shadow-array"),
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+    let bind = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("shadow-bind"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry { binding: 0, resource: ubo.as_entire_binding() },
+            wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&array_view) },
+            wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::Sampler(sampler) },
+        ],
+    });
+    (atlas, views, bind)
+}
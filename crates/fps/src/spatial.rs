@@ -0,0 +1,487 @@
+//! Bounding volume hierarchy over level boxes and trigger volumes.
+//!
+//! Boxes take their full orientation from the scene, grouped boxes included; triggers are
+//! axis-aligned. Sizes are half-extents, as in the scene format.
+
+use glam::{Mat4, Quat, Vec3, Vec4};
+use kengaai_scene_fps::{BoxDef, FpsScene, Trigger};
+use std::cell::Cell;
+
+/// Most volumes stored in a single leaf
+const LEAF_SIZE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb { min: Vec3::splat(f32::INFINITY), max: Vec3::splat(f32::NEG_INFINITY) };
+
+    pub fn from_center(center: Vec3, half: Vec3) -> Self {
+        Self { min: center - half, max: center + half }
+    }
+
+    pub fn union(&self, o: &Aabb) -> Aabb {
+        Aabb { min: self.min.min(o.min), max: self.max.max(o.max) }
+    }
+
+    /// Smallest box around `points`; [`Aabb::EMPTY`] when there are none
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points.into_iter().fold(Aabb::EMPTY, |b, p| Aabb { min: b.min.min(p), max: b.max.max(p) })
+    }
+
+    /// True for [`Aabb::EMPTY`] and other boxes with `min > max` on some axis
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    /// The eight corners, `min` first and `max` last
+    pub fn corners(&self) -> [Vec3; 8] {
+        std::array::from_fn(|i| {
+            Vec3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        })
+    }
+
+    /// Box around this one after transforming it by `m`
+    pub fn transformed(&self, m: Mat4) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        Aabb::from_points(self.corners().map(|c| m.transform_point3(c)))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        0.5 * (self.max - self.min)
+    }
+
+    pub fn overlaps(&self, o: &Aabb) -> bool {
+        self.min.cmple(o.max).all() && o.min.cmple(self.max).all()
+    }
+
+    pub fn contains(&self, p: Vec3) -> bool {
+        self.min.cmple(p).all() && p.cmple(self.max).all()
+    }
+
+    pub fn closest_point(&self, p: Vec3) -> Vec3 {
+        p.clamp(self.min, self.max)
+    }
+
+    pub fn distance_squared(&self, p: Vec3) -> f32 {
+        self.closest_point(p).distance_squared(p)
+    }
+
+    /// Entry and exit distances along a ray, if it crosses the box within `0..=max_t`
+    fn ray_span(&self, origin: Vec3, inv_dir: Vec3, max_t: f32) -> Option<(f32, f32)> {
+        let t0 = (self.min - origin) * inv_dir;
+        let t1 = (self.max - origin) * inv_dir;
+        let near = t0.min(t1).max_element().max(0.0);
+        let far = t0.max(t1).min_element().min(max_t);
+        (near <= far).then_some((near, far))
+    }
+}
+
+/// The six planes of a view frustum
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// Normal in `xyz` and offset in `w`, facing inward: `p` is inside when `dot(xyz, p) + w >= 0`
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Planes of a `proj * view` matrix with OpenGL clip depth, as [`crate::Camera::proj`] builds
+    pub fn from_view_proj(m: Mat4) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|i| m.row(i));
+        let planes = [w + x, w - x, w + y, w - y, w + z, w - z].map(|p| p / p.truncate().length());
+        Self { planes }
+    }
+
+    pub fn contains(&self, p: Vec3) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(p) + plane.w >= 0.0)
+    }
+
+    /// False when `aabb` lies wholly behind one of the planes. Boxes near the frustum's edges may
+    /// pass without touching it, which is fine for culling.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        !aabb.is_empty()
+            && self.planes.iter().all(|plane| {
+                let n = plane.truncate();
+                // the corner furthest along the normal
+                let corner = Vec3::select(n.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+                n.dot(corner) + plane.w >= 0.0
+            })
+    }
+}
+
+/// An oriented box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Obb {
+    pub center: Vec3,
+    pub half: Vec3,
+    pub rotation: Quat,
+}
+
+impl From<&BoxDef> for Obb {
+    fn from(b: &BoxDef) -> Self {
+        Self { center: Vec3::from(b.pos), half: Vec3::from(b.size), rotation: Quat::from_array(b.orientation()) }
+    }
+}
+
+impl From<&Trigger> for Obb {
+    fn from(t: &Trigger) -> Self {
+        Self { center: Vec3::from(t.pos), half: Vec3::from(t.size), rotation: Quat::IDENTITY }
+    }
+}
+
+impl Obb {
+    /// Local axes in world space
+    fn axes(&self) -> [Vec3; 3] {
+        [self.rotation * Vec3::X, self.rotation * Vec3::Y, self.rotation * Vec3::Z]
+    }
+
+    fn local_dir(&self, v: Vec3) -> Vec3 {
+        let [ax, ay, az] = self.axes();
+        Vec3::new(v.dot(ax), v.dot(ay), v.dot(az))
+    }
+
+    fn world_dir(&self, v: Vec3) -> Vec3 {
+        let [ax, ay, az] = self.axes();
+        ax * v.x + ay * v.y + az * v.z
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        let [ax, ay, az] = self.axes();
+        let ext = ax.abs() * self.half.x + ay.abs() * self.half.y + az.abs() * self.half.z;
+        Aabb::from_center(self.center, ext)
+    }
+
+    pub fn contains(&self, p: Vec3) -> bool {
+        self.local_dir(p - self.center).abs().cmple(self.half).all()
+    }
+
+    pub fn closest_point(&self, p: Vec3) -> Vec3 {
+        let local = self.local_dir(p - self.center).clamp(-self.half, self.half);
+        self.center + self.world_dir(local)
+    }
+
+    pub fn overlaps_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.closest_point(center).distance_squared(center) <= radius * radius
+    }
+
+    /// Separating axis test over both boxes' face normals and their pairwise edge cross products
+    pub fn overlaps_aabb(&self, b: &Aabb) -> bool {
+        let (c, h) = (b.center(), b.half_extents());
+        let d = c - self.center;
+        let axes = self.axes();
+        let separated = |axis: Vec3| {
+            let r_obb = self.half.x * axes[0].dot(axis).abs() + self.half.y * axes[1].dot(axis).abs() + self.half.z * axes[2].dot(axis).abs();
+            let r_aabb = h.dot(axis.abs());
+            d.dot(axis).abs() > r_obb + r_aabb
+        };
+        let faces = [Vec3::X, Vec3::Y, Vec3::Z].into_iter().chain(axes);
+        // parallel edges give a zero cross product, which the face axes already cover
+        let edges = [Vec3::X, Vec3::Y, Vec3::Z]
+            .into_iter()
+            .flat_map(|e| axes.map(|a| e.cross(a)))
+            .filter(|a| a.length_squared() > 1e-6);
+        !faces.chain(edges).any(separated)
+    }
+
+    /// Distance and outward world normal where the ray enters the box. A ray starting inside hits
+    /// at distance 0 with the normal facing back along the ray.
+    pub fn raycast(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<(f32, Vec3)> {
+        let o = self.local_dir(origin - self.center);
+        let d = self.local_dir(dir);
+        let local = Aabb { min: -self.half, max: self.half };
+        if local.contains(o) {
+            return Some((0.0, -dir.normalize_or_zero()));
+        }
+        let (t, _) = local.ray_span(o, d.recip(), max_t)?;
+        // the entry face is the one the hit point lies on, relative to the box size
+        let rel = (o + d * t) / self.half;
+        let axis = max_axis(rel.abs());
+        let mut n = Vec3::ZERO;
+        n[axis] = rel[axis].signum();
+        Some((t, self.world_dir(n)))
+    }
+}
+
+/// What a query result refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Volume {
+    /// Index into [`Level::world_boxes`](kengaai_scene_fps::Level::world_boxes), which matches
+    /// `level.boxes` for boxes outside groups
+    Box(usize),
+    /// Index into `triggers`
+    Trigger(usize),
+}
+
+/// Which volumes a query considers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryFilter {
+    pub boxes: bool,
+    pub triggers: bool,
+}
+
+impl QueryFilter {
+    pub const BOXES: QueryFilter = QueryFilter { boxes: true, triggers: false };
+    pub const TRIGGERS: QueryFilter = QueryFilter { boxes: false, triggers: true };
+    pub const ALL: QueryFilter = QueryFilter { boxes: true, triggers: true };
+
+    fn accepts(&self, v: Volume) -> bool {
+        match v {
+            Volume::Box(_) => self.boxes,
+            Volume::Trigger(_) => self.triggers,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub volume: Volume,
+    /// Distance along the ray, in units of the direction's length
+    pub t: f32,
+    pub point: Vec3,
+    /// Outward surface normal at the hit point
+    pub normal: Vec3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NearestPoint {
+    pub volume: Volume,
+    pub point: Vec3,
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    bounds: Aabb,
+    /// First child for inner nodes, first entry of `order` for leaves
+    start: u32,
+    /// Number of volumes in a leaf; 0 for inner nodes, whose children are `start` and `start + 1`
+    count: u32,
+}
+
+/// Spatial index over a scene's boxes and triggers
+#[derive(Debug, Clone, Default)]
+pub struct SceneBvh {
+    volumes: Vec<(Volume, Obb)>,
+    bounds: Vec<Aabb>,
+    order: Vec<u32>,
+    nodes: Vec<Node>,
+}
+
+impl SceneBvh {
+    pub fn new(scene: &FpsScene) -> Self {
+        let mut bvh = Self::default();
+        bvh.rebuild(scene);
+        bvh
+    }
+
+    /// Rebuild from scratch after the scene changed, reusing allocations
+    pub fn rebuild(&mut self, scene: &FpsScene) {
+        self.volumes.clear();
+        self.volumes.extend(scene.level.world_boxes().iter().enumerate().map(|(i, b)| (Volume::Box(i), Obb::from(b))));
+        self.volumes.extend(scene.triggers.iter().enumerate().map(|(i, t)| (Volume::Trigger(i), Obb::from(t))));
+        self.bounds.clear();
+        self.bounds.extend(self.volumes.iter().map(|(_, o)| o.aabb()));
+        self.order.clear();
+        self.order.extend(0..self.volumes.len() as u32);
+        self.nodes.clear();
+        if !self.volumes.is_empty() {
+            self.nodes.push(Node { bounds: Aabb::EMPTY, start: 0, count: 0 });
+            self.split(0, 0, self.volumes.len());
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.volumes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.volumes.is_empty()
+    }
+
+    /// Bounds of everything in the index
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |n| n.bounds)
+    }
+
+    pub fn obb(&self, volume: Volume) -> Option<&Obb> {
+        self.volumes.iter().find(|(v, _)| *v == volume).map(|(_, o)| o)
+    }
+
+    /// Median split along the longest axis of the centroid bounds
+    fn split(&mut self, node: usize, start: usize, end: usize) {
+        let items = &mut self.order[start..end];
+        let bounds = items.iter().fold(Aabb::EMPTY, |acc, &i| acc.union(&self.bounds[i as usize]));
+        self.nodes[node].bounds = bounds;
+        if items.len() <= LEAF_SIZE {
+            self.nodes[node].start = start as u32;
+            self.nodes[node].count = items.len() as u32;
+            return;
+        }
+        let centroids = items.iter().fold(Aabb::EMPTY, |acc, &i| {
+            let c = self.bounds[i as usize].center();
+            acc.union(&Aabb { min: c, max: c })
+        });
+        let axis = max_axis(centroids.max - centroids.min);
+        let mid = items.len() / 2;
+        let bounds_of = &self.bounds;
+        items.select_nth_unstable_by(mid, |&a, &b| {
+            bounds_of[a as usize].center()[axis].total_cmp(&bounds_of[b as usize].center()[axis])
+        });
+
+        let left = self.nodes.len();
+        self.nodes.push(Node { bounds: Aabb::EMPTY, start: 0, count: 0 });
+        self.nodes.push(Node { bounds: Aabb::EMPTY, start: 0, count: 0 });
+        self.nodes[node].start = left as u32;
+        self.split(left, start, start + mid);
+        self.split(left + 1, start + mid, end);
+    }
+
+    /// Visit leaves whose bounds pass `enter`, calling `visit` for each volume
+    fn walk(&self, mut enter: impl FnMut(&Aabb) -> bool, mut visit: impl FnMut(usize)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0usize];
+        while let Some(n) = stack.pop() {
+            let node = self.nodes[n];
+            if !enter(&node.bounds) {
+                continue;
+            }
+            if node.count > 0 {
+                let range = node.start as usize..(node.start + node.count) as usize;
+                self.order[range].iter().for_each(|&i| visit(i as usize));
+            } else {
+                stack.push(node.start as usize + 1);
+                stack.push(node.start as usize);
+            }
+        }
+    }
+
+    /// Closest hit along `origin + t * dir` for `t` in `0..=max_t`
+    pub fn raycast(&self, origin: Vec3, dir: Vec3, max_t: f32, filter: QueryFilter) -> Option<RayHit> {
+        if self.nodes.is_empty() || dir == Vec3::ZERO {
+            return None;
+        }
+        let inv = dir.recip();
+        let mut best: Option<RayHit> = None;
+        let mut stack = vec![0usize];
+        while let Some(n) = stack.pop() {
+            let node = self.nodes[n];
+            let limit = best.map_or(max_t, |h| h.t);
+            if node.bounds.ray_span(origin, inv, limit).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                for &i in &self.order[node.start as usize..(node.start + node.count) as usize] {
+                    let (volume, obb) = self.volumes[i as usize];
+                    if !filter.accepts(volume) {
+                        continue;
+                    }
+                    let limit = best.map_or(max_t, |h| h.t);
+                    if let Some((t, normal)) = obb.raycast(origin, dir, limit) {
+                        if best.is_none_or(|h| t < h.t || (t == h.t && volume < h.volume)) {
+                            best = Some(RayHit { volume, t, point: origin + dir * t, normal });
+                        }
+                    }
+                }
+            } else {
+                // visit the nearer child first so the farther one is more likely to be pruned
+                let (a, b) = (node.start as usize, node.start as usize + 1);
+                let dist = |c: usize| self.nodes[c].bounds.ray_span(origin, inv, limit).map_or(f32::INFINITY, |s| s.0);
+                let (near, far) = if dist(a) <= dist(b) { (a, b) } else { (b, a) };
+                stack.push(far);
+                stack.push(near);
+            }
+        }
+        best
+    }
+
+    /// Volumes touching a sphere, sorted
+    pub fn overlap_sphere(&self, center: Vec3, radius: f32, filter: QueryFilter) -> Vec<Volume> {
+        let mut out = Vec::new();
+        self.walk(
+            |b| b.distance_squared(center) <= radius * radius,
+            |i| {
+                let (volume, obb) = self.volumes[i];
+                if filter.accepts(volume) && obb.overlaps_sphere(center, radius) {
+                    out.push(volume);
+                }
+            },
+        );
+        out.sort_unstable();
+        out
+    }
+
+    /// Volumes touching an axis-aligned box, sorted
+    pub fn overlap_aabb(&self, aabb: &Aabb, filter: QueryFilter) -> Vec<Volume> {
+        let mut out = Vec::new();
+        self.walk(
+            |b| b.overlaps(aabb),
+            |i| {
+                let (volume, obb) = self.volumes[i];
+                if filter.accepts(volume) && obb.overlaps_aabb(aabb) {
+                    out.push(volume);
+                }
+            },
+        );
+        out.sort_unstable();
+        out
+    }
+
+    /// Indices of the triggers containing `p`, sorted
+    pub fn triggers_at(&self, p: Vec3) -> Vec<usize> {
+        self.overlap_sphere(p, 0.0, QueryFilter::TRIGGERS)
+            .into_iter()
+            .filter_map(|v| match v {
+                Volume::Trigger(i) => Some(i),
+                Volume::Box(_) => None,
+            })
+            .collect()
+    }
+
+    /// Closest point on any volume's surface or interior; `p` itself when inside one
+    pub fn nearest_point(&self, p: Vec3, filter: QueryFilter) -> Option<NearestPoint> {
+        let mut best: Option<NearestPoint> = None;
+        let best_d2 = Cell::new(f32::INFINITY);
+        self.walk(
+            |b| b.distance_squared(p) <= best_d2.get(),
+            |i| {
+                let (volume, obb) = self.volumes[i];
+                if !filter.accepts(volume) {
+                    return;
+                }
+                let q = obb.closest_point(p);
+                let d2 = q.distance_squared(p);
+                if d2 < best_d2.get() || (d2 == best_d2.get() && best.is_some_and(|b| volume < b.volume)) {
+                    best_d2.set(d2);
+                    best = Some(NearestPoint { volume, point: q, distance: d2.sqrt() });
+                }
+            },
+        );
+        best
+    }
+}
+
+/// Index of the largest component
+fn max_axis(v: Vec3) -> usize {
+    if v.x >= v.y && v.x >= v.z {
+        0
+    } else if v.y >= v.z {
+        1
+    } else {
+        2
+    }
+}
@@ -0,0 +1,22 @@
+//! Headless renderer setup shared by the GPU tests
+
+use kengaai_fps::{FpsRenderer, HeadlessOptions};
+use kengaai_scene_fps::FpsScene;
+use std::io::Write;
+
+/// Set on machines without any adapter to let the GPU tests pass without rendering
+pub const SKIP_VAR: &str = "KENGA_SKIP_GPU_TESTS";
+
+/// Headless renderer. Without an adapter, not even the software one, the test fails unless
+/// [`SKIP_VAR`] is set, in which case this logs the skip and returns `None`.
+pub fn headless(scene: &FpsScene, width: u32, height: u32) -> Option<FpsRenderer<'static>> {
+    match FpsRenderer::new_headless(scene, &HeadlessOptions { width, height, ..Default::default() }) {
+        Ok(r) => Some(r),
+        Err(e) if std::env::var_os(SKIP_VAR).is_some() => {
+            // straight to stderr, past the harness's capture, so the skip shows in the test log
+            let _ = writeln!(std::io::stderr(), "SKIPPED {}: {e}", std::thread::current().name().unwrap_or("GPU test"));
+            None
+        }
+        Err(e) => panic!("no headless renderer: {e}; set {SKIP_VAR}=1 to skip the GPU tests on this machine"),
+    }
+}
@@ -0,0 +1,269 @@
+use kengaai_scene_fps::{generate_level, load_scene, Bloom, FpsSceneBuilder, GeneratorParams, Material, PostProcess, Shading};
+use std::path::PathBuf;
+
+mod common;
+use common::headless;
+
+/// 8-bit sRGB encoding of a linear channel value
+fn srgb(c: f32) -> u8 {
+    let s = if c <= 0.0031308 { 12.92 * c } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    (s * 255.0).round() as u8
+}
+
+#[test]
+fn empty_scene_reads_back_clear_color() {
+    let scene = FpsSceneBuilder::new("empty").clear_color([0.2, 0.4, 0.6, 1.0]).build_unchecked();
+    let Some(mut r) = headless(&scene, 33, 17) else { return };
+    let img = r.render_to_image().unwrap();
+    // an odd width exercises the row padding of the readback
+    assert_eq!(img.dimensions(), (33, 17));
+    let expected = [srgb(0.2), srgb(0.4), srgb(0.6), 255];
+    for px in img.pixels() {
+        for (got, want) in px.0.iter().zip(expected) {
+            assert!(got.abs_diff(want) <= 1, "{:?} vs {expected:?}", px.0);
+        }
+    }
+}
+
+#[test]
+fn level_renders_geometry_and_survives_resize() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../assets/levels/kengaquest_level1.json");
+    let scene = load_scene(path).unwrap();
+    let Some(mut r) = headless(&scene, 160, 90) else { return };
+    let img = r.render_to_image().unwrap();
+    let corner = *img.get_pixel(0, 0);
+    assert!(img.pixels().any(|p| *p != corner), "frame is a single flat color");
+
+    r.resize(winit::dpi::PhysicalSize::new(64, 48));
+    r.update_camera();
+    assert_eq!(r.render_to_image().unwrap().dimensions(), (64, 48));
+}
+
+#[test]
+fn culling_leaves_out_boxes_behind_the_camera_without_changing_the_frame() {
+    let scene = generate_level(5, &GeneratorParams::default());
+    let total = scene.level.world_boxes().len() as u32;
+    let Some(mut r) = headless(&scene, 96, 64) else { return };
+    let culled = r.render_to_image().unwrap();
+    let stats = *r.stats();
+    assert_eq!(stats.drawn_instances + stats.culled_instances, total);
+    assert!(stats.drawn_instances > 0 && stats.culled_instances > 0, "{stats:?}");
+    // at least one call for the batches in view
+    assert!(stats.draw_calls > 0, "{stats:?}");
+
+    r.set_culling(false);
+    let full = r.render_to_image().unwrap();
+    assert_eq!((r.stats().drawn_instances, r.stats().culled_instances), (total, 0));
+    assert!(culled == full, "culling changed the frame");
+
+    // facing the other way shows a different part of the level
+    r.set_culling(true);
+    r.camera.yaw += std::f32::consts::PI;
+    r.update_camera();
+    r.render().unwrap();
+    assert_eq!(r.stats().drawn_instances + r.stats().culled_instances, total);
+    assert_ne!(r.stats().drawn_instances, stats.drawn_instances);
+}
+
+#[test]
+fn textures_apply_only_to_boxes_that_name_them() {
+    // looking down +X, so +Z is on the right of the frame
+    let scene = FpsSceneBuilder::new("textures")
+        .spawn([0.0, 0.0, 0.0], 0.0)
+        .box_at([5.0, 0.0, -2.0], [0.5, 3.0, 2.0])
+        .texture("red.png")
+        .box_at([5.0, 0.0, 2.0], [0.5, 3.0, 2.0])
+        .directional_light([1.0, 0.0, 0.0], [1.0, 1.0, 1.0], 1.0)
+        .build_unchecked();
+    let Some(mut r) = headless(&scene, 64, 64) else { return };
+    r.load_texture("red.png".into(), 1, 1, &[255, 0, 0, 255]).unwrap();
+    r.load_texture("unused.png".into(), 1, 1, &[0, 255, 0, 255]).unwrap();
+
+    let img = r.render_to_image().unwrap();
+    let [lr, lg, lb, _] = img.get_pixel(16, 32).0;
+    let [rr, rg, rb, _] = img.get_pixel(48, 32).0;
+    assert!(lr > 100 && lg < 20 && lb < 20, "textured box is {:?}", [lr, lg, lb]);
+    assert!(rr > 100 && rr.abs_diff(rg) <= 2 && rg.abs_diff(rb) <= 2, "untextured box is {:?}", [rr, rg, rb]);
+}
+
+#[test]
+fn pbr_shading_changes_only_boxes_with_materials() {
+    let scene = FpsSceneBuilder::new("pbr")
+        .spawn([0.0, 0.0, 0.0], 0.0)
+        .shading(Shading::Pbr)
+        .box_at([5.0, 0.0, -2.0], [0.5, 3.0, 2.0])
+        .material(Material { color: Some([1.0, 0.0, 0.0]), texture: None, metallic: 0.0, roughness: 0.8 })
+        .box_at([5.0, 0.0, 2.0], [0.5, 3.0, 2.0])
+        .directional_light([1.0, 0.0, 0.0], [1.0, 1.0, 1.0], 2.0)
+        .build_unchecked();
+    let Some(mut r) = headless(&scene, 64, 64) else { return };
+    assert_eq!(r.shading(), Shading::Pbr);
+    let pbr = r.render_to_image().unwrap();
+    r.set_shading(Shading::Simple);
+    let simple = r.render_to_image().unwrap();
+
+    let [pr, pg, pb, _] = pbr.get_pixel(16, 32).0;
+    // the dielectric specular adds a little white on top of the red albedo
+    assert!(pr > 100 && pg < 60 && pb < 60, "PBR box is {:?}", [pr, pg, pb]);
+    assert_ne!(pbr.get_pixel(16, 32), simple.get_pixel(16, 32));
+    // the box without a material stays on the simple path either way
+    assert_eq!(pbr.get_pixel(48, 32), simple.get_pixel(48, 32));
+}
+
+#[test]
+fn materials_without_a_color_leave_their_texture_untinted() {
+    let material = Material { color: None, texture: Some("red.png".into()), metallic: 0.0, roughness: 0.8 };
+    let scene = FpsSceneBuilder::new("texture only")
+        .spawn([0.0, 0.0, 0.0], 0.0)
+        .box_at([5.0, 0.0, 0.0], [0.5, 3.0, 3.0])
+        .material(material)
+        .directional_light([1.0, 0.0, 0.0], [1.0, 1.0, 1.0], 2.0)
+        .build_unchecked();
+    let Some(mut r) = headless(&scene, 64, 64) else { return };
+    r.load_texture("red.png".into(), 1, 1, &[255, 0, 0, 255]).unwrap();
+    for shading in [Shading::Simple, Shading::Pbr] {
+        r.set_shading(shading);
+        let [cr, cg, cb, _] = r.render_to_image().unwrap().get_pixel(32, 32).0;
+        assert!(cr > 100 && cg < 60 && cb < 60, "{shading:?} box is {:?}", [cr, cg, cb]);
+    }
+}
+
+#[test]
+fn occluders_shadow_the_floor_for_lights_that_opt_in() {
+    // looking down at the floor under a slab that hangs between it and the light
+    let lit = |directional: bool, shadows: bool| {
+        let mut b = FpsSceneBuilder::new("shadows").spawn([0.0, 3.0, 0.0], 0.0).floor(20.0, 20.0).box_at([6.0, 2.0, 0.0], [1.5, 0.2, 1.5]);
+        b = if directional {
+            b.directional_light([0.0, -1.0, 0.0], [1.0, 1.0, 1.0], 1.0)
+        } else {
+            b.point_light([6.0, 4.0, 0.0], [1.0, 1.0, 1.0], 8.0)
+        };
+        if shadows {
+            b = b.shadows(512);
+        }
+        let mut scene = b.build_unchecked();
+        scene.player.pitch = -0.5;
+        let mut r = headless(&scene, 64, 64)?;
+        let img = r.render_to_image().unwrap();
+        // red of the floor under the slab, and of floor well to its side
+        Some((img.get_pixel(32, 32).0[0], img.get_pixel(2, 32).0[0]))
+    };
+    for directional in [true, false] {
+        let (Some(open), Some(shadowed)) = (lit(directional, false), lit(directional, true)) else { return };
+        assert!(shadowed.0 + 40 < open.0, "directional: {directional}, shadowed {shadowed:?} vs unshadowed {open:?}");
+        // no acne on floor the slab doesn't cover
+        assert!(shadowed.1.abs_diff(open.1) <= 4, "directional: {directional}, shadowed {shadowed:?} vs unshadowed {open:?}");
+    }
+}
+
+#[test]
+fn post_process_chain_applies_bloom_exposure_and_vignette() {
+    // a small, very bright box on a black background, straight ahead
+    let scene = |post: Option<PostProcess>| {
+        let mut b = FpsSceneBuilder::new("post")
+            .spawn([0.0, 0.0, 0.0], 0.0)
+            .clear_color([0.0, 0.0, 0.0, 1.0])
+            .box_at([6.0, 0.0, 0.0], [0.2, 0.5, 0.5])
+            .color([1.0, 1.0, 1.0])
+            .directional_light([1.0, 0.0, 0.0], [1.0, 1.0, 1.0], 8.0);
+        if let Some(post) = post {
+            b = b.post_process(post);
+        }
+        b.build_unchecked()
+    };
+    let bloom = Some(Bloom { threshold: Some(1.0), intensity: Some(1.0), radius: Some(2.0) });
+    let Some(mut r) = headless(&scene(Some(PostProcess { bloom, ..Default::default() })), 64, 64) else { return };
+    let glow = r.render_to_image().unwrap();
+    let mut plain = headless(&scene(Some(PostProcess::default())), 64, 64).unwrap();
+    let flat = plain.render_to_image().unwrap();
+
+    // the box saturates either way; only bloom spills light onto the background beside it
+    assert!(glow.get_pixel(32, 32).0[0] > 240 && flat.get_pixel(32, 32).0[0] > 240);
+    let (halo, bare) = (glow.get_pixel(32, 22).0[0], flat.get_pixel(32, 22).0[0]);
+    assert!(bare == 0 && halo > 20, "halo {halo}, without bloom {bare}");
+    assert_eq!(glow.get_pixel(0, 0).0[0], 0);
+
+    // exposure scales the HDR color before tonemapping
+    plain.set_post_process(&PostProcess { exposure: Some(0.01), ..Default::default() }).unwrap();
+    let dim = plain.render_to_image().unwrap();
+    assert!(dim.get_pixel(32, 32).0[0] < 200, "{:?}", dim.get_pixel(32, 32));
+
+    // vignette darkens the corners of an evenly lit frame but not its center
+    let grey = |vignette| {
+        let post = PostProcess { vignette, ..Default::default() };
+        let scene = FpsSceneBuilder::new("grey").clear_color([0.5, 0.5, 0.5, 1.0]).post_process(post).build_unchecked();
+        let img = headless(&scene, 64, 64).unwrap().render_to_image().unwrap();
+        (img.get_pixel(32, 32).0[0], img.get_pixel(0, 0).0[0])
+    };
+    let ((center, corner), (vcenter, vcorner)) = (grey(0.0), grey(1.0));
+    assert_eq!(center, corner);
+    assert!(vcenter.abs_diff(center) <= 2 && vcorner + 100 < corner, "{:?}", [center, corner, vcenter, vcorner]);
+
+    // the chain is fixed when the renderer is created
+    let mut direct = headless(&scene(None), 64, 64).unwrap();
+    assert!(direct.post_process().is_none());
+    assert!(direct.set_post_process(&PostProcess::default()).is_err());
+}
+
+/// Looking straight down at a floor from 10 units up: +Z is to the right of the frame and +X up it
+fn from_above(b: FpsSceneBuilder) -> kengaai_scene_fps::FpsScene {
+    let mut scene = b.spawn([0.0, 10.0, 0.0], 0.0).floor(20.0, 20.0).build_unchecked();
+    scene.player.pitch = -1.55;
+    scene
+}
+
+#[test]
+fn every_light_reaches_its_own_patch_and_stops_at_its_range() {
+    // a 6×6 grid of short-range point lights well apart, far more than the old limit of 16
+    let mut b = FpsSceneBuilder::new("many");
+    for i in 0..36 {
+        let (x, z) = ((i / 6) as f32 * 2.4 - 6.0, (i % 6) as f32 * 2.4 - 6.0);
+        b = b.point_light([x, 0.5, z], [1.0, 0.8, 0.6], 3.0).range(1.1);
+    }
+    let scene = from_above(b);
+    let Some(mut r) = headless(&scene, 96, 96) else { return };
+    let all = r.render_to_image().unwrap();
+    let dark = *all.get_pixel(0, 0);
+
+    // no two lights reach the same floor, so the frame is every light's own frame laid together
+    for (i, light) in scene.lights.iter().enumerate() {
+        r.update_lights(std::slice::from_ref(light));
+        let alone = r.render_to_image().unwrap();
+        let mut lit = 0;
+        for (x, y, px) in alone.enumerate_pixels() {
+            if px.0[0] > dark.0[0] + 8 {
+                lit += 1;
+                let got = all.get_pixel(x, y).0;
+                assert!(got.iter().zip(px.0).all(|(a, b)| a.abs_diff(b) <= 2), "light {i} at ({x}, {y}): {got:?} vs {:?}", px.0);
+            }
+        }
+        // a whole disc of about 125 pixels: culling that is too tight would cut it along cluster edges
+        assert!((110..150).contains(&lit), "light {i} lights {lit} pixels");
+    }
+    // and nothing past the ranges is lit at all
+    let far = [scene.lights[0].clone()];
+    r.update_lights(&far);
+    let lone = r.render_to_image().unwrap();
+    assert_eq!(lone.get_pixel(95, 0), &dark);
+    assert_eq!(lone.get_pixel(48, 48), &dark);
+}
+
+#[test]
+fn spot_lights_light_only_inside_their_cone() {
+    let lit = |b: FpsSceneBuilder| {
+        let mut r = headless(&from_above(b), 64, 64)?;
+        let img = r.render_to_image().unwrap();
+        // floor under the light, and floor about three units to either side of it
+        Some([img.get_pixel(32, 32).0[0], img.get_pixel(46, 32).0[0], img.get_pixel(18, 32).0[0]])
+    };
+    let b = || FpsSceneBuilder::new("spot");
+    let Some(point) = lit(b().point_light([0.2, 4.0, 0.0], [1.0; 3], 6.0)) else { return };
+    let down = lit(b().spot_light([0.2, 4.0, 0.0], [0.0, -1.0, 0.0], [1.0; 3], 6.0).cone(0.2, 0.3)).unwrap();
+    let tilted = lit(b().spot_light([0.2, 4.0, 0.0], [0.0, -4.0, 3.0], [1.0; 3], 6.0).cone(0.2, 0.3)).unwrap();
+
+    assert!(point[1] > 60 && point[2] > 60, "point light: {point:?}");
+    // straight down, the cone covers the floor under the light and nothing three units away
+    assert!(down[0].abs_diff(point[0]) <= 2 && down[1] + 40 < point[1] && down[2] + 40 < point[2], "{down:?} vs {point:?}");
+    // tilted toward +Z, it lights the right of the frame instead
+    assert!(tilted[1] > 60 && tilted[0] + 40 < tilted[1] && tilted[2] + 40 < point[2], "{tilted:?}");
+}
@@ -0,0 +1,133 @@
+use kengaai_fps::{BoxHandle, FpsRenderer};
+use kengaai_scene_fps::{BoxDef, FpsScene, FpsSceneBuilder, Material, Rotation, Shading};
+
+mod common;
+
+fn renderer(scene: &FpsScene) -> Option<FpsRenderer<'static>> {
+    let mut r = common::headless(scene, 96, 96)?;
+    r.load_texture("a.png".into(), 1, 1, &[255, 128, 0, 255]).unwrap();
+    r.load_texture("b.png".into(), 1, 1, &[0, 128, 255, 255]).unwrap();
+    Some(r)
+}
+
+/// Looking down +X at a wall of cells, lit from the front and above
+fn wall(boxes: Vec<BoxDef>) -> FpsScene {
+    let mut scene = FpsSceneBuilder::new("instances")
+        .spawn([0.0, 0.0, 0.0], 0.0)
+        .shading(Shading::Pbr)
+        .box_at([11.0, 0.0, 0.0], [0.2, 5.0, 5.0])
+        .directional_light([1.0, -0.5, 0.3], [1.0, 1.0, 1.0], 1.5)
+        .shadows(256)
+        .build_unchecked();
+    scene.level.boxes.extend(boxes);
+    scene
+}
+
+/// Box in cell `cell` of a 12×12 grid; `kind` picks plain, textured, material and textured material
+fn cell_box(cell: usize, kind: u32, color: [f32; 3]) -> BoxDef {
+    let (row, col) = (cell / 12, cell % 12);
+    let material = |texture: Option<&str>| Material { color: Some(color), texture: texture.map(Into::into), metallic: 0.2, roughness: 0.6 };
+    BoxDef {
+        pos: [10.0, row as f32 * 0.7 - 3.85, col as f32 * 0.7 - 3.85],
+        size: [0.3, 0.3, 0.3],
+        rot_y: 0.0,
+        rotation: None,
+        color,
+        texture: (kind == 1).then(|| "a.png".into()),
+        material: match kind {
+            2 => Some(material(None)),
+            3 => Some(material(Some("b.png"))),
+            _ => None,
+        },
+    }
+}
+
+#[test]
+fn handles_name_scene_boxes_in_order_and_go_stale_on_removal() {
+    let scene = wall(vec![cell_box(0, 0, [1.0, 0.0, 0.0]), cell_box(1, 2, [0.0, 1.0, 0.0])]);
+    let Some(mut r) = renderer(&scene) else { return };
+    let handles: Vec<BoxHandle> = r.boxes().map(|(h, _)| h).collect();
+    assert_eq!(handles.len(), 3);
+    assert_eq!(r.box_def(handles[1]).unwrap().pos, scene.level.boxes[1].pos);
+
+    r.set_color(handles[2], [0.0, 0.0, 1.0]).unwrap();
+    assert_eq!(r.box_def(handles[2]).unwrap().material.as_ref().unwrap().color, Some([0.0, 0.0, 1.0]));
+    r.set_color(handles[1], [1.0, 1.0, 0.0]).unwrap();
+    assert_eq!(r.box_def(handles[1]).unwrap().color, [1.0, 1.0, 0.0]);
+    r.set_transform(handles[1], [9.0, 1.0, 1.0], Rotation::Euler([0.0, 0.5, 0.0]), [0.5, 0.2, 0.2]).unwrap();
+    let moved = r.box_def(handles[1]).unwrap();
+    assert_eq!((moved.pos, moved.size, moved.rotation), ([9.0, 1.0, 1.0], [0.5, 0.2, 0.2], Some(Rotation::Euler([0.0, 0.5, 0.0]))));
+
+    let removed = r.remove_box(handles[1]).unwrap();
+    assert_eq!(removed.pos, [9.0, 1.0, 1.0]);
+    assert!(r.remove_box(handles[1]).is_none());
+    assert!(r.box_def(handles[1]).is_none());
+    assert!(r.set_color(handles[1], [0.0; 3]).is_err());
+    assert!(r.set_transform(handles[1], [0.0; 3], Rotation::Euler([0.0; 3]), [1.0; 3]).is_err());
+
+    // the freed slot is reused without reviving the old handle
+    let added = r.add_box(cell_box(5, 1, [1.0; 3]));
+    assert_ne!(added, handles[1]);
+    assert!(r.box_def(handles[1]).is_none());
+    assert_eq!(r.boxes().count(), 3);
+    r.render().unwrap();
+}
+
+#[test]
+fn edited_renderer_draws_like_one_built_from_its_boxes() {
+    let mut seed = 0x2545_f491_u32;
+    let mut next = move |n: u32| {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed % n
+    };
+    let mut free: Vec<usize> = (8..144).collect();
+    let start = (0..8).map(|cell| cell_box(cell, cell as u32 % 4, [0.8, 0.8, 0.8])).collect();
+    let Some(mut r) = renderer(&wall(start)) else { return };
+    let mut live: Vec<(BoxHandle, usize)> = r.boxes().skip(1).map(|(h, _)| h).zip(0..8).collect();
+
+    for step in 0..300 {
+        let color = [next(11) as f32 / 10.0, next(11) as f32 / 10.0, next(11) as f32 / 10.0];
+        match next(10) {
+            // adds outweigh removals, so the instance buffer has to grow along the way
+            0..=5 if !free.is_empty() => {
+                let cell = free.swap_remove(next(free.len() as u32) as usize);
+                live.push((r.add_box(cell_box(cell, next(4), color)), cell));
+            }
+            6 | 7 if !live.is_empty() => {
+                let (handle, cell) = live.swap_remove(next(live.len() as u32) as usize);
+                assert!(r.remove_box(handle).is_some());
+                free.push(cell);
+            }
+            8 if !live.is_empty() => r.set_color(live[next(live.len() as u32) as usize].0, color).unwrap(),
+            _ if !live.is_empty() && !free.is_empty() => {
+                let i = next(live.len() as u32) as usize;
+                let cell = free.swap_remove(next(free.len() as u32) as usize);
+                free.push(std::mem::replace(&mut live[i].1, cell));
+                let pos = cell_box(cell, 0, color).pos;
+                r.set_transform(live[i].0, pos, Rotation::Euler([0.0, next(8) as f32 * 0.1, 0.0]), [0.3, 0.25, 0.2]).unwrap();
+            }
+            _ => {}
+        }
+        // uploads happen per frame, so some frames see several edits and some see one
+        if step % 37 == 0 {
+            r.render().unwrap();
+        }
+    }
+    assert!(live.len() > 64, "only {} boxes", live.len());
+    // frames whose only change is a single instance
+    r.render().unwrap();
+    r.set_color(live[0].0, [1.0, 0.0, 1.0]).unwrap();
+    r.render().unwrap();
+    let pos = cell_box(free[0], 0, [0.0; 3]).pos;
+    r.set_transform(live[1].0, pos, Rotation::Euler([0.0, 0.3, 0.0]), [0.3, 0.3, 0.1]).unwrap();
+
+    let edited = r.render_to_image().unwrap();
+    let Some(mut fresh) = renderer(&wall(r.boxes().skip(1).map(|(_, def)| def.clone()).collect())) else { return };
+    let rebuilt = fresh.render_to_image().unwrap();
+    for (x, y, px) in edited.enumerate_pixels() {
+        let want = rebuilt.get_pixel(x, y);
+        assert!(px.0.iter().zip(want.0).all(|(a, b)| a.abs_diff(b) <= 1), "({x}, {y}): {:?} vs {:?}", px.0, want.0);
+    }
+}
@@ -0,0 +1,105 @@
+use kengaai_fps::load_mesh;
+use kengaai_scene_fps::{FpsSceneBuilder, Material, MeshDef};
+use std::path::PathBuf;
+
+mod common;
+
+/// Fresh directory under the system temp dir for one test's files
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("kengaai-mesh-{}-{name}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Unit quad in the YZ plane facing -X, with a yellow MTL material
+fn write_quad_obj(dir: &std::path::Path) -> PathBuf {
+    std::fs::write(dir.join("quad.mtl"), "newmtl yellow\nKd 1 1 0\n").unwrap();
+    let obj = "mtllib quad.mtl\no quad\n\
+        v 0 -1 -1\nv 0 -1 1\nv 0 1 1\nv 0 1 -1\n\
+        vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn -1 0 0\n\
+        usemtl yellow\nf 1/1/1 2/2/1 3/3/1 4/4/1\n";
+    let path = dir.join("quad.obj");
+    std::fs::write(&path, obj).unwrap();
+    path
+}
+
+#[test]
+fn obj_loads_triangulated_with_mtl_color() {
+    let dir = scratch("obj");
+    let mesh = load_mesh(write_quad_obj(&dir)).unwrap();
+    assert_eq!(mesh.primitives.len(), 1);
+    assert_eq!(mesh.vertex_count(), 4);
+    assert_eq!(mesh.triangle_count(), 2);
+    let p = &mesh.primitives[0];
+    assert_eq!(p.base_color, [1.0, 1.0, 0.0, 1.0]);
+    assert!(p.normals.iter().all(|n| *n == [-1.0, 0.0, 0.0]));
+    // V is flipped into wgpu's top-left texture origin
+    assert_eq!(p.uvs[0], [0.0, 1.0]);
+    assert!(p.texture.is_none());
+}
+
+#[test]
+fn gltf_applies_node_transforms_and_pbr_factors() {
+    let dir = scratch("gltf");
+    let mut bin = Vec::new();
+    for v in [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+        bin.extend(v.iter().flat_map(|f| f.to_le_bytes()));
+    }
+    bin.extend([0u32, 1, 2].iter().flat_map(|i| i.to_le_bytes()));
+    std::fs::write(dir.join("tri.bin"), &bin).unwrap();
+    let gltf = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0, "translation": [0, 0, 5] }],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 }] }],
+        "materials": [{ "pbrMetallicRoughness": { "baseColorFactor": [0, 1, 0, 1], "metallicFactor": 0.25, "roughnessFactor": 0.5 } }],
+        "buffers": [{ "uri": "tri.bin", "byteLength": 48 }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 12 }
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+            { "bufferView": 1, "componentType": 5125, "count": 3, "type": "SCALAR" }
+        ]
+    }"#;
+    let path = dir.join("tri.gltf");
+    std::fs::write(&path, gltf).unwrap();
+
+    let mesh = load_mesh(&path).unwrap();
+    assert_eq!(mesh.triangle_count(), 1);
+    let p = &mesh.primitives[0];
+    assert_eq!(p.positions[1], [1.0, 0.0, 5.0]);
+    // missing normals are generated from the winding
+    assert_eq!(p.normals[0], [0.0, 0.0, 1.0]);
+    assert_eq!(p.base_color, [0.0, 1.0, 0.0, 1.0]);
+    assert_eq!((p.metallic, p.roughness), (0.25, 0.5));
+
+    assert!(load_mesh(dir.join("tri.fbx")).is_err());
+}
+
+#[test]
+fn meshes_render_with_file_and_scene_materials() {
+    let dir = scratch("render");
+    let obj = write_quad_obj(&dir);
+    // looking down +X, so +Z is on the right of the frame
+    let mut scene = FpsSceneBuilder::new("meshes")
+        .spawn([0.0, 0.0, 0.0], 0.0)
+        .directional_light([1.0, 0.0, 0.0], [1.0, 1.0, 1.0], 1.0)
+        .build_unchecked();
+    let quad = |z: f32, material| MeshDef { pos: [5.0, 0.0, z], scale: [1.0, 3.0, 2.0], rot_y: 0.0, rotation: None, file: "quad.obj".into(), material };
+    scene.level.meshes.push(quad(-2.0, None));
+    scene.level.meshes.push(quad(2.0, Some(Material { color: Some([0.0, 1.0, 0.0]), texture: None, metallic: 0.0, roughness: 1.0 })));
+
+    let Some(mut r) = common::headless(&scene, 64, 64) else { return };
+    let before = r.render_to_image().unwrap();
+    assert!(before.pixels().all(|p| *p == *before.get_pixel(0, 0)), "nothing is drawn before the file loads");
+
+    r.load_mesh_file("quad.obj".into(), &obj).unwrap();
+    let img = r.render_to_image().unwrap();
+    let [lr, lg, lb, _] = img.get_pixel(16, 32).0;
+    let [rr, rg, rb, _] = img.get_pixel(48, 32).0;
+    assert!(lr > 100 && lg > 100 && lb < 20, "file material gives {:?}", [lr, lg, lb]);
+    assert!(rr < 20 && rg > 100 && rb < 20, "scene material gives {:?}", [rr, rg, rb]);
+}
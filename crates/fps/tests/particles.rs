@@ -0,0 +1,91 @@
+use glam::Vec3;
+use kengaai_fps::{Burst, ParticleSim, MAX_PARTICLES};
+use kengaai_scene_fps::{FpsSceneBuilder, ParticleSystem};
+
+mod common;
+
+fn fountain(count: u32, lifetime: f32) -> ParticleSystem {
+    ParticleSystem { position: [0.0, 1.0, 0.0], color: [1.0, 0.5, 0.0], count, lifetime, speed: 2.0, spread: 0.3 }
+}
+
+#[test]
+fn same_seed_and_steps_give_the_same_particles() {
+    let run = |seed| {
+        let mut sim = ParticleSim::new(&[fountain(50, 1.0)], seed);
+        for frame in 0..40 {
+            sim.step(1.0 / 60.0);
+            if frame == 10 {
+                sim.burst(&Burst { position: Vec3::new(3.0, 0.0, 0.0), count: 20, ..Default::default() });
+            }
+        }
+        sim.particles().to_vec()
+    };
+    assert_eq!(run(7), run(7));
+    assert_ne!(run(7), run(8));
+}
+
+#[test]
+fn emitters_fill_up_to_count_and_particles_expire() {
+    let mut sim = ParticleSim::new(&[fountain(30, 0.5)], 1);
+    assert!(sim.is_empty());
+    // half a lifetime in, half the particles are out
+    for _ in 0..15 {
+        sim.step(1.0 / 60.0);
+    }
+    assert!((14..=16).contains(&sim.len()), "{}", sim.len());
+    for _ in 0..120 {
+        sim.step(1.0 / 60.0);
+        assert!(sim.len() <= 30);
+    }
+    assert!(sim.len() >= 28, "{}", sim.len());
+    assert!(sim.particles().iter().all(|p| p.age < p.lifetime && p.emitter == Some(0)));
+    // particles rise inside the cone at the emitter's speed
+    for p in sim.particles() {
+        assert!((p.vel.length() - 2.0).abs() < 1e-4);
+        assert!(p.vel.angle_between(Vec3::Y) <= 0.3 + 1e-4);
+    }
+}
+
+#[test]
+fn bursts_spray_in_their_cone_and_are_capped() {
+    let mut sim = ParticleSim::new(&[], 3);
+    let muzzle = Burst { position: Vec3::ZERO, direction: Vec3::new(0.0, 0.0, -4.0), count: 100, lifetime: 0.2, speed: 5.0, spread: 0.1, ..Default::default() };
+    assert_eq!(sim.burst(&muzzle), 100);
+    assert!(sim.particles().iter().all(|p| p.emitter.is_none() && p.vel.angle_between(Vec3::NEG_Z) <= 0.1 + 1e-4));
+    sim.step(0.1);
+    assert!(sim.particles().iter().all(|p| (p.pos.z + 0.5).abs() < 0.01));
+    sim.step(0.1);
+    assert!(sim.is_empty());
+
+    let huge = Burst { count: u32::MAX, ..Default::default() };
+    assert_eq!(sim.burst(&huge), MAX_PARTICLES);
+    assert_eq!(sim.burst(&huge), 0);
+}
+
+#[test]
+fn particles_draw_additively_in_front_of_the_scene() {
+    // looking down +X at a dark wall, with a burst between the wall and the camera
+    let scene = FpsSceneBuilder::new("particles")
+        .spawn([0.0, 0.0, 0.0], 0.0)
+        .clear_color([0.0, 0.0, 0.0, 1.0])
+        .box_at([6.0, 0.0, 0.0], [0.5, 4.0, 4.0])
+        .color([0.2, 0.2, 0.2])
+        .directional_light([1.0, 0.0, 0.0], [1.0, 1.0, 1.0], 1.0)
+        .build_unchecked();
+    let Some(mut r) = common::headless(&scene, 64, 64) else { return };
+    let before = r.render_to_image().unwrap();
+
+    let burst = Burst { position: Vec3::new(3.0, 0.0, 0.0), color: [0.0, 0.0, 1.0], count: 8, speed: 0.0, size: 0.3, lifetime: 1.0, ..Default::default() };
+    r.particles_mut().burst(&burst);
+    let after = r.render_to_image().unwrap();
+    let [br, bg, bb, _] = before.get_pixel(32, 32).0;
+    let [ar, ag, ab, _] = after.get_pixel(32, 32).0;
+    // blue is added on top of the wall; red and green stay as they were
+    assert!(ab > bb + 100, "{:?} -> {:?}", [br, bg, bb], [ar, ag, ab]);
+    assert_eq!((ar, ag), (br, bg));
+    assert_eq!(after.get_pixel(2, 2), before.get_pixel(2, 2));
+
+    // expired particles leave the frame as it was
+    r.particles_mut().step(1.0);
+    assert_eq!(r.render_to_image().unwrap(), before);
+}
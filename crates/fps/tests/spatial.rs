@@ -0,0 +1,183 @@
+use glam::{Quat, Vec3};
+use kengaai_fps::{Aabb, Camera, Frustum, Obb, QueryFilter, SceneBvh, Volume};
+use kengaai_scene_fps::{generate_level, BoxDef, FpsScene, FpsSceneBuilder, GeneratorParams, Group, Rotation};
+
+/// Small xorshift so the tests don't need a rand dependency
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn range(&mut self, lo: f32, hi: f32) -> f32 {
+        lo + (hi - lo) * self.next()
+    }
+
+    fn vec(&mut self, lo: f32, hi: f32) -> Vec3 {
+        Vec3::new(self.range(lo, hi), self.range(lo, hi), self.range(lo, hi))
+    }
+}
+
+fn random_scene(seed: u64) -> FpsScene {
+    let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1);
+    let mut b = FpsSceneBuilder::new("bvh");
+    for _ in 0..60 {
+        let pos = rng.vec(-30.0, 30.0).to_array();
+        let half = rng.vec(0.2, 3.0).to_array();
+        b = b.box_at(pos, half).rot_y(rng.range(-3.2, 3.2));
+        if rng.next() < 0.3 {
+            b = b.rotation(Rotation::Euler(rng.vec(-3.2, 3.2).to_array()));
+        }
+    }
+    for _ in 0..10 {
+        b = b.trigger(rng.vec(-30.0, 30.0).to_array(), rng.vec(0.5, 2.0).to_array(), "noop");
+    }
+    b.build_unchecked()
+}
+
+fn volumes(scene: &FpsScene) -> Vec<(Volume, Obb)> {
+    let boxes = scene.level.world_boxes().into_iter().enumerate().map(|(i, b)| (Volume::Box(i), Obb::from(&b)));
+    boxes.chain(scene.triggers.iter().enumerate().map(|(i, t)| (Volume::Trigger(i), Obb::from(t)))).collect()
+}
+
+#[test]
+fn raycast_reports_face_normal_of_rotated_box() {
+    let scene = FpsSceneBuilder::new("ray").box_at([0.0, 0.0, 0.0], [1.0, 1.0, 2.0]).rot_y(std::f32::consts::FRAC_PI_2).build_unchecked();
+    let bvh = SceneBvh::new(&scene);
+    // rotated a quarter turn, the long local Z axis lies along world X
+    let hit = bvh.raycast(Vec3::new(-10.0, 0.0, 0.0), Vec3::X, 100.0, QueryFilter::ALL).expect("hit");
+    assert_eq!(hit.volume, Volume::Box(0));
+    assert!((hit.t - 8.0).abs() < 1e-4, "t = {}", hit.t);
+    assert!(hit.normal.distance(-Vec3::X) < 1e-4, "normal = {}", hit.normal);
+    assert!(bvh.raycast(Vec3::new(-10.0, 0.0, 0.0), Vec3::X, 5.0, QueryFilter::ALL).is_none());
+    assert!(bvh.raycast(Vec3::new(-10.0, 0.0, 0.0), Vec3::X, 100.0, QueryFilter::TRIGGERS).is_none());
+}
+
+#[test]
+fn queries_match_brute_force() {
+    for seed in 0..8 {
+        let scene = random_scene(seed);
+        let bvh = SceneBvh::new(&scene);
+        let all = volumes(&scene);
+        assert_eq!(bvh.len(), all.len());
+        let mut rng = Rng(seed + 1000);
+
+        for _ in 0..200 {
+            let origin = rng.vec(-40.0, 40.0);
+            let dir = rng.vec(-1.0, 1.0).try_normalize().unwrap_or(Vec3::X);
+            let expected = all
+                .iter()
+                .filter_map(|(v, o)| o.raycast(origin, dir, 80.0).map(|(t, _)| (t, *v)))
+                .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            let got = bvh.raycast(origin, dir, 80.0, QueryFilter::ALL).map(|h| (h.t, h.volume));
+            assert_eq!(got, expected, "seed {seed}: ray from {origin} along {dir}");
+
+            let center = rng.vec(-35.0, 35.0);
+            let radius = rng.range(0.0, 6.0);
+            let expected: Vec<Volume> = all.iter().filter(|(_, o)| o.overlaps_sphere(center, radius)).map(|(v, _)| *v).collect();
+            assert_eq!(bvh.overlap_sphere(center, radius, QueryFilter::ALL), expected);
+
+            let aabb = Aabb::from_center(center, rng.vec(0.1, 5.0));
+            let expected: Vec<Volume> = all.iter().filter(|(_, o)| o.overlaps_aabb(&aabb)).map(|(v, _)| *v).collect();
+            assert_eq!(bvh.overlap_aabb(&aabb, QueryFilter::ALL), expected);
+
+            let expected = all.iter().map(|(_, o)| o.closest_point(center).distance(center)).fold(f32::INFINITY, f32::min);
+            let got = bvh.nearest_point(center, QueryFilter::ALL).expect("nearest").distance;
+            assert!((got - expected).abs() < 1e-5, "seed {seed}: nearest {got} vs {expected}");
+        }
+    }
+}
+
+#[test]
+fn obb_aabb_overlap_agrees_with_sampling() {
+    let mut rng = Rng(7);
+    for _ in 0..500 {
+        let axis = rng.vec(-1.0, 1.0).try_normalize().unwrap_or(Vec3::Y);
+        let obb = Obb { center: rng.vec(-2.0, 2.0), half: rng.vec(0.2, 1.5), rotation: Quat::from_axis_angle(axis, rng.range(-3.2, 3.2)) };
+        let aabb = Aabb::from_center(rng.vec(-2.0, 2.0), rng.vec(0.2, 1.5));
+        // any sampled point inside both proves an overlap
+        let witnessed = (0..400).any(|_| {
+            let p = aabb.min + (aabb.max - aabb.min) * Vec3::new(rng.next(), rng.next(), rng.next());
+            obb.contains(p)
+        });
+        if witnessed {
+            assert!(obb.overlaps_aabb(&aabb), "{obb:?} {aabb:?}");
+        }
+        if !obb.overlaps_aabb(&aabb) {
+            assert!(!aabb.contains(obb.center) && !obb.contains(aabb.center()));
+        }
+    }
+}
+
+#[test]
+fn frustum_culling_never_rejects_a_visible_box() {
+    let camera = Camera { pos: Vec3::new(1.0, 2.0, 3.0), yaw: 0.7, pitch: -0.2, fov_y: 70.0, z_near: 0.1, z_far: 50.0 };
+    let frustum = Frustum::from_view_proj(camera.proj(16.0 / 9.0) * camera.view());
+    let ahead = camera.pos + Camera::dir(camera.yaw, camera.pitch) * 10.0;
+    assert!(frustum.contains(ahead));
+    assert!(!frustum.contains(camera.pos - Camera::dir(camera.yaw, camera.pitch) * 10.0));
+    assert!(!frustum.contains(camera.pos + Camera::dir(camera.yaw, camera.pitch) * 60.0));
+    assert!(!frustum.intersects_aabb(&Aabb::EMPTY));
+
+    let mut rng = Rng(11);
+    let mut rejected = 0;
+    for _ in 0..500 {
+        let aabb = Aabb::from_center(camera.pos + rng.vec(-40.0, 40.0), rng.vec(0.1, 4.0));
+        // any sampled point inside the frustum proves the box is visible
+        let witnessed = (0..200).any(|_| frustum.contains(aabb.min + (aabb.max - aabb.min) * Vec3::new(rng.next(), rng.next(), rng.next())));
+        if witnessed {
+            assert!(frustum.intersects_aabb(&aabb), "{aabb:?}");
+        }
+        if !frustum.intersects_aabb(&aabb) {
+            rejected += 1;
+            assert!(aabb.corners().iter().all(|&c| !frustum.contains(c)), "{aabb:?}");
+        }
+    }
+    // most of the space around the camera is out of view
+    assert!(rejected > 250, "only {rejected} rejected");
+}
+
+#[test]
+fn rebuild_tracks_scene_edits() {
+    let mut scene = generate_level(3, &GeneratorParams::default());
+    let mut bvh = SceneBvh::new(&scene);
+    let spawn = Vec3::from(scene.player.spawn);
+    assert!(bvh.triggers_at(spawn).is_empty());
+
+    scene.triggers.push(kengaai_scene_fps::Trigger { pos: scene.player.spawn, size: [1.0; 3], on_enter: "noop".into() });
+    bvh.rebuild(&scene);
+    assert_eq!(bvh.triggers_at(spawn), vec![scene.triggers.len() - 1]);
+    assert_eq!(bvh.len(), scene.level.boxes.len() + scene.triggers.len());
+
+    scene.level.boxes.clear();
+    scene.triggers.clear();
+    bvh.rebuild(&scene);
+    assert!(bvh.is_empty());
+    assert!(bvh.raycast(spawn, Vec3::NEG_Y, 100.0, QueryFilter::ALL).is_none());
+    assert!(bvh.nearest_point(spawn, QueryFilter::ALL).is_none());
+}
+
+#[test]
+fn grouped_boxes_are_indexed_in_world_space() {
+    let tilted = Group {
+        pos: [10.0, 0.0, 0.0],
+        rotation: Some(Rotation::Euler([0.0, 0.0, std::f32::consts::FRAC_PI_2])),
+        boxes: vec![BoxDef { pos: [0.0, 2.0, 0.0], size: [3.0, 0.5, 0.5], rot_y: 0.0, rotation: None, color: [1.0; 3], texture: None, material: None }],
+        ..Default::default()
+    };
+    let scene = FpsSceneBuilder::new("group").box_at([0.0, -1.0, 0.0], [5.0, 0.5, 5.0]).group(tilted).build_unchecked();
+    let bvh = SceneBvh::new(&scene);
+    assert_eq!(bvh.len(), 2);
+
+    // rolled a quarter turn, the beam stands upright beside the group origin
+    let obb = bvh.obb(Volume::Box(1)).expect("grouped box");
+    assert!(obb.center.distance(Vec3::new(8.0, 0.0, 0.0)) < 1e-4, "center = {}", obb.center);
+    assert!(obb.aabb().half_extents().distance(Vec3::new(0.5, 3.0, 0.5)) < 1e-4);
+    let hit = bvh.raycast(Vec3::new(8.0, 10.0, 0.0), Vec3::NEG_Y, 100.0, QueryFilter::BOXES).expect("hit");
+    assert_eq!(hit.volume, Volume::Box(1));
+    assert!((hit.t - 7.0).abs() < 1e-4, "t = {}", hit.t);
+}
@@ -0,0 +1,172 @@
+//! Enemy behaviors with typed per-behavior parameters.
+//!
+//! On disk a behavior is `{"type": "patrol", "parameters": {...}}`; `parameters` may be omitted.
+
+use crate::string_enum;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+string_enum! {
+    BehaviorKind, "behavior type" {
+        Patrol => "patrol",
+        Chase => "chase",
+        Flee => "flee",
+        Idle => "idle",
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct PatrolParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+    /// Seconds to wait at each patrol point
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wait_time: Option<f32>,
+    /// Walk the route back and forth instead of looping
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub ping_pong: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct ChaseParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+    /// Distance at which the enemy notices the player
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detection_range: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attack_range: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct FleeParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+    /// Distance from the player at which the enemy stops running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safe_distance: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct IdleParams {
+    /// Turn in place while idle
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub look_around: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawBehavior", into = "RawBehavior")]
+pub enum Behavior {
+    Patrol(PatrolParams),
+    Chase(ChaseParams),
+    Flee(FleeParams),
+    Idle(IdleParams),
+}
+
+impl Behavior {
+    pub fn kind(&self) -> BehaviorKind {
+        match self {
+            Behavior::Patrol(_) => BehaviorKind::Patrol,
+            Behavior::Chase(_) => BehaviorKind::Chase,
+            Behavior::Flee(_) => BehaviorKind::Flee,
+            Behavior::Idle(_) => BehaviorKind::Idle,
+        }
+    }
+
+    /// Behavior of the given kind with default parameters
+    pub fn with_defaults(kind: BehaviorKind) -> Self {
+        match kind {
+            BehaviorKind::Patrol => Behavior::Patrol(PatrolParams::default()),
+            BehaviorKind::Chase => Behavior::Chase(ChaseParams::default()),
+            BehaviorKind::Flee => Behavior::Flee(FleeParams::default()),
+            BehaviorKind::Idle => Behavior::Idle(IdleParams::default()),
+        }
+    }
+}
+
+/// `oneOf` the `{"type", "parameters"}` shapes, mirroring the custom serde representation
+impl JsonSchema for Behavior {
+    fn schema_name() -> String {
+        "Behavior".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let variants: Vec<serde_json::Value> = [
+            (BehaviorKind::Patrol, gen.subschema_for::<PatrolParams>()),
+            (BehaviorKind::Chase, gen.subschema_for::<ChaseParams>()),
+            (BehaviorKind::Flee, gen.subschema_for::<FleeParams>()),
+            (BehaviorKind::Idle, gen.subschema_for::<IdleParams>()),
+        ]
+        .into_iter()
+        .map(|(kind, params)| {
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "type": { "type": "string", "const": kind.as_str() },
+                    "parameters": params,
+                },
+                "required": ["type"],
+                "additionalProperties": false,
+            })
+        })
+        .collect();
+        serde_json::from_value(serde_json::json!({ "oneOf": variants })).unwrap_or(Schema::Bool(true))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawBehavior {
+    #[serde(rename = "type")]
+    kind: BehaviorKind,
+    #[serde(default, skip_serializing_if = "is_empty_params")]
+    parameters: serde_json::Value,
+}
+
+fn is_empty_params(v: &serde_json::Value) -> bool {
+    match v {
+        serde_json::Value::Null => true,
+        serde_json::Value::Object(m) => m.is_empty(),
+        _ => false,
+    }
+}
+
+impl TryFrom<RawBehavior> for Behavior {
+    type Error = String;
+
+    fn try_from(raw: RawBehavior) -> Result<Self, Self::Error> {
+        fn params<T: Default + serde::de::DeserializeOwned>(kind: BehaviorKind, v: serde_json::Value) -> Result<T, String> {
+            if v.is_null() {
+                return Ok(T::default());
+            }
+            serde_json::from_value(v).map_err(|e| format!("invalid parameters for {kind} behavior: {e}"))
+        }
+
+        let kind = raw.kind;
+        Ok(match kind {
+            BehaviorKind::Patrol => Behavior::Patrol(params(kind, raw.parameters)?),
+            BehaviorKind::Chase => Behavior::Chase(params(kind, raw.parameters)?),
+            BehaviorKind::Flee => Behavior::Flee(params(kind, raw.parameters)?),
+            BehaviorKind::Idle => Behavior::Idle(params(kind, raw.parameters)?),
+        })
+    }
+}
+
+impl From<Behavior> for RawBehavior {
+    fn from(b: Behavior) -> Self {
+        let kind = b.kind();
+        let parameters = match b {
+            Behavior::Patrol(p) => serde_json::to_value(p),
+            Behavior::Chase(p) => serde_json::to_value(p),
+            Behavior::Flee(p) => serde_json::to_value(p),
+            Behavior::Idle(p) => serde_json::to_value(p),
+        }
+        .unwrap_or_default();
+        Self { kind, parameters }
+    }
+}
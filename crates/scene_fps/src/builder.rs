@@ -0,0 +1,335 @@
+//! Fluent construction of scenes from Rust code.
+//!
+//! Sizes follow the scene format: `size` values are half-extents, as drawn by the renderer's unit cube.
+
+use crate::{
+    schema_tag, Behavior, BoxDef, Enemy, FpsScene, Goals, GoalKind, Group, Level, Light, LightKind, Material, Meta, Move,
+    PatrolParams, Player, PostProcess, Render, Rotation, SceneError, Shading, Trigger, Weapon, WeaponKind, CURRENT_SCHEMA_VERSION,
+};
+use std::collections::BTreeMap;
+
+/// Half-thickness of walls created by [`FpsSceneBuilder::wall`]
+pub const WALL_HALF_THICKNESS: f32 = 0.25;
+
+pub(crate) const DEFAULT_BOX_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+
+/// Builds an [`FpsScene`] with sensible defaults. `floor` puts the walkable surface at `y = 0`.
+///
+/// `rot_y`, `rotation`, `color`, `texture` and `material` change the box added by the call right before
+/// them (`box_at`, `floor`, `wall` or another of these) and panic after any other call. `range`, `cone`
+/// and `shadows` do the same for lights.
+///
+/// ```
+/// use kengaai_scene_fps::FpsSceneBuilder;
+///
+/// let scene = FpsSceneBuilder::new("arena")
+///     .floor(10.0, 10.0)
+///     .wall([-10.0, -10.0], [10.0, -10.0], 3.0)
+///     .box_at([0.0, 1.0, -4.0], [1.0, 1.0, 1.0]).color([1.0, 0.0, 0.0])
+///     .point_light([0.0, 4.0, 0.0], [1.0, 1.0, 1.0], 1.5)
+///     .enemy("grunt", [3.0, 1.0, -6.0])
+///     .trigger([0.0, 1.0, -8.0], [1.0, 1.0, 1.0], "collect_key:red")
+///     .build()
+///     .unwrap();
+/// assert_eq!(scene.level.boxes.len(), 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FpsSceneBuilder {
+    scene: FpsScene,
+    /// What the previous call added, which the box and light modifiers change
+    last: Last,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Last {
+    Other,
+    Box(usize),
+    Light(usize),
+}
+
+impl FpsSceneBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            scene: FpsScene {
+                meta: Meta {
+                    schema: schema_tag(CURRENT_SCHEMA_VERSION),
+                    version: "0.1.0".to_string(),
+                    name: name.into(),
+                },
+                render: Render { clear_color: [0.05, 0.05, 0.1, 1.0], shading: Shading::Simple, post_process: None },
+                player: Player {
+                    spawn: [0.0, 1.5, 0.0],
+                    yaw: 0.0,
+                    pitch: 0.0,
+                    r#move: Move { speed: 4.5, run: 7.5 },
+                },
+                weapons: Vec::new(),
+                level: Level { boxes: Vec::new(), meshes: Vec::new(), groups: Vec::new() },
+                lights: Vec::new(),
+                particles: Vec::new(),
+                enemies: Vec::new(),
+                sounds: Vec::new(),
+                triggers: Vec::new(),
+                goals: None,
+                includes: Vec::new(),
+                prefabs: BTreeMap::new(),
+                instances: Vec::new(),
+                included_prefabs: BTreeMap::new(),
+            },
+            last: Last::Other,
+        }
+    }
+
+    pub fn clear_color(mut self, rgba: [f32; 4]) -> Self {
+        self.last = Last::Other;
+        self.scene.render.clear_color = rgba;
+        self
+    }
+
+    /// Pick the lighting model; [`Shading::Pbr`] needs materials on the boxes and meshes it applies to
+    pub fn shading(mut self, shading: Shading) -> Self {
+        self.last = Last::Other;
+        self.scene.render.shading = shading;
+        self
+    }
+
+    /// Render through the HDR post-processing chain
+    pub fn post_process(mut self, post: PostProcess) -> Self {
+        self.last = Last::Other;
+        self.scene.render.post_process = Some(post);
+        self
+    }
+
+    pub fn spawn(mut self, pos: [f32; 3], yaw: f32) -> Self {
+        self.last = Last::Other;
+        self.scene.player.spawn = pos;
+        self.scene.player.yaw = yaw;
+        self
+    }
+
+    pub fn move_speed(mut self, speed: f32, run: f32) -> Self {
+        self.last = Last::Other;
+        self.scene.player.r#move = Move { speed, run };
+        self
+    }
+
+    pub fn weapon(mut self, id: impl Into<String>, kind: WeaponKind, damage: f32, rate: f32) -> Self {
+        self.last = Last::Other;
+        self.scene.weapons.push(Weapon { id: id.into(), kind, damage, rate, spread: None });
+        self
+    }
+
+    /// Axis-aligned box centered at `pos`
+    pub fn box_at(mut self, pos: [f32; 3], half_size: [f32; 3]) -> Self {
+        self.last = Last::Box(self.scene.level.boxes.len());
+        self.scene.level.boxes.push(BoxDef {
+            pos,
+            size: half_size,
+            rot_y: 0.0,
+            rotation: None,
+            color: DEFAULT_BOX_COLOR,
+            texture: None,
+            material: None,
+        });
+        self
+    }
+
+    /// Floor slab centered on the origin whose top face is at `y = 0`
+    pub fn floor(self, half_x: f32, half_z: f32) -> Self {
+        self.box_at([0.0, -0.5, 0.0], [half_x, 0.5, half_z])
+    }
+
+    /// Wall standing on the floor from `from` to `to` (XZ coordinates)
+    pub fn wall(mut self, from: [f32; 2], to: [f32; 2], height: f32) -> Self {
+        let (dx, dz) = (to[0] - from[0], to[1] - from[1]);
+        let half_len = 0.5 * (dx * dx + dz * dz).sqrt();
+        self.last = Last::Box(self.scene.level.boxes.len());
+        self.scene.level.boxes.push(BoxDef {
+            pos: [0.5 * (from[0] + to[0]), 0.5 * height, 0.5 * (from[1] + to[1])],
+            size: [half_len, 0.5 * height, WALL_HALF_THICKNESS],
+            // the box's local X axis runs along the wall
+            rot_y: dz.atan2(dx),
+            rotation: None,
+            color: DEFAULT_BOX_COLOR,
+            texture: None,
+            material: None,
+        });
+        self
+    }
+
+    /// Rotate the box just added around Y
+    pub fn rot_y(mut self, radians: f32) -> Self {
+        self.last_box("rot_y").rot_y = radians;
+        self
+    }
+
+    /// Give the box just added a full orientation, overriding its `rotY`
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.last_box("rotation").rotation = Some(rotation);
+        self
+    }
+
+    /// Add a group of boxes and meshes placed relative to the group's transform
+    pub fn group(mut self, group: Group) -> Self {
+        self.last = Last::Other;
+        self.scene.level.groups.push(group);
+        self
+    }
+
+    /// Set the color of the box just added
+    pub fn color(mut self, rgb: [f32; 3]) -> Self {
+        self.last_box("color").color = rgb;
+        self
+    }
+
+    /// Set the texture of the box just added
+    pub fn texture(mut self, name: impl Into<String>) -> Self {
+        self.last_box("texture").texture = Some(name.into());
+        self
+    }
+
+    /// Give the box just added a PBR material
+    pub fn material(mut self, material: Material) -> Self {
+        self.last_box("material").material = Some(material);
+        self
+    }
+
+    pub fn point_light(mut self, position: [f32; 3], color: [f32; 3], intensity: f32) -> Self {
+        self.last = Last::Light(self.scene.lights.len());
+        self.scene.lights.push(Light {
+            kind: LightKind::Point,
+            position,
+            color,
+            intensity,
+            range: None,
+            direction: None,
+            inner_angle: None,
+            outer_angle: None,
+            cast_shadows: false,
+            shadow_resolution: None,
+        });
+        self
+    }
+
+    /// Directional light; `direction` is stored in the light's `position` field
+    pub fn directional_light(mut self, direction: [f32; 3], color: [f32; 3], intensity: f32) -> Self {
+        self.last = Last::Light(self.scene.lights.len());
+        self.scene.lights.push(Light {
+            kind: LightKind::Directional,
+            position: direction,
+            color,
+            intensity,
+            range: None,
+            direction: None,
+            inner_angle: None,
+            outer_angle: None,
+            cast_shadows: false,
+            shadow_resolution: None,
+        });
+        self
+    }
+
+    /// Spot light at `position` shining along `direction`, with the default cone
+    pub fn spot_light(mut self, position: [f32; 3], direction: [f32; 3], color: [f32; 3], intensity: f32) -> Self {
+        self.last = Last::Light(self.scene.lights.len());
+        self.scene.lights.push(Light {
+            kind: LightKind::Spot,
+            position,
+            color,
+            intensity,
+            range: None,
+            direction: Some(direction),
+            inner_angle: None,
+            outer_angle: None,
+            cast_shadows: false,
+            shadow_resolution: None,
+        });
+        self
+    }
+
+    /// Set the distance at which the light just added fades out
+    pub fn range(mut self, range: f32) -> Self {
+        self.last_light("range").range = Some(range);
+        self
+    }
+
+    /// Set the cone half-angles of the spot light just added, in radians
+    pub fn cone(mut self, inner: f32, outer: f32) -> Self {
+        let l = self.last_light("cone");
+        l.inner_angle = Some(inner);
+        l.outer_angle = Some(outer);
+        self
+    }
+
+    /// Make the light just added cast shadows into a `resolution`² map
+    pub fn shadows(mut self, resolution: u32) -> Self {
+        let l = self.last_light("shadows");
+        l.cast_shadows = true;
+        l.shadow_resolution = Some(resolution);
+        self
+    }
+
+    pub fn enemy(mut self, kind: impl Into<String>, spawn: [f32; 3]) -> Self {
+        self.last = Last::Other;
+        self.scene.enemies.push(Enemy {
+            kind: kind.into(),
+            spawn,
+            patrol: Vec::new(),
+            physics: None,
+            behavior: None,
+        });
+        self
+    }
+
+    /// Give the most recently added enemy a patrol route
+    pub fn patrol(mut self, route: &[[f32; 3]]) -> Self {
+        self.last = Last::Other;
+        if let Some(e) = self.scene.enemies.last_mut() {
+            e.patrol = route.to_vec();
+            e.behavior = Some(Behavior::Patrol(PatrolParams::default()));
+        }
+        self
+    }
+
+    pub fn trigger(mut self, pos: [f32; 3], half_size: [f32; 3], on_enter: impl Into<String>) -> Self {
+        self.last = Last::Other;
+        self.scene.triggers.push(Trigger { pos, size: half_size, on_enter: on_enter.into() });
+        self
+    }
+
+    pub fn goal(mut self, kind: GoalKind, point: [f32; 3]) -> Self {
+        self.last = Last::Other;
+        self.scene.goals = Some(Goals { r#type: kind, point });
+        self
+    }
+
+    /// The box added by `box_at`, `floor` or `wall`, if the calls since only modified it
+    fn last_box(&mut self, method: &str) -> &mut BoxDef {
+        match self.last {
+            Last::Box(i) => &mut self.scene.level.boxes[i],
+            _ => panic!("FpsSceneBuilder::{method} must follow box_at, floor, wall or another box modifier"),
+        }
+    }
+
+    /// The light added by one of the `*_light` methods, if the calls since only modified it
+    fn last_light(&mut self, method: &str) -> &mut Light {
+        match self.last {
+            Last::Light(i) => &mut self.scene.lights[i],
+            _ => panic!("FpsSceneBuilder::{method} must follow a *_light method or another light modifier"),
+        }
+    }
+
+    /// Finish and run [`FpsScene::validate`]; warnings are ignored
+    pub fn build(self) -> Result<FpsScene, SceneError> {
+        let diagnostics = self.scene.validate();
+        if crate::has_errors(&diagnostics) {
+            return Err(SceneError::Semantic { file: None, diagnostics, location: None });
+        }
+        Ok(self.scene)
+    }
+
+    /// Finish without validating
+    pub fn build_unchecked(self) -> FpsScene {
+        self.scene
+    }
+}
@@ -0,0 +1,511 @@
+//! Structural differences between two scenes, and invertible patches built from them.
+//!
+//! Entity lists (boxes, lights, enemies, triggers, ...) are matched by content, so inserting in the
+//! middle or reordering doesn't show up as a cascade of modifications. Everything else is compared
+//! field by field. Paths use the same syntax as diagnostics, e.g. `level.boxes[3].pos`.
+
+use crate::format::canonical_value;
+use crate::FpsScene;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use thiserror::Error;
+
+/// Largest float difference [`DiffOptions::default`] treats as equal
+pub const DEFAULT_TOLERANCE: f32 = 1e-4;
+
+/// Entity lists whose elements are matched rather than compared by index
+const COLLECTIONS: &[&str] = &[
+    "weapons",
+    "level.boxes",
+    "level.meshes",
+    "level.groups",
+    "lights",
+    "particles",
+    "enemies",
+    "sounds",
+    "triggers",
+    "instances",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffOptions {
+    /// Numbers closer than this compare equal
+    pub tolerance: f32,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self { tolerance: DEFAULT_TOLERANCE }
+    }
+}
+
+/// A changed value; `Null` stands for a field that is absent on that side
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub path: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntityChange {
+    /// Present only in the new scene, at `index`
+    Added { index: usize, value: Value },
+    /// Present only in the old scene, at `index`
+    Removed { index: usize, value: Value },
+    /// Matched across both scenes. `moved` is set when its order relative to the other matched
+    /// entities changed; `fields` are relative to the entity.
+    Modified { old_index: usize, new_index: usize, old: Value, new: Value, moved: bool, fields: Vec<FieldChange> },
+}
+
+/// Changes to one entity list, e.g. `level.boxes`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectionDiff {
+    pub path: &'static str,
+    pub changes: Vec<EntityChange>,
+}
+
+impl CollectionDiff {
+    pub fn added(&self) -> usize {
+        self.changes.iter().filter(|c| matches!(c, EntityChange::Added { .. })).count()
+    }
+
+    pub fn removed(&self) -> usize {
+        self.changes.iter().filter(|c| matches!(c, EntityChange::Removed { .. })).count()
+    }
+
+    pub fn modified(&self) -> usize {
+        self.changes.iter().filter(|c| matches!(c, EntityChange::Modified { .. })).count()
+    }
+}
+
+/// Result of [`FpsScene::diff`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SceneDiff {
+    /// Changes outside the entity lists, e.g. `player.spawn`
+    pub fields: Vec<FieldChange>,
+    /// Entity lists with at least one change
+    pub collections: Vec<CollectionDiff>,
+}
+
+impl SceneDiff {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.collections.is_empty()
+    }
+
+    pub fn collection(&self, path: &str) -> Option<&CollectionDiff> {
+        self.collections.iter().find(|c| c.path == path)
+    }
+
+    /// Patch that turns the old scene into the new one, up to the diff tolerance
+    pub fn to_patch(&self) -> ScenePatch {
+        let mut sets = Vec::new();
+        let mut removes = Vec::new();
+        let mut inserts = Vec::new();
+        for f in &self.fields {
+            sets.push(PatchOp::Set { path: f.path.clone(), old: f.old.clone(), new: f.new.clone() });
+        }
+        for c in &self.collections {
+            let mut rm = Vec::new();
+            let mut ins = Vec::new();
+            for change in &c.changes {
+                match change {
+                    EntityChange::Added { index, value } => ins.push((*index, value.clone())),
+                    EntityChange::Removed { index, value } => rm.push((*index, value.clone())),
+                    // moved entities are taken out and put back in their new place
+                    EntityChange::Modified { old_index, new_index, old, new, moved: true, .. } => {
+                        rm.push((*old_index, old.clone()));
+                        ins.push((*new_index, new.clone()));
+                    }
+                    EntityChange::Modified { old_index, fields, .. } => {
+                        for f in fields {
+                            sets.push(PatchOp::Set {
+                                path: format!("{}[{old_index}].{}", c.path, f.path),
+                                old: f.old.clone(),
+                                new: f.new.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+            // removing back to front keeps the remaining old indices valid; inserting front to back
+            // lands every entity at its new index
+            rm.sort_by_key(|(i, _)| std::cmp::Reverse(*i));
+            ins.sort_by_key(|(i, _)| *i);
+            removes.extend(rm.into_iter().map(|(index, value)| PatchOp::Remove { path: c.path.to_string(), index, value }));
+            inserts.extend(ins.into_iter().map(|(index, value)| PatchOp::Insert { path: c.path.to_string(), index, value }));
+        }
+        ScenePatch { ops: sets.into_iter().chain(removes).chain(inserts).collect() }
+    }
+}
+
+impl fmt::Display for SceneDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in &self.fields {
+            writeln!(f, "~ {}: {} -> {}", c.path, show(&c.old), show(&c.new))?;
+        }
+        for c in &self.collections {
+            for change in &c.changes {
+                match change {
+                    EntityChange::Added { index, value } => writeln!(f, "+ {}[{index}]: {value}", c.path)?,
+                    EntityChange::Removed { index, value } => writeln!(f, "- {}[{index}]: {value}", c.path)?,
+                    EntityChange::Modified { old_index, new_index, moved, fields, .. } => {
+                        write!(f, "~ {}[{old_index}]", c.path)?;
+                        if old_index != new_index {
+                            write!(f, " -> [{new_index}]")?;
+                        }
+                        writeln!(f, "{}", if *moved { " (moved)" } else { "" })?;
+                        for fc in fields {
+                            writeln!(f, "    {}: {} -> {}", fc.path, show(&fc.old), show(&fc.new))?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn show(v: &Value) -> String {
+    if v.is_null() { "(none)".to_string() } else { v.to_string() }
+}
+
+/// A single edit of the scene document. Ops apply in order, so indices refer to the document as
+/// left by the previous op.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    /// Replace the value at `path`, which must currently be `old`; `Null` means absent
+    Set { path: String, old: Value, new: Value },
+    /// Insert `value` into the list at `path`
+    Insert { path: String, index: usize, value: Value },
+    /// Remove the element at `index`, which must currently be `value`, from the list at `path`
+    Remove { path: String, index: usize, value: Value },
+}
+
+impl PatchOp {
+    pub fn inverse(&self) -> PatchOp {
+        match self.clone() {
+            PatchOp::Set { path, old, new } => PatchOp::Set { path, old: new, new: old },
+            PatchOp::Insert { path, index, value } => PatchOp::Remove { path, index, value },
+            PatchOp::Remove { path, index, value } => PatchOp::Insert { path, index, value },
+        }
+    }
+}
+
+/// An ordered list of edits, stored as JSON alongside levels for undo and review
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScenePatch {
+    pub ops: Vec<PatchOp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum PatchError {
+    #[error("{path}: no such value")]
+    NotFound { path: String },
+    #[error("{path}: expected {}, found {}", show(expected), show(found))]
+    Conflict { path: String, expected: Box<Value>, found: Box<Value> },
+    #[error("{path}: index {index} out of range for length {len}")]
+    OutOfRange { path: String, index: usize, len: usize },
+    #[error("`{0}` is not a valid path")]
+    BadPath(String),
+    #[error("patched scene is invalid: {0}")]
+    Invalid(String),
+}
+
+impl ScenePatch {
+    /// Patch from `old` to `new`
+    pub fn between(old: &FpsScene, new: &FpsScene, opts: &DiffOptions) -> ScenePatch {
+        old.diff_with(new, opts).to_patch()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Patch that undoes this one
+    pub fn inverse(&self) -> ScenePatch {
+        ScenePatch { ops: self.ops.iter().rev().map(PatchOp::inverse).collect() }
+    }
+
+    /// Apply every op, failing without partial results if the scene doesn't match what the patch expects
+    pub fn apply(&self, scene: &FpsScene) -> Result<FpsScene, PatchError> {
+        let mut doc = canonical_value(scene);
+        for op in &self.ops {
+            apply_op(&mut doc, op)?;
+        }
+        serde_json::from_value(doc).map_err(|e| PatchError::Invalid(e.to_string()))
+    }
+}
+
+impl FpsScene {
+    /// Differences from `self` to `other` with [`DiffOptions::default`]
+    pub fn diff(&self, other: &FpsScene) -> SceneDiff {
+        self.diff_with(other, &DiffOptions::default())
+    }
+
+    pub fn diff_with(&self, other: &FpsScene, opts: &DiffOptions) -> SceneDiff {
+        let (a, b) = (canonical_value(self), canonical_value(other));
+        let tol = f64::from(opts.tolerance);
+        let mut out = SceneDiff::default();
+        diff_values("", &a, &b, tol, &mut out.fields);
+        for &path in COLLECTIONS {
+            let changes = diff_collection(list_at(&a, path), list_at(&b, path), tol);
+            if !changes.is_empty() {
+                out.collections.push(CollectionDiff { path, changes });
+            }
+        }
+        out
+    }
+}
+
+fn list_at<'a>(doc: &'a Value, path: &str) -> &'a [Value] {
+    let mut v = doc;
+    for key in path.split('.') {
+        match v.get(key) {
+            Some(next) => v = next,
+            None => return &[],
+        }
+    }
+    v.as_array().map_or(&[], Vec::as_slice)
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{path}.{key}") }
+}
+
+/// Field changes between two values, descending into objects and skipping entity lists
+fn diff_values(path: &str, a: &Value, b: &Value, tol: f64, out: &mut Vec<FieldChange>) {
+    if COLLECTIONS.contains(&path) {
+        return;
+    }
+    match (a, b) {
+        (Value::Object(x), Value::Object(y)) => {
+            let keys = x.keys().chain(y.keys().filter(|k| !x.contains_key(*k)));
+            for k in keys {
+                let null = Value::Null;
+                diff_values(&join(path, k), x.get(k).unwrap_or(&null), y.get(k).unwrap_or(&null), tol, out);
+            }
+        }
+        _ if approx_eq(a, b, tol) => {}
+        _ => out.push(FieldChange { path: path.to_string(), old: a.clone(), new: b.clone() }),
+    }
+}
+
+fn approx_eq(a: &Value, b: &Value, tol: f64) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => match (x.as_f64(), y.as_f64()) {
+            (Some(x), Some(y)) => (x - y).abs() <= tol,
+            _ => x == y,
+        },
+        (Value::Array(x), Value::Array(y)) => x.len() == y.len() && x.iter().zip(y).all(|(a, b)| approx_eq(a, b, tol)),
+        (Value::Object(x), Value::Object(y)) => {
+            x.len() == y.len() && x.iter().all(|(k, v)| y.get(k).is_some_and(|w| approx_eq(v, w, tol)))
+        }
+        _ => a == b,
+    }
+}
+
+/// Match identical entities first, then pair the most similar leftovers as modifications
+fn diff_collection(old: &[Value], new: &[Value], tol: f64) -> Vec<EntityChange> {
+    let mut new_taken = vec![false; new.len()];
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    let mut old_left = Vec::new();
+    for (i, a) in old.iter().enumerate() {
+        match (0..new.len()).find(|&j| !new_taken[j] && approx_eq(a, &new[j], tol)) {
+            Some(j) => {
+                new_taken[j] = true;
+                pairs.push((i, j));
+            }
+            None => old_left.push(i),
+        }
+    }
+    let new_left: Vec<usize> = (0..new.len()).filter(|&j| !new_taken[j]).collect();
+
+    // (changed fields, old, new) for leftovers that still share at least one field
+    let mut similar = Vec::new();
+    for &i in &old_left {
+        for &j in &new_left {
+            let mut fields = Vec::new();
+            diff_values("", &old[i], &new[j], tol, &mut fields);
+            if fields.len() < field_count(&old[i]).max(field_count(&new[j])) {
+                similar.push((fields.len(), i, j));
+            }
+        }
+    }
+    similar.sort_unstable();
+    let mut old_paired = vec![false; old.len()];
+    for (_, i, j) in similar {
+        if !old_paired[i] && !new_taken[j] {
+            old_paired[i] = true;
+            new_taken[j] = true;
+            pairs.push((i, j));
+        }
+    }
+    pairs.sort_unstable();
+
+    let in_order = longest_increasing(&pairs.iter().map(|p| p.1).collect::<Vec<_>>());
+    let mut changes = Vec::new();
+    for (k, &(i, j)) in pairs.iter().enumerate() {
+        let mut fields = Vec::new();
+        diff_values("", &old[i], &new[j], tol, &mut fields);
+        let moved = !in_order[k];
+        if moved || !fields.is_empty() {
+            changes.push(EntityChange::Modified {
+                old_index: i,
+                new_index: j,
+                old: old[i].clone(),
+                new: new[j].clone(),
+                moved,
+                fields,
+            });
+        }
+    }
+    for i in old_left.into_iter().filter(|&i| !old_paired[i]) {
+        changes.push(EntityChange::Removed { index: i, value: old[i].clone() });
+    }
+    for j in new_left.into_iter().filter(|&j| !new_taken[j]) {
+        changes.push(EntityChange::Added { index: j, value: new[j].clone() });
+    }
+    changes
+}
+
+fn field_count(v: &Value) -> usize {
+    v.as_object().map_or(1, |m| m.len())
+}
+
+/// Marks one longest strictly increasing subsequence of `xs`
+fn longest_increasing(xs: &[usize]) -> Vec<bool> {
+    // tails[k]: index into xs of the smallest tail of an increasing run of length k + 1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev = vec![usize::MAX; xs.len()];
+    for (i, &x) in xs.iter().enumerate() {
+        let k = tails.partition_point(|&t| xs[t] < x);
+        if k > 0 {
+            prev[i] = tails[k - 1];
+        }
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+    let mut keep = vec![false; xs.len()];
+    let mut cur = tails.last().copied();
+    while let Some(i) = cur {
+        keep[i] = true;
+        cur = (prev[i] != usize::MAX).then(|| prev[i]);
+    }
+    keep
+}
+
+enum Seg {
+    Key(String),
+    Index(usize),
+}
+
+fn parse_path(path: &str) -> Result<Vec<Seg>, PatchError> {
+    let bad = || PatchError::BadPath(path.to_string());
+    let mut segs = Vec::new();
+    for part in path.split('.') {
+        let (key, mut rest) = part.split_once('[').map_or((part, ""), |(k, r)| (k, r));
+        if key.is_empty() {
+            return Err(bad());
+        }
+        segs.push(Seg::Key(key.to_string()));
+        while !rest.is_empty() {
+            let (n, tail) = rest.split_once(']').ok_or_else(bad)?;
+            segs.push(Seg::Index(n.parse().map_err(|_| bad())?));
+            rest = tail.strip_prefix('[').unwrap_or(tail);
+        }
+    }
+    Ok(segs)
+}
+
+/// The value at `segs`, creating missing object keys when `create` is set
+fn resolve<'a>(doc: &'a mut Value, segs: &[Seg], path: &str, create: bool) -> Result<&'a mut Value, PatchError> {
+    let not_found = || PatchError::NotFound { path: path.to_string() };
+    let mut v = doc;
+    for seg in segs {
+        v = match seg {
+            Seg::Key(k) => {
+                let map = v.as_object_mut().ok_or_else(not_found)?;
+                if create {
+                    map.entry(k.clone()).or_insert(Value::Null)
+                } else {
+                    map.get_mut(k).ok_or_else(not_found)?
+                }
+            }
+            Seg::Index(i) => v.as_array_mut().and_then(|a| a.get_mut(*i)).ok_or_else(not_found)?,
+        };
+    }
+    Ok(v)
+}
+
+fn list_mut<'a>(doc: &'a mut Value, path: &str) -> Result<&'a mut Vec<Value>, PatchError> {
+    let v = resolve(doc, &parse_path(path)?, path, true)?;
+    if v.is_null() {
+        *v = Value::Array(Vec::new());
+    }
+    v.as_array_mut().ok_or_else(|| PatchError::NotFound { path: path.to_string() })
+}
+
+fn apply_op(doc: &mut Value, op: &PatchOp) -> Result<(), PatchError> {
+    match op {
+        PatchOp::Set { path, old, new } => {
+            let mut segs = parse_path(path)?;
+            let last = segs.pop().ok_or_else(|| PatchError::BadPath(path.clone()))?;
+            let parent = resolve(doc, &segs, path, !new.is_null())?;
+            if parent.is_null() {
+                *parent = Value::Object(Default::default());
+            }
+            let slot = match (last, parent) {
+                (Seg::Key(k), Value::Object(map)) => {
+                    let found = map.get(&k).cloned().unwrap_or(Value::Null);
+                    if found != *old {
+                        return Err(PatchError::Conflict { path: path.clone(), expected: Box::new(old.clone()), found: Box::new(found) });
+                    }
+                    if new.is_null() {
+                        map.shift_remove(&k);
+                        return Ok(());
+                    }
+                    map.entry(k).or_insert(Value::Null)
+                }
+                (Seg::Index(i), Value::Array(list)) => {
+                    let len = list.len();
+                    let slot = list.get_mut(i).ok_or(PatchError::OutOfRange { path: path.clone(), index: i, len })?;
+                    if *slot != *old {
+                        return Err(PatchError::Conflict {
+                            path: path.clone(),
+                            expected: Box::new(old.clone()),
+                            found: Box::new(slot.clone()),
+                        });
+                    }
+                    slot
+                }
+                _ => return Err(PatchError::NotFound { path: path.clone() }),
+            };
+            *slot = new.clone();
+        }
+        PatchOp::Insert { path, index, value } => {
+            let list = list_mut(doc, path)?;
+            if *index > list.len() {
+                return Err(PatchError::OutOfRange { path: path.clone(), index: *index, len: list.len() });
+            }
+            list.insert(*index, value.clone());
+        }
+        PatchOp::Remove { path, index, value } => {
+            let list = list_mut(doc, path)?;
+            let found = list
+                .get(*index)
+                .ok_or(PatchError::OutOfRange { path: path.clone(), index: *index, len: list.len() })?;
+            if found != value {
+                let at = format!("{path}[{index}]");
+                return Err(PatchError::Conflict { path: at, expected: Box::new(value.clone()), found: Box::new(found.clone()) });
+            }
+            list.remove(*index);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,377 @@
+//! Scene loading errors with source locations.
+
+use crate::{Diagnostic, MigrationError, PrefabError, SceneFormat};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Lines of context shown above and below the offending line in a code frame
+const FRAME_CONTEXT: usize = 2;
+
+/// Where in a scene document an error was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: Option<PathBuf>,
+    /// 1-based line
+    pub line: usize,
+    /// 1-based column, counted in bytes as serde_json does
+    pub column: usize,
+    /// JSON path of the innermost value at this position, e.g. `level.boxes[12].size`
+    pub json_path: String,
+    /// Numbered source lines around the position with a caret under the column
+    pub snippet: String,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}:{}", file.display(), self.line, self.column),
+            None => write!(f, "<scene>:{}:{}", self.line, self.column),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SceneError {
+    #[error("failed to read {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The schema version couldn't be upgraded to the one this build reads
+    #[error("{}: {source}", display_file(file))]
+    Migration {
+        file: Option<PathBuf>,
+        #[source]
+        source: MigrationError,
+    },
+    /// A prefab include or placement couldn't be resolved
+    #[error("{}: {source}", display_file(file))]
+    Prefab {
+        file: Option<PathBuf>,
+        #[source]
+        source: PrefabError,
+    },
+    /// A RON, TOML or binary document couldn't be encoded or decoded, or didn't match the scene types
+    /// where no source position is known
+    #[error("{}: {format}: {message}", display_file(file))]
+    Format { file: Option<PathBuf>, format: SceneFormat, message: String },
+    /// The document is not well-formed JSON, RON or TOML
+    #[error("{location}: syntax error: {message}")]
+    Syntax { message: String, location: Box<Location> },
+    /// Well-formed JSON that doesn't match the scene types
+    #[error("{location}: schema error at `{}`: {message}", display_path(&location.json_path))]
+    Schema { message: String, location: Box<Location> },
+    /// The scene parsed but [`FpsScene::validate`](crate::FpsScene::validate) reported errors
+    #[error("{}: {} semantic error(s), first: {}", display_file(file), count_errors(diagnostics), first_error(diagnostics))]
+    Semantic {
+        file: Option<PathBuf>,
+        diagnostics: Vec<Diagnostic>,
+        /// Location of the first error, when its path could be found in the source
+        location: Option<Box<Location>>,
+    },
+}
+
+impl SceneError {
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            SceneError::Io { .. } | SceneError::Migration { .. } | SceneError::Prefab { .. } | SceneError::Format { .. } => {
+                None
+            }
+            SceneError::Syntax { location, .. } | SceneError::Schema { location, .. } => Some(location),
+            SceneError::Semantic { location, .. } => location.as_deref(),
+        }
+    }
+
+    pub fn json_path(&self) -> Option<&str> {
+        self.location().map(|l| l.json_path.as_str())
+    }
+
+    /// Multi-line report with the code frame, for CLI and log output
+    pub fn report(&self) -> String {
+        let mut out = format!("error: {self}");
+        if let SceneError::Semantic { diagnostics, .. } = self {
+            for d in diagnostics {
+                out.push_str(&format!("\n  {d}"));
+            }
+        }
+        if let Some(loc) = self.location() {
+            out.push_str(&format!("\n  --> {loc}\n{}", loc.snippet));
+        }
+        out
+    }
+
+    pub(crate) fn from_json(err: serde_json::Error, src: &str, file: Option<&Path>) -> Self {
+        let line = err.line();
+        let column = err.column();
+        let full = err.to_string();
+        let message = match full.rsplit_once(" at line ") {
+            Some((m, _)) => m.to_string(),
+            None => full,
+        };
+        let offset = offset_of(src, line, column);
+        let json_path = path_at(src, offset);
+        let location = Box::new(Location {
+            file: file.map(Path::to_path_buf),
+            line: line.max(1),
+            column: column.max(1),
+            json_path,
+            snippet: code_frame(src, line.max(1), column.max(1)),
+        });
+        match err.classify() {
+            serde_json::error::Category::Data => SceneError::Schema { message, location },
+            _ => SceneError::Syntax { message, location },
+        }
+    }
+
+    /// Syntax error at a 1-based line and column of `src`
+    pub(crate) fn syntax_at(message: String, src: &str, file: Option<&Path>, line: usize, column: usize) -> Self {
+        let (line, column) = (line.max(1), column.max(1));
+        let location = Box::new(Location {
+            file: file.map(Path::to_path_buf),
+            line,
+            column,
+            json_path: path_at(src, offset_of(src, line, column)),
+            snippet: code_frame(src, line, column),
+        });
+        SceneError::Syntax { message, location }
+    }
+
+    pub(crate) fn syntax_at_offset(message: String, src: &str, file: Option<&Path>, offset: usize) -> Self {
+        let (line, column) = line_col(src, offset);
+        Self::syntax_at(message, src, file, line, column)
+    }
+
+    /// Attach `file` to errors raised before the path was known
+    pub(crate) fn with_file(mut self, path: &Path) -> Self {
+        if let SceneError::Format { file, .. } = &mut self {
+            file.get_or_insert_with(|| path.to_path_buf());
+        }
+        self
+    }
+
+    /// Turn an error found in a JSON rendering of a `format` document into one without a line and
+    /// column, which would point into text the user never saw
+    pub(crate) fn without_location(self, format: SceneFormat) -> Self {
+        match self {
+            SceneError::Syntax { message, location } | SceneError::Schema { message, location } => SceneError::Format {
+                file: location.file,
+                format,
+                message: format!("at `{}`: {message}", display_path(&location.json_path)),
+            },
+            other => other,
+        }
+    }
+
+    pub(crate) fn semantic(diagnostics: Vec<Diagnostic>, src: Option<&str>, file: Option<&Path>) -> Self {
+        let location = diagnostics
+            .iter()
+            .find(|d| d.is_error())
+            .and_then(|d| locate(src?, &d.path, file))
+            .map(Box::new);
+        SceneError::Semantic { file: file.map(Path::to_path_buf), diagnostics, location }
+    }
+}
+
+fn display_path(p: &str) -> &str {
+    if p.is_empty() { "(root)" } else { p }
+}
+
+fn display_file(file: &Option<PathBuf>) -> String {
+    file.as_ref().map_or_else(|| "<scene>".to_string(), |f| f.display().to_string())
+}
+
+fn count_errors(diags: &[Diagnostic]) -> usize {
+    diags.iter().filter(|d| d.is_error()).count()
+}
+
+fn first_error(diags: &[Diagnostic]) -> String {
+    diags.iter().find(|d| d.is_error()).map(|d| format!("{}: {}", d.path, d.message)).unwrap_or_default()
+}
+
+/// Location of the value at `json_path`, if present in `src`
+pub fn locate(src: &str, json_path: &str, file: Option<&Path>) -> Option<Location> {
+    let span = value_spans(src).into_iter().find(|s| s.path == json_path)?;
+    let (line, column) = line_col(src, span.start);
+    Some(Location {
+        file: file.map(Path::to_path_buf),
+        line,
+        column,
+        json_path: json_path.to_string(),
+        snippet: code_frame(src, line, column),
+    })
+}
+
+/// Render numbered lines around `line` with a caret under `column` (both 1-based; `column` counts bytes)
+pub fn code_frame(src: &str, line: usize, column: usize) -> String {
+    let lines: Vec<&str> = src.lines().collect();
+    if lines.is_empty() {
+        return String::new();
+    }
+    let line = line.clamp(1, lines.len());
+    let first = line.saturating_sub(FRAME_CONTEXT).max(1);
+    let last = (line + FRAME_CONTEXT).min(lines.len());
+    let width = last.to_string().len();
+
+    let mut out = String::new();
+    for n in first..=last {
+        out.push_str(&format!("{n:>width$} | {}\n", lines[n - 1]));
+        if n == line {
+            // one pad character per character before the column, not per byte
+            let text = lines[n - 1];
+            let mut end = column.saturating_sub(1).min(text.len());
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            let pad: String = text[..end].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+            out.push_str(&format!("{:>width$} | {pad}^\n", ""));
+        }
+    }
+    out
+}
+
+fn offset_of(src: &str, line: usize, column: usize) -> usize {
+    if line == 0 {
+        return src.len();
+    }
+    let start: usize = src.split_inclusive('\n').take(line - 1).map(str::len).sum();
+    (start + column.saturating_sub(1)).min(src.len())
+}
+
+fn line_col(src: &str, offset: usize) -> (usize, usize) {
+    let before = &src[..offset.min(src.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+/// JSON path of the innermost value whose span contains `offset`
+fn path_at(src: &str, offset: usize) -> String {
+    value_spans(src)
+        .into_iter()
+        .filter(|s| s.start <= offset && offset <= s.end)
+        .max_by_key(|s| s.start)
+        .map(|s| s.path)
+        .unwrap_or_default()
+}
+
+struct Span {
+    path: String,
+    start: usize,
+    end: usize,
+}
+
+/// Byte spans of every value in `src`, keyed by JSON path. Stops quietly at the first syntax error,
+/// leaving unfinished containers open to the end of the input.
+fn value_spans(src: &str) -> Vec<Span> {
+    let mut sc = Scanner { b: src.as_bytes(), pos: 0, spans: Vec::new() };
+    sc.value(String::new());
+    sc.spans
+}
+
+struct Scanner<'a> {
+    b: &'a [u8],
+    pos: usize,
+    spans: Vec<Span>,
+}
+
+impl Scanner<'_> {
+    fn ws(&mut self) {
+        while self.pos < self.b.len() && self.b[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.b.get(self.pos).copied()
+    }
+
+    fn value(&mut self, path: String) -> Option<()> {
+        self.ws();
+        let idx = self.spans.len();
+        self.spans.push(Span { path: path.clone(), start: self.pos, end: self.b.len() });
+        let ok = match self.peek()? {
+            b'{' => self.object(&path),
+            b'[' => self.array(&path),
+            b'"' => self.string().map(|_| ()),
+            _ => self.scalar(),
+        };
+        if ok.is_some() {
+            self.spans[idx].end = self.pos;
+        }
+        ok
+    }
+
+    fn object(&mut self, path: &str) -> Option<()> {
+        self.pos += 1;
+        loop {
+            self.ws();
+            match self.peek()? {
+                b'}' => {
+                    self.pos += 1;
+                    return Some(());
+                }
+                b',' => self.pos += 1,
+                b'"' => {
+                    let key = self.string()?;
+                    self.ws();
+                    if self.peek()? != b':' {
+                        return None;
+                    }
+                    self.pos += 1;
+                    let child = if path.is_empty() { key } else { format!("{path}.{key}") };
+                    self.value(child)?;
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn array(&mut self, path: &str) -> Option<()> {
+        self.pos += 1;
+        let mut i = 0;
+        loop {
+            self.ws();
+            match self.peek()? {
+                b']' => {
+                    self.pos += 1;
+                    return Some(());
+                }
+                b',' => self.pos += 1,
+                _ => {
+                    self.value(format!("{path}[{i}]"))?;
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let start = self.pos + 1;
+        self.pos += 1;
+        while let Some(c) = self.peek() {
+            match c {
+                b'\\' => self.pos += 2,
+                b'"' => {
+                    let s = String::from_utf8_lossy(&self.b[start..self.pos]).into_owned();
+                    self.pos += 1;
+                    return Some(s);
+                }
+                _ => self.pos += 1,
+            }
+        }
+        None
+    }
+
+    fn scalar(&mut self) -> Option<()> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if matches!(c, b',' | b'}' | b']') || c.is_ascii_whitespace() {
+                break;
+            }
+            self.pos += 1;
+        }
+        (self.pos > start).then_some(())
+    }
+}
@@ -0,0 +1,258 @@
+//! Scene file formats besides JSON: RON and TOML for hand authoring, and a compact binary encoding
+//! for shipping builds.
+//!
+//! Every format decodes into the same document tree as JSON, so schema migrations, prefab includes
+//! and validation work identically. The binary encoding is a [`BINARY_MAGIC`] header, a version byte
+//! and a MessagePack payload.
+
+use crate::{load_scene, save_scene, to_string_pretty, FpsScene, SceneError};
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// First bytes of every binary scene
+pub const BINARY_MAGIC: [u8; 4] = *b"KFSB";
+
+/// Layout version written after [`BINARY_MAGIC`]
+pub const BINARY_VERSION: u8 = 1;
+
+const INDENT: &str = "  ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SceneFormat {
+    Json,
+    Ron,
+    Toml,
+    Binary,
+}
+
+impl SceneFormat {
+    pub const ALL: [SceneFormat; 4] = [SceneFormat::Json, SceneFormat::Ron, SceneFormat::Toml, SceneFormat::Binary];
+
+    /// File extension without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            SceneFormat::Json => "json",
+            SceneFormat::Ron => "ron",
+            SceneFormat::Toml => "toml",
+            SceneFormat::Binary => "kfsb",
+        }
+    }
+
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::ALL.into_iter().find(|f| f.extension() == ext)
+    }
+
+    /// Guess the format from the content: the binary header, then the first significant character
+    pub fn sniff(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&BINARY_MAGIC) {
+            return SceneFormat::Binary;
+        }
+        let text = bytes.trim_ascii_start();
+        match text.first() {
+            Some(b'{') => SceneFormat::Json,
+            Some(b'(' | b'/') => SceneFormat::Ron,
+            _ if text.starts_with(b"#![") => SceneFormat::Ron,
+            _ => SceneFormat::Toml,
+        }
+    }
+
+    /// The binary header always wins; otherwise the extension, falling back to [`SceneFormat::sniff`]
+    pub fn detect(path: &Path, bytes: &[u8]) -> Self {
+        if bytes.starts_with(&BINARY_MAGIC) {
+            return SceneFormat::Binary;
+        }
+        Self::from_extension(path).unwrap_or_else(|| Self::sniff(bytes))
+    }
+}
+
+impl fmt::Display for SceneFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SceneFormat::Json => "JSON",
+            SceneFormat::Ron => "RON",
+            SceneFormat::Toml => "TOML",
+            SceneFormat::Binary => "binary",
+        })
+    }
+}
+
+/// Serialize a scene in any format. Text formats share the key order, float formatting and
+/// omitted defaults of [`to_string_pretty`].
+pub fn encode_scene(scene: &FpsScene, format: SceneFormat) -> Result<Vec<u8>, SceneError> {
+    let fail = |message: String| SceneError::Format { file: None, format, message };
+    match format {
+        SceneFormat::Json => Ok(to_string_pretty(scene).into_bytes()),
+        SceneFormat::Ron => {
+            let mut out = String::new();
+            write_ron(&mut out, &canonical_value(scene), 0);
+            out.push('\n');
+            Ok(out.into_bytes())
+        }
+        SceneFormat::Toml => toml::to_string(&canonical_value(scene)).map(String::into_bytes).map_err(|e| fail(e.to_string())),
+        SceneFormat::Binary => {
+            let mut out = BINARY_MAGIC.to_vec();
+            out.push(BINARY_VERSION);
+            rmp_serde::encode::write_named(&mut out, scene).map_err(|e| fail(e.to_string()))?;
+            Ok(out)
+        }
+    }
+}
+
+/// Decode a non-JSON document into the JSON tree the loader migrates and deserializes
+pub(crate) fn decode_value(bytes: &[u8], format: SceneFormat, file: Option<&Path>) -> Result<Value, SceneError> {
+    let fail = |message: String| SceneError::Format { file: file.map(Path::to_path_buf), format, message };
+    let text = || std::str::from_utf8(bytes).map_err(|e| fail(e.to_string()));
+    match format {
+        SceneFormat::Json => {
+            let src = text()?;
+            serde_json::from_str(src).map_err(|e| SceneError::from_json(e, src, file))
+        }
+        SceneFormat::Ron => {
+            let src = text()?;
+            ron::from_str(src).map_err(|e| {
+                SceneError::syntax_at(e.code.to_string(), src, file, e.span.start.line, e.span.start.col)
+            })
+        }
+        SceneFormat::Toml => {
+            let src = text()?;
+            toml::from_str(src).map_err(|e| {
+                let offset = e.span().map_or(0, |s| s.start);
+                SceneError::syntax_at_offset(e.message().to_string(), src, file, offset)
+            })
+        }
+        SceneFormat::Binary => {
+            let payload = bytes.strip_prefix(&BINARY_MAGIC).ok_or_else(|| fail("missing header".to_string()))?;
+            match payload.split_first() {
+                Some((&BINARY_VERSION, body)) => rmp_serde::from_slice(body).map_err(|e| fail(e.to_string())),
+                Some((v, _)) => Err(fail(format!("unsupported binary version {v}, expected {BINARY_VERSION}"))),
+                None => Err(fail("truncated header".to_string())),
+            }
+        }
+    }
+}
+
+/// Write a scene to `path` in the given format
+pub fn save_scene_as<P: AsRef<Path>>(scene: &FpsScene, path: P, format: SceneFormat) -> Result<(), SceneError> {
+    let path = path.as_ref();
+    let bytes = encode_scene(scene, format).map_err(|e| e.with_file(path))?;
+    fs::write(path, bytes).map_err(|source| SceneError::Io { path: path.to_path_buf(), source })
+}
+
+/// Load a scene in any format and write it to `to`, choosing the output format from its extension.
+/// `includes` are rewritten relative to the output file, so they keep naming the same libraries.
+pub fn convert_scene<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<(), SceneError> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    let mut scene = load_scene(from)?;
+    let (src_dir, dst_dir) = (dir_of(from), dir_of(to));
+    scene.includes = scene.includes.iter().map(|inc| rebase(&src_dir.join(inc), dst_dir)).collect();
+    save_scene(&scene, to)
+}
+
+fn dir_of(file: &Path) -> &Path {
+    file.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."))
+}
+
+/// `path` relative to `dir` with `/` separators, or absolute when they share no root
+fn rebase(path: &Path, dir: &Path) -> String {
+    let (Ok(path), Ok(dir)) = (fs::canonicalize(path), fs::canonicalize(dir)) else {
+        return path.display().to_string();
+    };
+    let common = path.components().zip(dir.components()).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return path.display().to_string();
+    }
+    let ups = dir.components().count() - common;
+    let rest = path.components().skip(common).map(|c| c.as_os_str().to_string_lossy().into_owned());
+    std::iter::repeat_n("..".to_string(), ups).chain(rest).collect::<Vec<_>>().join("/")
+}
+
+/// The scene as a JSON tree in declaration order, with floats parsed back from their shortest `f32` form
+pub(crate) fn canonical_value(scene: &FpsScene) -> Value {
+    let compact = serde_json::to_string(scene).expect("scene serialization is infallible");
+    serde_json::from_str(&compact).expect("serialized scene is valid JSON")
+}
+
+fn write_ron(out: &mut String, v: &Value, depth: usize) {
+    match v {
+        Value::Null => out.push_str("None"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::String(s) => write_ron_str(out, s),
+        Value::Array(items) if items.iter().all(is_scalar) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_ron(out, item, depth);
+            }
+            out.push(']');
+        }
+        Value::Array(items) => {
+            out.push_str("[\n");
+            for item in items {
+                push_indent(out, depth + 1);
+                write_ron(out, item, depth + 1);
+                out.push_str(",\n");
+            }
+            push_indent(out, depth);
+            out.push(']');
+        }
+        Value::Object(map) if map.is_empty() => out.push_str("{}"),
+        Value::Object(map) => {
+            // struct syntax when every key is an identifier, map syntax otherwise
+            let as_struct = map.keys().all(|k| is_ident(k));
+            out.push_str(if as_struct { "(\n" } else { "{\n" });
+            for (key, value) in map {
+                push_indent(out, depth + 1);
+                if as_struct {
+                    out.push_str(key);
+                } else {
+                    write_ron_str(out, key);
+                }
+                out.push_str(": ");
+                write_ron(out, value, depth + 1);
+                out.push_str(",\n");
+            }
+            push_indent(out, depth);
+            out.push(if as_struct { ')' } else { '}' });
+        }
+    }
+}
+
+fn write_ron_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn push_indent(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push_str(INDENT);
+    }
+}
+
+fn is_scalar(v: &Value) -> bool {
+    !matches!(v, Value::Array(_) | Value::Object(_))
+}
+
+fn is_ident(s: &str) -> bool {
+    if matches!(s, "true" | "false" | "None" | "Some" | "inf" | "NaN") {
+        return false;
+    }
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c == '_' || c.is_ascii_alphabetic()) && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
@@ -0,0 +1,318 @@
+//! Seeded rooms-and-corridors level generator.
+//!
+//! Rooms are placed at random on a 1 m tile grid and joined by L-shaped corridors along a minimum
+//! spanning tree, so every room is reachable. Open tiles become floor and ceiling slabs, solid tiles
+//! bordering them become walls, and both are merged into as few boxes as possible. The same seed
+//! and parameters always produce the same scene.
+
+use crate::{FpsScene, FpsSceneBuilder, GoalKind, WeaponKind, MAX_LIGHTS};
+
+/// Edge length of a grid tile in meters
+const TILE: f32 = 1.0;
+/// Half-thickness of floor and ceiling slabs
+const SLAB_HALF: f32 = 0.25;
+/// Solid tiles kept between rooms; corridors cut through them
+const ROOM_GAP: i32 = 3;
+const ENEMY_Y: f32 = 1.0;
+
+const FLOOR_COLOR: [f32; 3] = [0.35, 0.33, 0.3];
+const WALL_COLOR: [f32; 3] = [0.55, 0.55, 0.6];
+const CEILING_COLOR: [f32; 3] = [0.25, 0.25, 0.28];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorParams {
+    /// Rooms to place; fewer are generated if they don't fit
+    pub rooms: usize,
+    /// Smallest and largest room side in meters
+    pub room_size: (f32, f32),
+    pub corridor_width: f32,
+    pub ceiling_height: f32,
+    /// Enemies per 100 m² of room floor; the start room stays empty
+    pub enemy_density: f32,
+}
+
+impl Default for GeneratorParams {
+    fn default() -> Self {
+        Self { rooms: 6, room_size: (5.0, 10.0), corridor_width: 2.0, ceiling_height: 4.0, enemy_density: 2.0 }
+    }
+}
+
+/// Generate a complete, valid scene from `seed`
+pub fn generate_level(seed: u64, params: &GeneratorParams) -> FpsScene {
+    let mut rng = Rng::new(seed);
+    let min_side = (params.room_size.0 / TILE).round().max(3.0) as i32;
+    let max_side = (params.room_size.1 / TILE).round().max(min_side as f32) as i32;
+    let corridor = (params.corridor_width / TILE).round().max(1.0) as i32;
+    let rooms_wanted = params.rooms.max(1);
+
+    let per_row = (rooms_wanted as f32).sqrt().ceil() as i32;
+    let side = per_row * (max_side + ROOM_GAP + 2) + 2;
+    let mut grid = Grid::new(side, side);
+
+    let mut rooms: Vec<Rect> = Vec::new();
+    for _ in 0..rooms_wanted * 50 {
+        if rooms.len() == rooms_wanted {
+            break;
+        }
+        let w = rng.range(min_side, max_side + 1);
+        let h = rng.range(min_side, max_side + 1);
+        let r = Rect { x: rng.range(1, side - w - 1), z: rng.range(1, side - h - 1), w, h };
+        if rooms.iter().all(|o| !r.grown(ROOM_GAP).overlaps(o)) {
+            rooms.push(r);
+        }
+    }
+    for r in &rooms {
+        grid.fill(r, Cell::Room);
+    }
+    for (a, b) in spanning_tree(&rooms) {
+        carve_corridor(&mut grid, rooms[a].center(), rooms[b].center(), corridor, rng.chance(0.5));
+    }
+
+    let h = params.ceiling_height;
+    let mut b = FpsSceneBuilder::new(format!("generated_{seed:016x}"));
+    for r in grid.rects(|x, z| grid.get(x, z) != Cell::Solid) {
+        let (center, half) = grid.world_rect(&r);
+        b = b.box_at([center[0], -SLAB_HALF, center[1]], [half[0], SLAB_HALF, half[1]]).color(FLOOR_COLOR);
+        b = b.box_at([center[0], h + SLAB_HALF, center[1]], [half[0], SLAB_HALF, half[1]]).color(CEILING_COLOR);
+    }
+    for r in grid.rects(|x, z| grid.is_wall(x, z)) {
+        let (center, half) = grid.world_rect(&r);
+        b = b.box_at([center[0], 0.5 * h, center[1]], [half[0], 0.5 * h, half[1]]).color(WALL_COLOR);
+    }
+
+    let start = 0;
+    // the goal goes in the room farthest from the start
+    let goal = (0..rooms.len()).max_by_key(|&i| rooms[i].center_distance2(&rooms[start])).unwrap_or(start);
+    let [sx, sz] = grid.world(rooms[start].center());
+    b = b.spawn([sx, 1.5, sz], 0.0).weapon("rifle", WeaponKind::Hitscan, 12.0, 6.0);
+    let [gx, gz] = grid.world(rooms[goal].center());
+    b = b.goal(GoalKind::Extract, [gx, 0.5, gz]);
+
+    for (i, r) in rooms.iter().enumerate().take(MAX_LIGHTS) {
+        let [x, z] = grid.world(r.center());
+        let warm = 0.8 + 0.2 * rng.unit();
+        b = b.point_light([x, h - 0.5, z], [1.0, warm, warm * 0.85], 1.0 + 0.1 * (i % 3) as f32);
+    }
+
+    let mut enemies = 0;
+    for r in rooms.iter().enumerate().filter(|&(i, _)| i != start).map(|(_, r)| r) {
+        let area = (r.w * r.h) as f32 * TILE * TILE;
+        let expected = area * params.enemy_density / 100.0;
+        let count = expected.floor() as usize + usize::from(rng.chance(expected.fract()));
+        for _ in 0..count {
+            let route = patrol_route(&mut rng, &grid, r);
+            b = b.enemy("grunt", route[0]).patrol(&route);
+            enemies += 1;
+        }
+    }
+    if goal != start {
+        // reinforcements when the player reaches the exit
+        b = b.trigger([gx, 1.0, gz], [1.0, 1.0, 1.0], format!("spawn_wave:grunt:{}", 1 + enemies / 3));
+    }
+    let middle: Vec<usize> = (0..rooms.len()).filter(|&i| i != start && i != goal).collect();
+    if !middle.is_empty() {
+        let [kx, kz] = grid.world(rooms[middle[rng.range(0, middle.len() as i32) as usize]].center());
+        b = b.trigger([kx, 1.0, kz], [0.5, 1.0, 0.5], "collect_key:red");
+    }
+
+    b.build_unchecked()
+}
+
+/// Rectangular loop inside the room, kept a tile away from the walls
+fn patrol_route(rng: &mut Rng, grid: &Grid, r: &Rect) -> Vec<[f32; 3]> {
+    let x0 = rng.range(r.x + 1, r.x + r.w / 2);
+    let z0 = rng.range(r.z + 1, r.z + r.h / 2);
+    let x1 = rng.range(r.x + r.w / 2, r.x + r.w - 1);
+    let z1 = rng.range(r.z + r.h / 2, r.z + r.h - 1);
+    let p = |x: i32, z: i32| {
+        let [wx, wz] = grid.world((x, z));
+        [wx, ENEMY_Y, wz]
+    };
+    let mut route = vec![p(x0, z0), p(x1, z0), p(x1, z1), p(x0, z1)];
+    route.rotate_left(rng.range(0, 4) as usize);
+    route
+}
+
+/// Edges of a minimum spanning tree over room centers (Prim's algorithm)
+fn spanning_tree(rooms: &[Rect]) -> Vec<(usize, usize)> {
+    let mut edges = Vec::new();
+    if rooms.is_empty() {
+        return edges;
+    }
+    let mut in_tree = vec![false; rooms.len()];
+    in_tree[0] = true;
+    for _ in 1..rooms.len() {
+        let best = (0..rooms.len())
+            .filter(|&a| in_tree[a])
+            .flat_map(|a| (0..rooms.len()).filter(|&b| !in_tree[b]).map(move |b| (a, b)))
+            .min_by_key(|&(a, b)| rooms[a].center_distance2(&rooms[b]));
+        let Some((a, b)) = best else { break };
+        in_tree[b] = true;
+        edges.push((a, b));
+    }
+    edges
+}
+
+fn carve_corridor(grid: &mut Grid, from: (i32, i32), to: (i32, i32), width: i32, x_first: bool) {
+    let corner = if x_first { (to.0, from.1) } else { (from.0, to.1) };
+    for (a, b) in [(from, corner), (corner, to)] {
+        let r = Rect {
+            x: a.0.min(b.0) - width / 2,
+            z: a.1.min(b.1) - width / 2,
+            w: (a.0 - b.0).abs() + width,
+            h: (a.1 - b.1).abs() + width,
+        };
+        grid.fill_where(&r, Cell::Corridor, |c| c == Cell::Solid);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    x: i32,
+    z: i32,
+    w: i32,
+    h: i32,
+}
+
+impl Rect {
+    fn grown(&self, by: i32) -> Rect {
+        Rect { x: self.x - by, z: self.z - by, w: self.w + 2 * by, h: self.h + 2 * by }
+    }
+
+    fn overlaps(&self, o: &Rect) -> bool {
+        self.x < o.x + o.w && o.x < self.x + self.w && self.z < o.z + o.h && o.z < self.z + self.h
+    }
+
+    fn center(&self) -> (i32, i32) {
+        (self.x + self.w / 2, self.z + self.h / 2)
+    }
+
+    fn center_distance2(&self, o: &Rect) -> i32 {
+        let (a, b) = (self.center(), o.center());
+        (a.0 - b.0).pow(2) + (a.1 - b.1).pow(2)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    Solid,
+    Room,
+    Corridor,
+}
+
+struct Grid {
+    w: i32,
+    h: i32,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    fn new(w: i32, h: i32) -> Self {
+        Self { w, h, cells: vec![Cell::Solid; (w * h) as usize] }
+    }
+
+    fn get(&self, x: i32, z: i32) -> Cell {
+        if x < 0 || z < 0 || x >= self.w || z >= self.h { Cell::Solid } else { self.cells[(z * self.w + x) as usize] }
+    }
+
+    fn set(&mut self, x: i32, z: i32, c: Cell) {
+        if x >= 0 && z >= 0 && x < self.w && z < self.h {
+            self.cells[(z * self.w + x) as usize] = c;
+        }
+    }
+
+    fn fill(&mut self, r: &Rect, c: Cell) {
+        self.fill_where(r, c, |_| true);
+    }
+
+    fn fill_where(&mut self, r: &Rect, c: Cell, pred: impl Fn(Cell) -> bool) {
+        // keep the outermost ring solid so every open tile has a wall around it
+        for z in r.z.max(1)..(r.z + r.h).min(self.h - 1) {
+            for x in r.x.max(1)..(r.x + r.w).min(self.w - 1) {
+                if pred(self.get(x, z)) {
+                    self.set(x, z, c);
+                }
+            }
+        }
+    }
+
+    /// Solid tile touching an open one, including diagonally
+    fn is_wall(&self, x: i32, z: i32) -> bool {
+        self.get(x, z) == Cell::Solid && (-1..=1).any(|dz| (-1..=1).any(|dx| self.get(x + dx, z + dz) != Cell::Solid))
+    }
+
+    /// Cover the matching tiles with rectangles, greedily growing each one right and then down
+    fn rects(&self, pred: impl Fn(i32, i32) -> bool) -> Vec<Rect> {
+        let mut used = vec![false; self.cells.len()];
+        let free = |used: &[bool], x: i32, z: i32| pred(x, z) && !used[(z * self.w + x) as usize];
+        let mut out = Vec::new();
+        for z in 0..self.h {
+            for x in 0..self.w {
+                if !free(&used, x, z) {
+                    continue;
+                }
+                let mut w = 1;
+                while x + w < self.w && free(&used, x + w, z) {
+                    w += 1;
+                }
+                let mut h = 1;
+                while z + h < self.h && (x..x + w).all(|i| free(&used, i, z + h)) {
+                    h += 1;
+                }
+                for dz in 0..h {
+                    for dx in 0..w {
+                        used[((z + dz) * self.w + x + dx) as usize] = true;
+                    }
+                }
+                out.push(Rect { x, z, w, h });
+            }
+        }
+        out
+    }
+
+    /// World XZ of a tile center; the grid is centered on the origin
+    fn world(&self, (x, z): (i32, i32)) -> [f32; 2] {
+        [(x as f32 + 0.5 - 0.5 * self.w as f32) * TILE, (z as f32 + 0.5 - 0.5 * self.h as f32) * TILE]
+    }
+
+    /// World XZ center and half-extents of a tile rectangle
+    fn world_rect(&self, r: &Rect) -> ([f32; 2], [f32; 2]) {
+        let [x0, z0] = self.world((r.x, r.z));
+        let half = [0.5 * r.w as f32 * TILE, 0.5 * r.h as f32 * TILE];
+        ([x0 - 0.5 * TILE + half[0], z0 - 0.5 * TILE + half[1]], half)
+    }
+}
+
+/// SplitMix64; small, fast and identical on every platform
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `lo..hi`; `lo` when the range is empty
+    fn range(&mut self, lo: i32, hi: i32) -> i32 {
+        if hi <= lo {
+            return lo;
+        }
+        lo + (self.next_u64() % (hi - lo) as u64) as i32
+    }
+
+    /// Uniform in `[0, 1)`
+    fn unit(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn chance(&mut self, p: f32) -> bool {
+        self.unit() < p
+    }
+}
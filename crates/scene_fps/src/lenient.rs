@@ -0,0 +1,351 @@
+//! Forgiving loading of machine-generated scenes.
+//!
+//! LLM output tends to arrive wrapped in markdown fences, with comments and trailing commas, with
+//! fields left out, or with more entities than the prompt allowed. [`parse_scene_lenient`] cleans the
+//! text up, fills in defaults, clamps counts and values to [`RepairLimits`], and reports each repair.
+
+use crate::{parse_scene_document, schema_tag, FpsScene, LoadOptions, SceneError, SceneFormat, CURRENT_SCHEMA_VERSION, MAX_LIGHTS};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Bounds enforced by the lenient loader
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepairLimits {
+    pub max_boxes: usize,
+    pub max_enemies: usize,
+    pub max_lights: usize,
+    pub max_triggers: usize,
+    pub max_particles_per_system: u32,
+    /// Largest absolute value of any position coordinate
+    pub max_coord: f32,
+    /// Range of box and trigger half-extents
+    pub min_half_extent: f32,
+    pub max_half_extent: f32,
+    pub max_light_intensity: f32,
+}
+
+impl Default for RepairLimits {
+    fn default() -> Self {
+        Self {
+            max_boxes: 256,
+            max_enemies: 64,
+            max_lights: MAX_LIGHTS,
+            max_triggers: 64,
+            max_particles_per_system: 10_000,
+            max_coord: 1000.0,
+            min_half_extent: 0.01,
+            max_half_extent: 500.0,
+            max_light_intensity: 100.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RepairKind {
+    /// Fences, prose, comments or trailing commas removed from the text
+    Cleanup,
+    /// A missing or malformed field replaced with a default
+    Default,
+    /// Entities beyond a count limit dropped
+    Truncate,
+    /// A value moved into its allowed range
+    Clamp,
+}
+
+/// A single change made by the lenient loader
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repair {
+    pub kind: RepairKind,
+    /// JSON path of the repaired value; empty for text cleanup
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// Every repair made while loading, in the order they were made
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RepairReport {
+    pub repairs: Vec<Repair>,
+}
+
+impl RepairReport {
+    pub fn is_empty(&self) -> bool {
+        self.repairs.is_empty()
+    }
+
+    pub fn count(&self, kind: RepairKind) -> usize {
+        self.repairs.iter().filter(|r| r.kind == kind).count()
+    }
+
+    fn push(&mut self, kind: RepairKind, path: impl Into<String>, message: impl Into<String>) {
+        self.repairs.push(Repair { kind, path: path.into(), message: message.into() });
+    }
+}
+
+impl fmt::Display for RepairReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for r in &self.repairs {
+            writeln!(f, "{r}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Like [`load_scene`](crate::load_scene) for JSON, but repairs what it can instead of failing
+pub fn load_scene_lenient<P: AsRef<Path>>(path: P, limits: &RepairLimits) -> Result<(FpsScene, RepairReport), SceneError> {
+    let path = path.as_ref();
+    let src = fs::read_to_string(path).map_err(|source| SceneError::Io { path: path.to_path_buf(), source })?;
+    repair(&src, Some(path), limits)
+}
+
+/// Like [`parse_scene`](crate::parse_scene), but repairs what it can instead of failing
+pub fn parse_scene_lenient(src: &str, limits: &RepairLimits) -> Result<(FpsScene, RepairReport), SceneError> {
+    repair(src, None, limits)
+}
+
+fn repair(src: &str, file: Option<&Path>, limits: &RepairLimits) -> Result<(FpsScene, RepairReport), SceneError> {
+    let mut report = RepairReport::default();
+    let text = clean_text(src, &mut report);
+    // positions refer to the cleaned text
+    let mut doc: Value = serde_json::from_str(&text).map_err(|e| SceneError::from_json(e, &text, None))?;
+    fill_defaults(&mut doc, &mut report);
+    let (mut scene, _) = parse_scene_document(doc, SceneFormat::Json, file, &LoadOptions::default())?;
+    clamp_scene(&mut scene, limits, &mut report);
+    Ok((scene, report))
+}
+
+/// Strip markdown fences and surrounding prose, comments, and trailing commas
+fn clean_text(src: &str, report: &mut RepairReport) -> String {
+    let mut text = src;
+    if let Some(open) = text.find("```") {
+        // skip the rest of the fence line, e.g. "```json"
+        let body = text[open + 3..].split_once('\n').map_or("", |(_, rest)| rest);
+        text = body.find("```").map_or(body, |close| &body[..close]);
+        report.push(RepairKind::Cleanup, "", "removed markdown code fence");
+    }
+    if let (Some(start), Some(end)) = (text.find('{'), text.rfind('}')) {
+        if start < end && !(text[..start].trim().is_empty() && text[end + 1..].trim().is_empty()) {
+            text = &text[start..=end];
+            report.push(RepairKind::Cleanup, "", "removed text around the JSON object");
+        }
+    }
+
+    let (text, comments) = strip_comments(text);
+    if comments > 0 {
+        report.push(RepairKind::Cleanup, "", format!("removed {comments} comment(s)"));
+    }
+    let (text, commas) = strip_trailing_commas(&text);
+    if commas > 0 {
+        report.push(RepairKind::Cleanup, "", format!("removed {commas} trailing comma(s)"));
+    }
+    text
+}
+
+fn strip_comments(src: &str) -> (String, usize) {
+    let mut out = String::with_capacity(src.len());
+    let mut count = 0;
+    let mut chars = src.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                count += 1;
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                count += 1;
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    // keep line numbers stable for error positions
+                    if c == '\n' {
+                        out.push('\n');
+                    }
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    (out, count)
+}
+
+fn strip_trailing_commas(src: &str) -> (String, usize) {
+    let mut out = String::with_capacity(src.len());
+    let mut count = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in src.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' && matches!(src[i + 1..].trim_start().chars().next(), Some('}' | ']')) {
+            count += 1;
+            continue;
+        }
+        out.push(c);
+    }
+    (out, count)
+}
+
+/// Fill fields generators commonly leave out
+fn fill_defaults(doc: &mut Value, report: &mut RepairReport) {
+    let Some(root) = doc.as_object_mut() else { return };
+
+    let meta = object_entry(root, "meta", report);
+    if !meta.get("schema").is_some_and(Value::is_string) {
+        meta.insert("schema".into(), json!(schema_tag(CURRENT_SCHEMA_VERSION)));
+        report.push(RepairKind::Default, "meta.schema", format!("set to {}", schema_tag(CURRENT_SCHEMA_VERSION)));
+    }
+    default_field(meta, "meta", "version", json!("0.1.0"), report);
+    default_field(meta, "meta", "name", json!("untitled"), report);
+
+    let render = object_entry(root, "render", report);
+    default_field(render, "render", "clearColor", json!([0.05, 0.05, 0.1, 1.0]), report);
+    fix_len(render, "render", "clearColor", 4, 1.0, report);
+
+    let player = object_entry(root, "player", report);
+    default_field(player, "player", "spawn", json!([0.0, 1.5, 0.0]), report);
+    default_field(player, "player", "yaw", json!(0.0), report);
+    default_field(player, "player", "pitch", json!(0.0), report);
+    default_field(player, "player", "move", json!({ "speed": 4.5, "run": 7.5 }), report);
+    fix_len(player, "player", "spawn", 3, 0.0, report);
+
+    let level = object_entry(root, "level", report);
+    if let Some(boxes) = level.get_mut("boxes").and_then(Value::as_array_mut) {
+        for (i, b) in boxes.iter_mut().enumerate() {
+            let Some(b) = b.as_object_mut() else { continue };
+            let path = format!("level.boxes[{i}]");
+            for key in ["pos", "size", "color"] {
+                fix_len(b, &path, key, 3, 0.0, report);
+            }
+        }
+    }
+}
+
+fn object_entry<'a>(parent: &'a mut Map<String, Value>, key: &str, report: &mut RepairReport) -> &'a mut Map<String, Value> {
+    let slot = parent.entry(key).or_insert(Value::Null);
+    if !slot.is_object() {
+        report.push(RepairKind::Default, key, "replaced with an empty object");
+        *slot = Value::Object(Map::new());
+    }
+    slot.as_object_mut().expect("just made an object")
+}
+
+fn default_field(obj: &mut Map<String, Value>, path: &str, key: &str, value: Value, report: &mut RepairReport) {
+    if obj.get(key).is_none_or(Value::is_null) {
+        report.push(RepairKind::Default, format!("{path}.{key}"), format!("missing, set to {value}"));
+        obj.insert(key.to_string(), value);
+    }
+}
+
+/// Pad or cut a numeric array to `len` elements
+fn fix_len(obj: &mut Map<String, Value>, path: &str, key: &str, len: usize, pad: f64, report: &mut RepairReport) {
+    let Some(arr) = obj.get_mut(key).and_then(Value::as_array_mut) else { return };
+    if arr.len() != len {
+        let found = arr.len();
+        arr.resize(len, json!(pad));
+        report.push(RepairKind::Default, format!("{path}.{key}"), format!("had {found} components, expected {len}"));
+    }
+}
+
+fn clamp_scene(scene: &mut FpsScene, limits: &RepairLimits, report: &mut RepairReport) {
+    truncate(&mut scene.level.boxes, limits.max_boxes, "level.boxes", report);
+    truncate(&mut scene.enemies, limits.max_enemies, "enemies", report);
+    truncate(&mut scene.lights, limits.max_lights, "lights", report);
+    truncate(&mut scene.triggers, limits.max_triggers, "triggers", report);
+
+    let c = limits.max_coord;
+    let (lo, hi) = (limits.min_half_extent, limits.max_half_extent);
+    clamp3(&mut scene.player.spawn, -c, c, "player.spawn", report);
+    for (i, b) in scene.level.boxes.iter_mut().enumerate() {
+        clamp3(&mut b.pos, -c, c, &format!("level.boxes[{i}].pos"), report);
+        clamp3(&mut b.size, lo, hi, &format!("level.boxes[{i}].size"), report);
+        clamp3(&mut b.color, 0.0, 1.0, &format!("level.boxes[{i}].color"), report);
+    }
+    for (i, l) in scene.lights.iter_mut().enumerate() {
+        clamp3(&mut l.position, -c, c, &format!("lights[{i}].position"), report);
+        clamp(&mut l.intensity, 0.0, limits.max_light_intensity, &format!("lights[{i}].intensity"), report);
+    }
+    for (i, e) in scene.enemies.iter_mut().enumerate() {
+        clamp3(&mut e.spawn, -c, c, &format!("enemies[{i}].spawn"), report);
+        for (j, p) in e.patrol.iter_mut().enumerate() {
+            clamp3(p, -c, c, &format!("enemies[{i}].patrol[{j}]"), report);
+        }
+    }
+    for (i, t) in scene.triggers.iter_mut().enumerate() {
+        clamp3(&mut t.pos, -c, c, &format!("triggers[{i}].pos"), report);
+        clamp3(&mut t.size, lo, hi, &format!("triggers[{i}].size"), report);
+    }
+    for (i, p) in scene.particles.iter_mut().enumerate() {
+        if p.count > limits.max_particles_per_system {
+            report.push(
+                RepairKind::Clamp,
+                format!("particles[{i}].count"),
+                format!("{} -> {}", p.count, limits.max_particles_per_system),
+            );
+            p.count = limits.max_particles_per_system;
+        }
+    }
+}
+
+fn truncate<T>(list: &mut Vec<T>, max: usize, path: &str, report: &mut RepairReport) {
+    if list.len() > max {
+        report.push(RepairKind::Truncate, path, format!("kept the first {max} of {}", list.len()));
+        list.truncate(max);
+    }
+}
+
+fn clamp(v: &mut f32, lo: f32, hi: f32, path: &str, report: &mut RepairReport) {
+    let c = v.clamp(lo, hi);
+    if c != *v {
+        report.push(RepairKind::Clamp, path, format!("{} -> {c}", *v));
+        *v = c;
+    }
+}
+
+fn clamp3(v: &mut [f32; 3], lo: f32, hi: f32, path: &str, report: &mut RepairReport) {
+    let c = v.map(|x| x.clamp(lo, hi));
+    if c != *v {
+        report.push(RepairKind::Clamp, path, format!("{v:?} -> {c:?}"));
+        *v = c;
+    }
+}
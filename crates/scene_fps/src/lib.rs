@@ -1,44 +1,227 @@
-use anyhow::{Context, Result};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
 use std::path::Path;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+mod behavior;
+mod builder;
+mod diff;
+mod error;
+mod format;
+mod generator;
+mod lenient;
+mod migrate;
+mod prefab;
+mod save;
+mod schema;
+mod transform;
+mod trigger;
+mod validate;
+
+pub use behavior::{Behavior, BehaviorKind, ChaseParams, FleeParams, IdleParams, PatrolParams};
+pub use builder::{FpsSceneBuilder, WALL_HALF_THICKNESS};
+pub use diff::{
+    CollectionDiff, DiffOptions, EntityChange, FieldChange, PatchError, PatchOp, SceneDiff, ScenePatch, DEFAULT_TOLERANCE,
+};
+pub use error::{code_frame, locate, Location, SceneError};
+pub use format::{convert_scene, encode_scene, save_scene_as, SceneFormat, BINARY_MAGIC, BINARY_VERSION};
+pub use generator::{generate_level, GeneratorParams};
+pub use lenient::{load_scene_lenient, parse_scene_lenient, Repair, RepairKind, RepairLimits, RepairReport};
+pub use migrate::{
+    detect_version, schema_tag, Migration, MigrationError, MigrationReport, Migrator, CURRENT_SCHEMA_VERSION, SCHEMA_PREFIX,
+};
+pub use prefab::{Prefab, PrefabError, PrefabInstance, PrefabLibrary, PrefabOverrides};
+pub use save::{save_scene, to_string_pretty};
+pub use schema::{scene_json_schema, write_scene_json_schema};
+pub use transform::{quat_mul, quat_y, rotate, Group, Quat, Rotation, QUAT_IDENTITY};
+pub use trigger::{ActionArg, ActionParseError, ActionRegistry, ArgType, TriggerAction, TriggerActionError};
+pub use validate::{has_errors, Diagnostic, Severity, SCHEMA_V0};
+
+/// Error returned when a string doesn't name a known variant of a scene enum
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseKindError {
+    pub kind: &'static str,
+    pub value: String,
+    pub expected: &'static [&'static str],
+}
+
+impl fmt::Display for ParseKindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown {} `{}`, expected one of: {}", self.kind, self.value, self.expected.join(", "))
+    }
+}
+
+impl std::error::Error for ParseKindError {}
+
+/// Declares a fieldless enum stored in scene JSON as a lowercase string
+macro_rules! string_enum {
+    ($(#[$m:meta])* $name:ident, $what:literal { $($(#[$vm:meta])* $variant:ident => $s:literal),+ $(,)? }) => {
+        $(#[$m])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ::schemars::JsonSchema)]
+        pub enum $name {
+            $($(#[$vm])* #[serde(rename = $s)] $variant),+
+        }
+
+        impl $name {
+            /// JSON spellings of all variants
+            pub const NAMES: &'static [&'static str] = &[$($s),+];
+
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $($name::$variant => $s),+
+                }
+            }
+        }
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl ::std::str::FromStr for $name {
+            type Err = $crate::ParseKindError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($s => Ok($name::$variant),)+
+                    _ => Err($crate::ParseKindError { kind: $what, value: s.to_string(), expected: Self::NAMES }),
+                }
+            }
+        }
+    };
+}
+pub(crate) use string_enum;
+
+/// `skip_serializing_if` helper so saved scenes leave out fields that still hold their default
+pub(crate) fn is_default<T: Default + PartialEq>(v: &T) -> bool {
+    *v == T::default()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FpsScene {
     pub meta: Meta,
     pub render: Render,
     pub player: Player,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub weapons: Vec<Weapon>,
     pub level: Level,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub lights: Vec<Light>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub particles: Vec<ParticleSystem>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub enemies: Vec<Enemy>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub sounds: Vec<SoundSource>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub triggers: Vec<Trigger>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub goals: Option<Goals>,
+    /// Prefab library files, relative to the scene file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub includes: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub prefabs: BTreeMap<String, Prefab>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub instances: Vec<PrefabInstance>,
+    /// Prefabs loaded from `includes`; kept apart from `prefabs` so saving writes only the scene's own
+    #[serde(skip)]
+    pub included_prefabs: BTreeMap<String, Prefab>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Meta {
     pub schema: String,   // "KengaFPSSceneV0"
     pub version: String,  // "0.1.0"
     pub name: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Render {
     #[serde(rename = "clearColor")]
     pub clear_color: [f32; 4],
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub shading: Shading,
+    /// HDR post-processing; without it the scene is drawn straight to the screen
+    #[serde(rename = "postProcess", default, skip_serializing_if = "Option::is_none")]
+    pub post_process: Option<PostProcess>,
+}
+
+string_enum! {
+    /// Lighting model the renderer draws the level with
+    #[derive(Default)]
+    Shading, "shading" {
+        /// Lambert lighting with vertex colors and textures
+        #[default]
+        Simple => "simple",
+        /// Metallic/roughness PBR for boxes and meshes that carry a [`Material`]
+        Pbr => "pbr",
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Post-processing exposure when `postProcess.exposure` is unset
+pub const DEFAULT_EXPOSURE: f32 = 1.0;
+
+/// Display gamma when `postProcess.gamma` is unset
+pub const DEFAULT_GAMMA: f32 = 2.2;
+
+/// Bloom luminance threshold when `postProcess.bloom.threshold` is unset
+pub const DEFAULT_BLOOM_THRESHOLD: f32 = 1.0;
+
+/// Bloom strength when `postProcess.bloom.intensity` is unset
+pub const DEFAULT_BLOOM_INTENSITY: f32 = 0.5;
+
+/// Bloom blur radius when `postProcess.bloom.radius` is unset
+pub const DEFAULT_BLOOM_RADIUS: f32 = 1.0;
+
+/// Chain the HDR scene goes through on its way to the screen: bloom, exposure, tonemapping,
+/// gamma and vignette, in that order
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PostProcess {
+    /// Scale on scene color before tonemapping; [`DEFAULT_EXPOSURE`] when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exposure: Option<f32>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub tonemap: Tonemap,
+    /// Display gamma; [`DEFAULT_GAMMA`] when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gamma: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bloom: Option<Bloom>,
+    /// How much the corners darken, from 0 (off) to 1
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub vignette: f32,
+}
+
+/// Glow around bright areas: a bright-pass followed by a blur, added back onto the scene
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Bloom {
+    /// Luminance above which pixels bloom; [`DEFAULT_BLOOM_THRESHOLD`] when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f32>,
+    /// Strength of the added glow; [`DEFAULT_BLOOM_INTENSITY`] when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intensity: Option<f32>,
+    /// Blur radius in half-resolution texels; [`DEFAULT_BLOOM_RADIUS`] when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f32>,
+}
+
+string_enum! {
+    /// Curve that maps HDR color into displayable range
+    #[derive(Default)]
+    Tonemap, "tonemap" {
+        /// Filmic ACES approximation
+        #[default]
+        Aces => "aces",
+        Reinhard => "reinhard",
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Player {
     pub spawn: [f32; 3],
     pub yaw: f32,
@@ -47,110 +230,133 @@ pub struct Player {
     pub r#move: Move,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Move {
     pub speed: f32,
     pub run: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Weapon {
     pub id: String,
-    pub kind: String, // "hitscan" | "projectile"
+    pub kind: WeaponKind,
     pub damage: f32,
     pub rate: f32,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub spread: Option<f32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+string_enum! {
+    WeaponKind, "weapon kind" {
+        Hitscan => "hitscan",
+        Projectile => "projectile",
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Level {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub boxes: Vec<BoxDef>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub meshes: Vec<MeshDef>,
+    /// Boxes and meshes placed relative to a parent transform; see [`Level::world_boxes`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Group>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BoxDef {
     pub pos: [f32; 3],
     pub size: [f32; 3],
-    #[serde(rename = "rotY")]
+    #[serde(rename = "rotY", default, skip_serializing_if = "is_default")]
     pub rot_y: f32,
-    #[serde(default)]
+    /// Full orientation, replacing `rotY` when present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<Rotation>,
+    #[serde(default, skip_serializing_if = "is_default")]
     pub color: [f32; 3],
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub texture: Option<String>,
+    /// Surface for PBR shading; its color and texture replace the box's own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<Material>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MeshDef {
     pub pos: [f32; 3],
     pub scale: [f32; 3],
-    #[serde(rename = "rotY")]
+    #[serde(rename = "rotY", default, skip_serializing_if = "is_default")]
     pub rot_y: f32,
+    /// Full orientation, replacing `rotY` when present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<Rotation>,
     pub file: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub material: Option<Material>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Base color of materials without `color`, which leaves their texture untinted
+pub const DEFAULT_MATERIAL_COLOR: [f32; 3] = [1.0; 3];
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Material {
-    #[serde(default)]
-    pub color: [f32; 3],
-    #[serde(default)]
+    /// Base color, multiplied with the texture; [`DEFAULT_MATERIAL_COLOR`] when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub texture: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub metallic: f32,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub roughness: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Enemy {
     pub kind: String,
     pub spawn: [f32; 3],
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub patrol: Vec<[f32; 3]>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub physics: Option<PhysicsProperties>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub behavior: Option<Behavior>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Behavior {
-    #[serde(rename = "type")]
-    pub type_: String, // "patrol", "chase", "flee", "idle"
-    #[serde(default)]
-    pub parameters: std::collections::HashMap<String, serde_json::Value>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PhysicsProperties {
     #[serde(rename = "type")]
-    pub type_: String, // "dynamic", "static", "kinematic"
-    #[serde(default)]
+    pub type_: BodyType,
+    #[serde(default, skip_serializing_if = "is_default")]
     pub mass: f32,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub friction: f32,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub restitution: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+string_enum! {
+    BodyType, "physics body type" {
+        Dynamic => "dynamic",
+        Static => "static",
+        Kinematic => "kinematic",
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SoundSource {
     pub position: [f32; 3],
     pub file: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub volume: f32,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub looping: bool,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub spatial: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Trigger {
     pub pos: [f32; 3],
     pub size: [f32; 3],
@@ -158,38 +364,209 @@ pub struct Trigger {
     pub on_enter: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Goals {
     #[serde(default)]
-    pub r#type: String, // "extract" | ...
+    pub r#type: GoalKind,
     pub point: [f32; 3],
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+string_enum! {
+    #[derive(Default)]
+    GoalKind, "goal type" {
+        #[default]
+        Extract => "extract",
+        Eliminate => "eliminate",
+        Collect => "collect",
+    }
+}
+
+/// Number of lights `FpsRenderer` uploads; extras are dropped
+pub const MAX_LIGHTS: usize = 1024;
+
+/// Distance at which point and spot lights fade out when `range` is unset
+pub const DEFAULT_LIGHT_RANGE: f32 = 25.0;
+
+/// Full-strength half-angle of spot lights without `innerAngle`, in radians
+pub const DEFAULT_SPOT_INNER_ANGLE: f32 = 0.3;
+
+/// Cone half-angle of spot lights without `outerAngle`, in radians
+pub const DEFAULT_SPOT_OUTER_ANGLE: f32 = 0.5;
+
+/// Directional lights that get a cascaded shadow map; later opt-ins cast no shadows
+pub const MAX_SHADOWED_DIRECTIONAL_LIGHTS: usize = 1;
+
+/// Point lights that get a cube shadow map; later opt-ins cast no shadows
+pub const MAX_SHADOWED_POINT_LIGHTS: usize = 4;
+
+/// Shadow map size used when a light doesn't set `shadowResolution`
+pub const DEFAULT_SHADOW_RESOLUTION: u32 = 1024;
+
+/// Largest shadow map the renderer allocates; bigger requests are clamped
+pub const MAX_SHADOW_RESOLUTION: u32 = 4096;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Light {
-    pub kind: String, // "point" | "directional"
+    pub kind: LightKind,
     pub position: [f32; 3],
     pub color: [f32; 3],
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub intensity: f32,
+    /// Distance at which a point or spot light has faded out, [`DEFAULT_LIGHT_RANGE`] when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<f32>,
+    /// Where a spot light points; required for spot lights
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<[f32; 3]>,
+    /// Angle from a spot light's axis within which it shines fully, in radians;
+    /// [`DEFAULT_SPOT_INNER_ANGLE`] when unset
+    #[serde(rename = "innerAngle", default, skip_serializing_if = "Option::is_none")]
+    pub inner_angle: Option<f32>,
+    /// Angle from the axis at which a spot light's cone ends, [`DEFAULT_SPOT_OUTER_ANGLE`] when unset
+    #[serde(rename = "outerAngle", default, skip_serializing_if = "Option::is_none")]
+    pub outer_angle: Option<f32>,
+    /// Render a shadow map for this light; see [`MAX_SHADOWED_POINT_LIGHTS`]
+    #[serde(rename = "castShadows", default, skip_serializing_if = "is_default")]
+    pub cast_shadows: bool,
+    /// Edge length of the shadow map in texels, [`DEFAULT_SHADOW_RESOLUTION`] when unset
+    #[serde(rename = "shadowResolution", default, skip_serializing_if = "Option::is_none")]
+    pub shadow_resolution: Option<u32>,
+}
+
+string_enum! {
+    LightKind, "light kind" {
+        Point => "point",
+        Directional => "directional",
+        Spot => "spot",
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ParticleSystem {
     pub position: [f32; 3],
     pub color: [f32; 3],
     pub count: u32,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub lifetime: f32,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub speed: f32,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub spread: f32,
 }
 
-/// Load scene JSON from path
-pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<FpsScene> {
-    let s = fs::read_to_string(&path).with_context(|| format!("read {}", path.as_ref().display()))?;
-    let scene: FpsScene = serde_json::from_str(&s).with_context(|| "parse scene json")?;
-    Ok(scene)
+/// Options for [`load_scene_with`]
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Fail on scenes with a newer schema than this build understands instead of loading them as-is
+    pub reject_future_versions: bool,
+    /// Fail with [`SceneError::Semantic`] if [`FpsScene::validate`] reports errors
+    pub validate: bool,
+}
+
+/// Load a scene from path, in any [`SceneFormat`]. Prefab `includes` are resolved relative to the
+/// file's directory; call [`FpsScene::flatten`] to expand prefab placements.
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<FpsScene, SceneError> {
+    load_scene_with(path, &LoadOptions::default()).map(|(scene, _)| scene)
+}
+
+/// Like [`load_scene`], but also fails with [`SceneError::Semantic`] if [`FpsScene::validate`] reports errors
+pub fn load_scene_checked<P: AsRef<Path>>(path: P) -> Result<FpsScene, SceneError> {
+    let opts = LoadOptions { validate: true, ..Default::default() };
+    load_scene_with(path, &opts).map(|(scene, _)| scene)
+}
+
+/// Load a scene, upgrading older schema versions, and report which migrations ran.
+/// The format is taken from the binary header, the extension, or the content, in that order.
+pub fn load_scene_with<P: AsRef<Path>>(path: P, opts: &LoadOptions) -> Result<(FpsScene, MigrationReport), SceneError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|source| SceneError::Io { path: path.to_path_buf(), source })?;
+    match SceneFormat::detect(path, &bytes) {
+        SceneFormat::Json => {
+            let src = String::from_utf8(bytes).map_err(|e| SceneError::Io {
+                path: path.to_path_buf(),
+                source: std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+            })?;
+            parse_scene_source(&src, Some(path), opts)
+        }
+        format => parse_scene_document(format::decode_value(&bytes, format, Some(path))?, format, Some(path), opts),
+    }
+}
+
+/// Parse scene JSON from a string
+pub fn parse_scene(src: &str) -> Result<FpsScene, SceneError> {
+    parse_scene_with(src, &LoadOptions::default()).map(|(scene, _)| scene)
+}
+
+pub fn parse_scene_with(src: &str, opts: &LoadOptions) -> Result<(FpsScene, MigrationReport), SceneError> {
+    parse_scene_source(src, None, opts)
+}
+
+/// Parse a scene held in memory in the given format
+pub fn parse_scene_as(bytes: &[u8], format: SceneFormat, opts: &LoadOptions) -> Result<(FpsScene, MigrationReport), SceneError> {
+    match format {
+        SceneFormat::Json => {
+            let src = std::str::from_utf8(bytes).map_err(|e| SceneError::Format { file: None, format, message: e.to_string() })?;
+            parse_scene_source(src, None, opts)
+        }
+        format => parse_scene_document(format::decode_value(bytes, format, None)?, format, None, opts),
+    }
+}
+
+fn parse_scene_source(src: &str, file: Option<&Path>, opts: &LoadOptions) -> Result<(FpsScene, MigrationReport), SceneError> {
+    let (scene, report) = decode_json(src, file, opts)?;
+    finish_scene(scene, report, file, opts, Some(src))
+}
+
+/// Load a document decoded from RON, TOML or binary, or repaired by the lenient loader
+pub(crate) fn parse_scene_document(
+    doc: serde_json::Value,
+    format: SceneFormat,
+    file: Option<&Path>,
+    opts: &LoadOptions,
+) -> Result<(FpsScene, MigrationReport), SceneError> {
+    // the decoders don't keep positions, so errors found in this rendering keep only the JSON path
+    let json = serde_json::to_string_pretty(&doc).unwrap_or_default();
+    let (scene, report) = decode_json(&json, file, opts).map_err(|e| e.without_location(format))?;
+    finish_scene(scene, report, file, opts, None)
+}
+
+fn decode_json(src: &str, file: Option<&Path>, opts: &LoadOptions) -> Result<(FpsScene, MigrationReport), SceneError> {
+    let from_src = || serde_json::from_str::<FpsScene>(src).map_err(|e| SceneError::from_json(e, src, file));
+
+    let mut doc: serde_json::Value = serde_json::from_str(src).map_err(|e| SceneError::from_json(e, src, file))?;
+    let report = match Migrator::new().reject_future_versions(opts.reject_future_versions).migrate(&mut doc) {
+        Ok(report) => report,
+        // let serde point at the missing or mistyped field
+        Err(MigrationError::MissingSchema) => return from_src().map(|scene| (scene, MigrationReport::default())),
+        Err(source) => return Err(SceneError::Migration { file: file.map(Path::to_path_buf), source }),
+    };
+
+    let scene = if report.is_empty() {
+        from_src()?
+    } else {
+        // error positions refer to the upgraded document, not the file on disk
+        let migrated = serde_json::to_string_pretty(&doc).unwrap_or_default();
+        serde_json::from_str(&migrated).map_err(|e| SceneError::from_json(e, &migrated, file))?
+    };
+    Ok((scene, report))
+}
+
+/// Resolve includes and validate; `src`, when the scene was read from JSON, locates semantic errors
+fn finish_scene(
+    mut scene: FpsScene,
+    report: MigrationReport,
+    file: Option<&Path>,
+    opts: &LoadOptions,
+    src: Option<&str>,
+) -> Result<(FpsScene, MigrationReport), SceneError> {
+    let base_dir = file.and_then(Path::parent).unwrap_or_else(|| Path::new("."));
+    prefab::resolve_includes(&mut scene, base_dir)?;
+
+    if opts.validate {
+        let diags = scene.validate();
+        if has_errors(&diags) {
+            return Err(SceneError::semantic(diags, src, file));
+        }
+    }
+    Ok((scene, report))
 }
@@ -0,0 +1,131 @@
+//! Schema version detection and step-by-step upgrades of raw scene documents.
+//!
+//! The format generation is the number in `meta.schema` (`KengaFPSSceneV0` is generation 0).
+//! Migration `n` upgrades a generation `n` document to `n + 1` and rewrites `meta.schema`.
+
+use serde_json::Value;
+use thiserror::Error;
+
+/// Prefix of every `meta.schema` tag
+pub const SCHEMA_PREFIX: &str = "KengaFPSSceneV";
+
+/// Generation this build reads and writes
+pub const CURRENT_SCHEMA_VERSION: u32 = 0;
+
+/// `meta.schema` tag for a generation, e.g. `KengaFPSSceneV0`
+pub fn schema_tag(version: u32) -> String {
+    format!("{SCHEMA_PREFIX}{version}")
+}
+
+/// A single upgrade from generation `from` to `from + 1`
+#[derive(Clone, Copy)]
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut Value) -> Result<(), String>,
+}
+
+impl std::fmt::Debug for Migration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "V{} -> V{}: {}", self.from, self.from + 1, self.description)
+    }
+}
+
+/// Built-in upgrades, one per generation, ordered by `from`; empty while V0 is the only generation.
+/// Bump [`CURRENT_SCHEMA_VERSION`] together with adding an entry here.
+const MIGRATIONS: &[Migration] = &[];
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MigrationError {
+    #[error("meta.schema is missing or not a string")]
+    MissingSchema,
+    #[error("unknown schema `{0}`, expected {SCHEMA_PREFIX}<n>")]
+    UnknownSchema(String),
+    #[error("scene uses schema V{found}, this build supports up to V{supported}")]
+    FutureVersion { found: u32, supported: u32 },
+    #[error("no migration from schema V{0}")]
+    MissingStep(u32),
+    #[error("migration V{} -> V{} failed: {message}", from, from + 1)]
+    StepFailed { from: u32, message: String },
+}
+
+/// What [`Migrator::migrate`] did to a document
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+    /// Descriptions of the migrations that ran, in order
+    pub applied: Vec<String>,
+}
+
+impl MigrationReport {
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty()
+    }
+}
+
+/// Read the schema generation from `meta.schema`
+pub fn detect_version(doc: &Value) -> Result<u32, MigrationError> {
+    let tag = doc
+        .get("meta")
+        .and_then(|m| m.get("schema"))
+        .and_then(Value::as_str)
+        .ok_or(MigrationError::MissingSchema)?;
+    tag.strip_prefix(SCHEMA_PREFIX)
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| MigrationError::UnknownSchema(tag.to_string()))
+}
+
+/// Runs a chain of migrations up to a target generation
+#[derive(Debug, Clone)]
+pub struct Migrator {
+    steps: Vec<Migration>,
+    target: u32,
+    reject_future: bool,
+}
+
+impl Default for Migrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Migrator {
+    /// The built-in chain, targeting [`CURRENT_SCHEMA_VERSION`]
+    pub fn new() -> Self {
+        Self::with_steps(MIGRATIONS.to_vec(), CURRENT_SCHEMA_VERSION)
+    }
+
+    pub fn with_steps(steps: Vec<Migration>, target: u32) -> Self {
+        Self { steps, target, reject_future: false }
+    }
+
+    /// Fail with [`MigrationError::FutureVersion`] instead of passing newer documents through untouched
+    pub fn reject_future_versions(mut self, reject: bool) -> Self {
+        self.reject_future = reject;
+        self
+    }
+
+    pub fn migrate(&self, doc: &mut Value) -> Result<MigrationReport, MigrationError> {
+        let from = detect_version(doc)?;
+        if from > self.target {
+            if self.reject_future {
+                return Err(MigrationError::FutureVersion { found: from, supported: self.target });
+            }
+            return Ok(MigrationReport { from, to: from, applied: Vec::new() });
+        }
+
+        let mut report = MigrationReport { from, to: from, applied: Vec::new() };
+        while report.to < self.target {
+            let v = report.to;
+            let step = self.steps.iter().find(|m| m.from == v).ok_or(MigrationError::MissingStep(v))?;
+            (step.apply)(doc).map_err(|message| MigrationError::StepFailed { from: v, message })?;
+            if let Some(meta) = doc.get_mut("meta").and_then(Value::as_object_mut) {
+                meta.insert("schema".to_string(), Value::String(schema_tag(v + 1)));
+            }
+            report.to = v + 1;
+            report.applied.push(format!("{step:?}"));
+        }
+        Ok(report)
+    }
+}
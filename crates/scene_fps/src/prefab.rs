@@ -0,0 +1,248 @@
+//! Reusable groups of boxes, lights, triggers and enemies.
+//!
+//! Prefabs are declared inline in `prefabs` or in library files listed in `includes`, and placed
+//! through `instances`. [`FpsScene::flatten`] expands the placements into plain scene entities.
+
+use crate::transform::{rotate, Xform};
+use crate::{is_default, BoxDef, Enemy, FpsScene, Light, LightKind, Rotation, SceneError, Trigger};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Prefab {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub boxes: Vec<BoxDef>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lights: Vec<Light>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triggers: Vec<Trigger>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enemies: Vec<Enemy>,
+    /// Nested placements of other prefabs, in this prefab's local space
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub instances: Vec<PrefabInstance>,
+}
+
+/// A placement of a named prefab
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PrefabInstance {
+    pub prefab: String,
+    #[serde(default)]
+    pub pos: [f32; 3],
+    #[serde(default, rename = "rotY", skip_serializing_if = "is_default")]
+    pub rot_y: f32,
+    /// Full orientation, replacing `rotY` when present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<Rotation>,
+    #[serde(default, skip_serializing_if = "PrefabOverrides::is_empty")]
+    pub overrides: PrefabOverrides,
+}
+
+/// Per-instance changes applied to every entity of the placed prefab, including nested placements
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PrefabOverrides {
+    /// Multiplies box sizes and local offsets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<[f32; 3]>,
+    /// Replaces the color of every box
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<[f32; 3]>,
+    /// Replaces the texture of every box
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture: Option<String>,
+    /// Replaces the action of every trigger
+    #[serde(default, rename = "onEnter", skip_serializing_if = "Option::is_none")]
+    pub on_enter: Option<String>,
+}
+
+impl PrefabOverrides {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Contents of a file listed in `includes`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PrefabLibrary {
+    /// Further libraries, relative to this file
+    #[serde(default)]
+    pub includes: Vec<String>,
+    #[serde(default)]
+    pub prefabs: BTreeMap<String, Prefab>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PrefabError {
+    #[error("{path}: unknown prefab `{name}`")]
+    Unknown { path: String, name: String },
+    #[error("prefab `{name}` is defined more than once")]
+    Duplicate { name: String },
+    #[error("prefab cycle: {}", chain.join(" -> "))]
+    Cycle { chain: Vec<String> },
+    #[error("include cycle: {}", chain.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(" -> "))]
+    IncludeCycle { chain: Vec<PathBuf> },
+}
+
+impl FpsScene {
+    /// True if the scene still has prefab placements to expand
+    pub fn has_prefabs(&self) -> bool {
+        !self.instances.is_empty()
+    }
+
+    /// A prefab declared in the scene or loaded from one of its `includes`
+    pub fn prefab(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name).or_else(|| self.included_prefabs.get(name))
+    }
+
+    /// Expand every prefab placement into plain boxes, lights, triggers and enemies, and move the
+    /// contents of level groups into world space. Includes must already be resolved, which
+    /// [`load_scene`](crate::load_scene) does.
+    pub fn flatten(&self) -> Result<FpsScene, PrefabError> {
+        let mut out = self.clone();
+        out.includes.clear();
+        out.prefabs.clear();
+        out.included_prefabs.clear();
+        out.instances.clear();
+        out.level.boxes = self.level.world_boxes();
+        out.level.meshes = self.level.world_meshes();
+        out.level.groups.clear();
+        let mut stack = Vec::new();
+        for (i, inst) in self.instances.iter().enumerate() {
+            let path = format!("instances[{i}]");
+            expand(self, inst, &path, Xform::IDENTITY, &PrefabOverrides::default(), &mut stack, &mut out)?;
+        }
+        Ok(out)
+    }
+}
+
+/// Load every file in `scene.includes` relative to `base_dir` into `scene.included_prefabs`
+pub(crate) fn resolve_includes(scene: &mut FpsScene, base_dir: &Path) -> Result<(), SceneError> {
+    let mut libs = Libraries { chain: Vec::new(), loaded: HashSet::new(), prefabs: BTreeMap::new() };
+    for inc in &scene.includes {
+        libs.load(&base_dir.join(inc), &scene.prefabs)?;
+    }
+    scene.included_prefabs = libs.prefabs;
+    Ok(())
+}
+
+struct Libraries {
+    /// Libraries being loaded, each included by the one before
+    chain: Vec<PathBuf>,
+    /// Libraries already loaded, which later includes skip
+    loaded: HashSet<PathBuf>,
+    prefabs: BTreeMap<String, Prefab>,
+}
+
+impl Libraries {
+    /// Load the library at `path` and the ones it includes; `own` are the scene's inline prefabs,
+    /// which library prefabs must not shadow
+    fn load(&mut self, path: &Path, own: &BTreeMap<String, Prefab>) -> Result<(), SceneError> {
+        let canonical = fs::canonicalize(path).map_err(|source| SceneError::Io { path: path.to_path_buf(), source })?;
+        if self.chain.contains(&canonical) {
+            let mut cycle = self.chain.clone();
+            cycle.push(canonical);
+            return Err(SceneError::Prefab { file: Some(path.to_path_buf()), source: PrefabError::IncludeCycle { chain: cycle } });
+        }
+        // reached again through another include, e.g. both sides of a diamond
+        if !self.loaded.insert(canonical.clone()) {
+            return Ok(());
+        }
+        let src = fs::read_to_string(path).map_err(|source| SceneError::Io { path: path.to_path_buf(), source })?;
+        let lib: PrefabLibrary = serde_json::from_str(&src).map_err(|e| SceneError::from_json(e, &src, Some(path)))?;
+
+        self.chain.push(canonical);
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        for inc in &lib.includes {
+            self.load(&dir.join(inc), own)?;
+        }
+        self.chain.pop();
+
+        for (name, prefab) in lib.prefabs {
+            if own.contains_key(&name) || self.prefabs.contains_key(&name) {
+                return Err(SceneError::Prefab { file: Some(path.to_path_buf()), source: PrefabError::Duplicate { name } });
+            }
+            self.prefabs.insert(name, prefab);
+        }
+        Ok(())
+    }
+}
+
+fn expand(
+    scene: &FpsScene,
+    inst: &PrefabInstance,
+    path: &str,
+    parent: Xform,
+    inherited: &PrefabOverrides,
+    stack: &mut Vec<String>,
+    out: &mut FpsScene,
+) -> Result<(), PrefabError> {
+    let prefab = scene
+        .prefab(&inst.prefab)
+        .ok_or_else(|| PrefabError::Unknown { path: path.to_string(), name: inst.prefab.clone() })?;
+    if stack.contains(&inst.prefab) {
+        let mut chain = stack.clone();
+        chain.push(inst.prefab.clone());
+        return Err(PrefabError::Cycle { chain });
+    }
+    stack.push(inst.prefab.clone());
+
+    let xf = parent.then(&inst.local());
+    // overrides of enclosing instances win over nested ones
+    let ov = PrefabOverrides {
+        scale: None,
+        color: inherited.color.or(inst.overrides.color),
+        texture: inherited.texture.clone().or_else(|| inst.overrides.texture.clone()),
+        on_enter: inherited.on_enter.clone().or_else(|| inst.overrides.on_enter.clone()),
+    };
+
+    for b in &prefab.boxes {
+        let (rot_y, rotation) = xf.orient(b.rot_y, b.rotation);
+        out.level.boxes.push(BoxDef {
+            pos: xf.point(b.pos),
+            size: xf.size(b.size),
+            rot_y,
+            rotation,
+            color: ov.color.unwrap_or(b.color),
+            texture: ov.texture.clone().or_else(|| b.texture.clone()),
+            material: b.material.clone(),
+        });
+    }
+    for l in &prefab.lights {
+        let position = match l.kind {
+            LightKind::Point | LightKind::Spot => xf.point(l.position),
+            // directional lights store a direction, which only rotates
+            LightKind::Directional => xf.direction(l.position),
+        };
+        let direction = l.direction.map(|d| xf.direction(d));
+        out.lights.push(Light { position, direction, ..l.clone() });
+    }
+    for t in &prefab.triggers {
+        // triggers are axis-aligned, so take the bounds of the rotated volume
+        let h = xf.size(t.size);
+        let axes = [[h[0], 0.0, 0.0], [0.0, h[1], 0.0], [0.0, 0.0, h[2]]].map(|a| rotate(xf.rot, a));
+        let size = [0, 1, 2].map(|i| axes.iter().map(|a| a[i].abs()).sum());
+        out.triggers.push(Trigger {
+            pos: xf.point(t.pos),
+            size,
+            on_enter: ov.on_enter.clone().unwrap_or_else(|| t.on_enter.clone()),
+        });
+    }
+    for e in &prefab.enemies {
+        out.enemies.push(Enemy {
+            spawn: xf.point(e.spawn),
+            patrol: e.patrol.iter().map(|p| xf.point(*p)).collect(),
+            ..e.clone()
+        });
+    }
+    for (i, child) in prefab.instances.iter().enumerate() {
+        let path = format!("prefabs.{}.instances[{i}]", inst.prefab);
+        expand(scene, child, &path, xf, &ov, stack, out)?;
+    }
+
+    stack.pop();
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! Canonical JSON output for scenes.
+//!
+//! Keys follow the struct declaration order, floats use the shortest form that reads back to the
+//! same `f32`, fields still at their default are left out, and arrays of scalars stay on one line.
+//! Saving a loaded scene again produces byte-identical output.
+
+use crate::{save_scene_as, FpsScene, SceneError, SceneFormat};
+use std::path::Path;
+
+/// Serialize a scene to canonical pretty-printed JSON, ending with a newline
+pub fn to_string_pretty(scene: &FpsScene) -> String {
+    let pretty = serde_json::to_string_pretty(scene).expect("scene serialization is infallible");
+    let mut out = collapse_scalar_arrays(&pretty);
+    out.push('\n');
+    out
+}
+
+/// Write a scene to `path`, in the format matching its extension and canonical JSON otherwise
+pub fn save_scene<P: AsRef<Path>>(scene: &FpsScene, path: P) -> Result<(), SceneError> {
+    let path = path.as_ref();
+    save_scene_as(scene, path, SceneFormat::from_extension(path).unwrap_or(SceneFormat::Json))
+}
+
+/// Join `[`…`]` blocks whose elements are all scalars onto a single line.
+/// Relies on the pretty printer putting each element on its own line.
+fn collapse_scalar_arrays(pretty: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    for line in pretty.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with(']') {
+            if let Some(start) = open.pop() {
+                let items = &lines[start + 1..];
+                if items.iter().all(|l| is_scalar_line(l)) {
+                    let joined: Vec<&str> = items.iter().map(|l| l.trim().trim_end_matches(',')).collect();
+                    let merged = format!("{}{}{}", lines[start], joined.join(", "), trimmed);
+                    lines.truncate(start);
+                    lines.push(merged);
+                    continue;
+                }
+            }
+        }
+        if line.ends_with('[') {
+            open.push(lines.len());
+        }
+        lines.push(line.to_string());
+    }
+    lines.join("\n")
+}
+
+fn is_scalar_line(line: &str) -> bool {
+    let t = line.trim();
+    !(t.starts_with('{') || t.starts_with('[') || t.starts_with('}') || t.starts_with(']') || t.ends_with('{'))
+}
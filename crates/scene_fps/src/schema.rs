@@ -0,0 +1,23 @@
+//! JSON Schema for scene files, generated from the Rust types.
+
+use crate::{schema_tag, FpsScene, CURRENT_SCHEMA_VERSION};
+use schemars::gen::SchemaSettings;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// JSON Schema (draft-07) describing [`FpsScene`] documents as serde reads them
+pub fn scene_json_schema() -> serde_json::Value {
+    let gen = SchemaSettings::draft07().into_generator();
+    let mut root = gen.into_root_schema_for::<FpsScene>();
+    let meta = root.schema.metadata();
+    meta.title = Some(schema_tag(CURRENT_SCHEMA_VERSION));
+    meta.description = Some("KengaAI FPS scene".to_string());
+    serde_json::to_value(root).unwrap_or_default()
+}
+
+/// Write [`scene_json_schema`] as pretty-printed JSON
+pub fn write_scene_json_schema<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let s = serde_json::to_string_pretty(&scene_json_schema())?;
+    fs::write(path, s + "\n")
+}
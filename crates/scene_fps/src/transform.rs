@@ -0,0 +1,230 @@
+//! Full 3D orientations and transform groups.
+//!
+//! `rotY` turns +X toward +Z, the sense of the renderer's original `rotationY`. [`Rotation`] covers
+//! ramps, slopes and tilted beams, and [`Group`] places its children relative to its own transform.
+//! [`Level::world_boxes`] and [`Level::world_meshes`] resolve groups into world space, and
+//! [`FpsScene::flatten`](crate::FpsScene::flatten) places prefab instances the same way.
+
+use crate::{is_default, BoxDef, Level, MeshDef, PrefabInstance};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Quaternion `[x, y, z, w]`
+pub type Quat = [f32; 4];
+
+pub const QUAT_IDENTITY: Quat = [0.0, 0.0, 0.0, 1.0];
+
+/// Orientation of a box, mesh, group or prefab instance. When present it replaces `rotY`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Rotation {
+    /// Quaternion `[x, y, z, w]`, normalized on use
+    Quat(Quat),
+    /// `[pitch, yaw, roll]` in radians: roll about Z first, then pitch about X, then yaw about Y.
+    /// Yaw turns the same way as `rotY`.
+    Euler([f32; 3]),
+}
+
+impl Rotation {
+    pub fn to_quat(self) -> Quat {
+        match self {
+            Rotation::Quat(q) => normalize(q),
+            Rotation::Euler([pitch, yaw, roll]) => {
+                let (sx, cx) = (0.5 * pitch).sin_cos();
+                let (sz, cz) = (0.5 * roll).sin_cos();
+                quat_mul(quat_mul(quat_y(yaw), [sx, 0.0, 0.0, cx]), [0.0, 0.0, sz, cz])
+            }
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        match self {
+            Rotation::Quat(q) => q.iter().all(|c| c.is_finite()),
+            Rotation::Euler(e) => e.iter().all(|c| c.is_finite()),
+        }
+    }
+}
+
+/// A node whose boxes, meshes and nested groups are positioned relative to it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Group {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub pos: [f32; 3],
+    #[serde(default, rename = "rotY", skip_serializing_if = "is_default")]
+    pub rot_y: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<Rotation>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub boxes: Vec<BoxDef>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub meshes: Vec<MeshDef>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Group>,
+}
+
+impl Group {
+    pub fn orientation(&self) -> Quat {
+        orientation(self.rot_y, self.rotation)
+    }
+
+    fn local(&self) -> Xform {
+        Xform { pos: self.pos, rot: self.orientation(), yaw: self.rotation.is_none().then_some(self.rot_y), scale: [1.0; 3] }
+    }
+}
+
+impl BoxDef {
+    /// World-space orientation as a quaternion, from `rotation` or else `rotY`
+    pub fn orientation(&self) -> Quat {
+        orientation(self.rot_y, self.rotation)
+    }
+}
+
+impl MeshDef {
+    /// World-space orientation as a quaternion, from `rotation` or else `rotY`
+    pub fn orientation(&self) -> Quat {
+        orientation(self.rot_y, self.rotation)
+    }
+}
+
+impl PrefabInstance {
+    /// Orientation of the placement, from `rotation` or else `rotY`
+    pub fn orientation(&self) -> Quat {
+        orientation(self.rot_y, self.rotation)
+    }
+
+    pub(crate) fn local(&self) -> Xform {
+        Xform {
+            pos: self.pos,
+            rot: self.orientation(),
+            yaw: self.rotation.is_none().then_some(self.rot_y),
+            scale: self.overrides.scale.unwrap_or([1.0; 3]),
+        }
+    }
+}
+
+impl Level {
+    /// Top-level boxes followed by the boxes of every group, depth first, in world space.
+    /// Indices below `boxes.len()` refer to `boxes` unchanged.
+    pub fn world_boxes(&self) -> Vec<BoxDef> {
+        let mut out = self.boxes.clone();
+        visit_groups(&self.groups, "level", Xform::IDENTITY, &mut |_, xf, g| {
+            out.extend(g.boxes.iter().map(|b| {
+                let (rot_y, rotation) = xf.orient(b.rot_y, b.rotation);
+                BoxDef { pos: xf.point(b.pos), rot_y, rotation, ..b.clone() }
+            }));
+        });
+        out
+    }
+
+    /// Top-level meshes followed by the meshes of every group, depth first, in world space
+    pub fn world_meshes(&self) -> Vec<MeshDef> {
+        let mut out = self.meshes.clone();
+        visit_groups(&self.groups, "level", Xform::IDENTITY, &mut |_, xf, g| {
+            out.extend(g.meshes.iter().map(|m| {
+                let (rot_y, rotation) = xf.orient(m.rot_y, m.rotation);
+                MeshDef { pos: xf.point(m.pos), rot_y, rotation, ..m.clone() }
+            }));
+        });
+        out
+    }
+}
+
+/// Call `f` with the JSON path, world transform and contents of every group, parents before children
+pub(crate) fn visit_groups(groups: &[Group], parent_path: &str, parent: Xform, f: &mut dyn FnMut(&str, Xform, &Group)) {
+    for (i, g) in groups.iter().enumerate() {
+        let path = format!("{parent_path}.groups[{i}]");
+        let xf = parent.then(&g.local());
+        f(&path, xf, g);
+        visit_groups(&g.groups, &path, xf, f);
+    }
+}
+
+/// Transform of a group or prefab instance: scale, then rotation, then translation. `yaw` is kept
+/// while every rotation so far is about Y, so children without a full `rotation` can still be
+/// written with plain `rotY`. Only prefab instances scale; nested scales multiply per axis.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Xform {
+    pub pos: [f32; 3],
+    pub rot: Quat,
+    pub yaw: Option<f32>,
+    pub scale: [f32; 3],
+}
+
+impl Xform {
+    pub const IDENTITY: Xform = Xform { pos: [0.0; 3], rot: QUAT_IDENTITY, yaw: Some(0.0), scale: [1.0; 3] };
+
+    pub fn point(&self, p: [f32; 3]) -> [f32; 3] {
+        let r = rotate(self.rot, self.size(p));
+        [r[0] + self.pos[0], r[1] + self.pos[1], r[2] + self.pos[2]]
+    }
+
+    /// A direction, which only rotates
+    pub fn direction(&self, d: [f32; 3]) -> [f32; 3] {
+        rotate(self.rot, d)
+    }
+
+    /// Half-extents or other per-axis lengths, which only scale
+    pub fn size(&self, s: [f32; 3]) -> [f32; 3] {
+        [s[0] * self.scale[0], s[1] * self.scale[1], s[2] * self.scale[2]]
+    }
+
+    pub fn then(&self, child: &Xform) -> Xform {
+        Xform {
+            pos: self.point(child.pos),
+            rot: quat_mul(self.rot, child.rot),
+            yaw: self.yaw.zip(child.yaw).map(|(a, b)| a + b),
+            scale: self.size(child.scale),
+        }
+    }
+
+    /// `rotY` and `rotation` of a child with the given local orientation
+    pub fn orient(&self, rot_y: f32, rotation: Option<Rotation>) -> (f32, Option<Rotation>) {
+        match (self.yaw, rotation) {
+            (Some(yaw), None) => (yaw + rot_y, None),
+            _ => (0.0, Some(Rotation::Quat(quat_mul(self.rot, orientation(rot_y, rotation))))),
+        }
+    }
+}
+
+fn orientation(rot_y: f32, rotation: Option<Rotation>) -> Quat {
+    rotation.map_or_else(|| quat_y(rot_y), Rotation::to_quat)
+}
+
+/// Rotation about Y by `rot_y` in the `rotY` sense, which maps +X to `(cos, 0, sin)`
+pub fn quat_y(rot_y: f32) -> Quat {
+    let (s, c) = (0.5 * rot_y).sin_cos();
+    [0.0, -s, 0.0, c]
+}
+
+/// Hamilton product: rotating by `b` and then by `a`
+pub fn quat_mul(a: Quat, b: Quat) -> Quat {
+    [
+        a[3] * b[0] + a[0] * b[3] + a[1] * b[2] - a[2] * b[1],
+        a[3] * b[1] - a[0] * b[2] + a[1] * b[3] + a[2] * b[0],
+        a[3] * b[2] + a[0] * b[1] - a[1] * b[0] + a[2] * b[3],
+        a[3] * b[3] - a[0] * b[0] - a[1] * b[1] - a[2] * b[2],
+    ]
+}
+
+/// Rotate `v` by the unit quaternion `q`
+pub fn rotate(q: Quat, v: [f32; 3]) -> [f32; 3] {
+    let u = [q[0], q[1], q[2]];
+    let t = cross(u, v).map(|c| 2.0 * c);
+    let ut = cross(u, t);
+    [v[0] + q[3] * t[0] + ut[0], v[1] + q[3] * t[1] + ut[1], v[2] + q[3] * t[2] + ut[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize(q: Quat) -> Quat {
+    let len = q.iter().map(|c| c * c).sum::<f32>().sqrt();
+    if len > 0.0 && len.is_finite() {
+        q.map(|c| c / len)
+    } else {
+        QUAT_IDENTITY
+    }
+}
@@ -0,0 +1,247 @@
+//! The `Trigger.onEnter` action language: `verb[:arg[:arg...]]`, e.g. `spawn_wave:grunt:3`.
+//!
+//! Arguments are separated by `:`; a string argument writes a `:` or `\` of its own as `\:` or `\\`.
+
+use crate::{FpsScene, Trigger};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Type of a single action argument
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArgType {
+    Str,
+    Int,
+    Float,
+    Bool,
+}
+
+impl fmt::Display for ArgType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ArgType::Str => "string",
+            ArgType::Int => "integer",
+            ArgType::Float => "number",
+            ArgType::Bool => "bool",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActionArg {
+    Str(String),
+    Int(i64),
+    Float(f32),
+    Bool(bool),
+}
+
+impl ActionArg {
+    fn parse(ty: ArgType, s: &str) -> Option<Self> {
+        match ty {
+            ArgType::Str if !s.is_empty() => Some(ActionArg::Str(s.to_string())),
+            ArgType::Str => None,
+            ArgType::Int => s.parse().ok().map(ActionArg::Int),
+            ArgType::Float => s.parse().ok().map(ActionArg::Float),
+            ArgType::Bool => s.parse().ok().map(ActionArg::Bool),
+        }
+    }
+}
+
+impl fmt::Display for ActionArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionArg::Str(s) => f.write_str(&escape(s)),
+            ActionArg::Int(n) => write!(f, "{n}"),
+            ActionArg::Float(x) => write!(f, "{x}"),
+            ActionArg::Bool(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+/// A parsed `Trigger.onEnter` command
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriggerAction {
+    /// `spawn_wave:<enemy kind>:<count>`
+    SpawnWave { enemy: String, count: u32 },
+    /// `collect_key:<color>`
+    CollectKey { color: String },
+    /// `start_level:<level>`
+    StartLevel { level: String },
+    /// A verb added through [`ActionRegistry::register`]
+    Custom { verb: String, args: Vec<ActionArg> },
+}
+
+impl TriggerAction {
+    pub fn verb(&self) -> &str {
+        match self {
+            TriggerAction::SpawnWave { .. } => "spawn_wave",
+            TriggerAction::CollectKey { .. } => "collect_key",
+            TriggerAction::StartLevel { .. } => "start_level",
+            TriggerAction::Custom { verb, .. } => verb,
+        }
+    }
+}
+
+impl fmt::Display for TriggerAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerAction::SpawnWave { enemy, count } => write!(f, "spawn_wave:{}:{count}", escape(enemy)),
+            TriggerAction::CollectKey { color } => write!(f, "collect_key:{}", escape(color)),
+            TriggerAction::StartLevel { level } => write!(f, "start_level:{}", escape(level)),
+            TriggerAction::Custom { verb, args } => {
+                f.write_str(verb)?;
+                for a in args {
+                    write!(f, ":{a}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parses with the built-in verbs only; use [`ActionRegistry::parse`] for custom ones
+impl FromStr for TriggerAction {
+    type Err = ActionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ActionRegistry::new().parse(s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ActionParseError {
+    #[error("empty action")]
+    Empty,
+    #[error("unknown action `{verb}`, expected one of: {}", known.join(", "))]
+    UnknownVerb { verb: String, known: Vec<String> },
+    #[error("`{verb}` takes {expected} argument(s), got {found}")]
+    Arity { verb: String, expected: usize, found: usize },
+    #[error("argument {index} of `{verb}` expects {expected}, got `{value}`")]
+    BadArg { verb: String, index: usize, expected: ArgType, value: String },
+    #[error("`{0}` is not a valid verb")]
+    InvalidVerb(String),
+    #[error("verb `{0}` is already registered")]
+    Duplicate(String),
+}
+
+/// Failure to parse the action of a specific trigger
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("triggers[{index}].onEnter: {source}")]
+pub struct TriggerActionError {
+    pub index: usize,
+    #[source]
+    pub source: ActionParseError,
+}
+
+/// Known action verbs and their argument signatures
+#[derive(Debug, Clone)]
+pub struct ActionRegistry {
+    verbs: BTreeMap<String, Vec<ArgType>>,
+}
+
+impl Default for ActionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ActionRegistry {
+    /// Registry with the built-in verbs
+    pub fn new() -> Self {
+        let mut verbs = BTreeMap::new();
+        verbs.insert("spawn_wave".to_string(), vec![ArgType::Str, ArgType::Int]);
+        verbs.insert("collect_key".to_string(), vec![ArgType::Str]);
+        verbs.insert("start_level".to_string(), vec![ArgType::Str]);
+        Self { verbs }
+    }
+
+    /// Add a game-specific verb; its actions parse as [`TriggerAction::Custom`]
+    pub fn register(&mut self, verb: &str, args: &[ArgType]) -> Result<(), ActionParseError> {
+        if verb.is_empty() || verb.contains([':', '\\']) {
+            return Err(ActionParseError::InvalidVerb(verb.to_string()));
+        }
+        if self.verbs.contains_key(verb) {
+            return Err(ActionParseError::Duplicate(verb.to_string()));
+        }
+        self.verbs.insert(verb.to_string(), args.to_vec());
+        Ok(())
+    }
+
+    pub fn signature(&self, verb: &str) -> Option<&[ArgType]> {
+        self.verbs.get(verb).map(Vec::as_slice)
+    }
+
+    pub fn parse(&self, s: &str) -> Result<TriggerAction, ActionParseError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(ActionParseError::Empty);
+        }
+        let mut raw = split(s);
+        let verb = raw.remove(0);
+        let verb = verb.as_str();
+        let sig = self.verbs.get(verb).ok_or_else(|| ActionParseError::UnknownVerb {
+            verb: verb.to_string(),
+            known: self.verbs.keys().cloned().collect(),
+        })?;
+        if raw.len() != sig.len() {
+            return Err(ActionParseError::Arity { verb: verb.to_string(), expected: sig.len(), found: raw.len() });
+        }
+        let bad = |index: usize| ActionParseError::BadArg {
+            verb: verb.to_string(),
+            index,
+            expected: sig[index],
+            value: raw[index].to_string(),
+        };
+        let mut args = Vec::with_capacity(raw.len());
+        for (i, (ty, r)) in sig.iter().zip(&raw).enumerate() {
+            args.push(ActionArg::parse(*ty, r).ok_or_else(|| bad(i))?);
+        }
+
+        Ok(match (verb, args.as_slice()) {
+            ("spawn_wave", [ActionArg::Str(enemy), ActionArg::Int(n)]) => TriggerAction::SpawnWave {
+                enemy: enemy.clone(),
+                count: u32::try_from(*n).map_err(|_| bad(1))?,
+            },
+            ("collect_key", [ActionArg::Str(color)]) => TriggerAction::CollectKey { color: color.clone() },
+            ("start_level", [ActionArg::Str(level)]) => TriggerAction::StartLevel { level: level.clone() },
+            _ => TriggerAction::Custom { verb: verb.to_string(), args },
+        })
+    }
+}
+
+/// `s` with `\` and `:` escaped, so it reads back as a single argument
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace(':', "\\:")
+}
+
+/// Split `s` at the colons that aren't escaped, unescaping each part
+fn split(s: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => parts.last_mut().unwrap().push(chars.next().unwrap_or('\\')),
+            ':' => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+impl Trigger {
+    pub fn action(&self, registry: &ActionRegistry) -> Result<TriggerAction, ActionParseError> {
+        registry.parse(&self.on_enter)
+    }
+}
+
+impl FpsScene {
+    /// Parse the `onEnter` action of every trigger, in order
+    pub fn trigger_actions(&self, registry: &ActionRegistry) -> Result<Vec<TriggerAction>, TriggerActionError> {
+        self.triggers
+            .iter()
+            .enumerate()
+            .map(|(index, t)| t.action(registry).map_err(|source| TriggerActionError { index, source }))
+            .collect()
+    }
+}
@@ -0,0 +1,338 @@
+//! Semantic checks for scenes that deserialized successfully.
+
+use crate::transform::{self, Xform};
+use crate::{
+    schema_tag, BoxDef, FpsScene, Light, LightKind, Material, MeshDef, Rotation, CURRENT_SCHEMA_VERSION,
+    DEFAULT_SPOT_INNER_ANGLE, DEFAULT_SPOT_OUTER_ANGLE, MAX_LIGHTS, MAX_SHADOWED_DIRECTIONAL_LIGHTS,
+    MAX_SHADOWED_POINT_LIGHTS, MAX_SHADOW_RESOLUTION,
+};
+use std::fmt;
+
+/// Schema tag of the first scene format generation
+pub const SCHEMA_V0: &str = "KengaFPSSceneV0";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// A single finding of [`FpsScene::validate`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// JSON path of the offending field, e.g. `level.boxes[12].size`
+    pub path: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { severity: Severity::Error, path: path.into(), message: message.into() }
+    }
+
+    pub fn warning(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { severity: Severity::Warning, path: path.into(), message: message.into() }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.path, self.message)
+    }
+}
+
+/// True if any diagnostic is an error
+pub fn has_errors(diags: &[Diagnostic]) -> bool {
+    diags.iter().any(Diagnostic::is_error)
+}
+
+impl FpsScene {
+    /// Run semantic checks that serde can't express. An empty result means the scene is clean.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut v = Validator { out: Vec::new() };
+
+        let current = schema_tag(CURRENT_SCHEMA_VERSION);
+        if self.meta.schema != current {
+            v.out.push(Diagnostic::error(
+                "meta.schema",
+                format!("unsupported schema {:?}, expected {:?}", self.meta.schema, current),
+            ));
+        }
+
+        v.finite("render.clearColor", &self.render.clear_color);
+        if self.render.clear_color.iter().any(|c| !(0.0..=1.0).contains(c)) {
+            v.out.push(Diagnostic::warning("render.clearColor", "components should be in 0..=1"));
+        }
+        if let Some(post) = &self.render.post_process {
+            let p = "render.postProcess";
+            if let Some(exposure) = post.exposure {
+                v.positive(&format!("{p}.exposure"), exposure);
+            }
+            if let Some(gamma) = post.gamma {
+                v.positive(&format!("{p}.gamma"), gamma);
+            }
+            if v.finite(&format!("{p}.vignette"), &[post.vignette]) && !(0.0..=1.0).contains(&post.vignette) {
+                v.out.push(Diagnostic::warning(format!("{p}.vignette"), "should be in 0..=1"));
+            }
+            if let Some(bloom) = &post.bloom {
+                for (field, value) in [("threshold", bloom.threshold), ("intensity", bloom.intensity), ("radius", bloom.radius)] {
+                    if let Some(value) = value {
+                        v.non_negative(&format!("{p}.bloom.{field}"), value);
+                    }
+                }
+            }
+        }
+
+        v.finite("player.spawn", &self.player.spawn);
+        v.finite("player.yaw", &[self.player.yaw]);
+        v.finite("player.pitch", &[self.player.pitch]);
+        v.non_negative("player.move.speed", self.player.r#move.speed);
+        v.non_negative("player.move.run", self.player.r#move.run);
+
+        for (i, w) in self.weapons.iter().enumerate() {
+            v.non_negative(&format!("weapons[{i}].damage"), w.damage);
+            v.positive(&format!("weapons[{i}].rate"), w.rate);
+            if let Some(spread) = w.spread {
+                v.non_negative(&format!("weapons[{i}].spread"), spread);
+            }
+        }
+
+        for (i, b) in self.level.boxes.iter().enumerate() {
+            v.box_def(&format!("level.boxes[{i}]"), b);
+        }
+        for (i, m) in self.level.meshes.iter().enumerate() {
+            v.mesh_def(&format!("level.meshes[{i}]"), m);
+        }
+        transform::visit_groups(&self.level.groups, "level", Xform::IDENTITY, &mut |p, _, g| {
+            v.finite(&format!("{p}.pos"), &g.pos);
+            v.finite(&format!("{p}.rotY"), &[g.rot_y]);
+            v.rotation(&format!("{p}.rotation"), g.rotation.as_ref());
+            for (i, b) in g.boxes.iter().enumerate() {
+                v.box_def(&format!("{p}.boxes[{i}]"), b);
+            }
+            for (i, m) in g.meshes.iter().enumerate() {
+                v.mesh_def(&format!("{p}.meshes[{i}]"), m);
+            }
+        });
+
+        if self.lights.len() > MAX_LIGHTS {
+            v.out.push(Diagnostic::warning(
+                "lights",
+                format!(
+                    "{} lights defined, the renderer only uses the first {MAX_LIGHTS}",
+                    self.lights.len()
+                ),
+            ));
+        }
+        for (i, l) in self.lights.iter().enumerate() {
+            v.finite(&format!("lights[{i}].position"), &l.position);
+            v.finite(&format!("lights[{i}].color"), &l.color);
+            v.non_negative(&format!("lights[{i}].intensity"), l.intensity);
+            if let Some(range) = l.range {
+                v.positive(&format!("lights[{i}].range"), range);
+            }
+            if l.kind == LightKind::Spot {
+                v.spot(&format!("lights[{i}]"), l);
+            }
+            match l.shadow_resolution {
+                Some(0) => v.out.push(Diagnostic::error(format!("lights[{i}].shadowResolution"), "must be positive")),
+                Some(r) if r > MAX_SHADOW_RESOLUTION => v.out.push(Diagnostic::warning(
+                    format!("lights[{i}].shadowResolution"),
+                    format!("{r} is clamped to {MAX_SHADOW_RESOLUTION}"),
+                )),
+                _ => {}
+            }
+        }
+        for (i, _) in self.lights.iter().enumerate().filter(|(_, l)| l.cast_shadows && l.kind == LightKind::Spot) {
+            v.out.push(Diagnostic::warning(format!("lights[{i}].castShadows"), "spot lights don't cast shadows"));
+        }
+        for (kind, max) in [(LightKind::Directional, MAX_SHADOWED_DIRECTIONAL_LIGHTS), (LightKind::Point, MAX_SHADOWED_POINT_LIGHTS)] {
+            let casters = self.lights.iter().enumerate().filter(|(_, l)| l.cast_shadows && l.kind == kind);
+            for (i, _) in casters.skip(max) {
+                v.out.push(Diagnostic::warning(
+                    format!("lights[{i}].castShadows"),
+                    format!("only the first {max} {} lights cast shadows", kind.as_str()),
+                ));
+            }
+        }
+
+        for (i, ps) in self.particles.iter().enumerate() {
+            let p = format!("particles[{i}]");
+            v.finite(&format!("{p}.position"), &ps.position);
+            v.finite(&format!("{p}.color"), &ps.color);
+            v.non_negative(&format!("{p}.lifetime"), ps.lifetime);
+            v.non_negative(&format!("{p}.speed"), ps.speed);
+            v.non_negative(&format!("{p}.spread"), ps.spread);
+        }
+
+        for (i, e) in self.enemies.iter().enumerate() {
+            v.finite(&format!("enemies[{i}].spawn"), &e.spawn);
+            for (j, pt) in e.patrol.iter().enumerate() {
+                v.finite(&format!("enemies[{i}].patrol[{j}]"), pt);
+            }
+        }
+
+        for (i, s) in self.sounds.iter().enumerate() {
+            v.finite(&format!("sounds[{i}].position"), &s.position);
+            v.non_negative(&format!("sounds[{i}].volume"), s.volume);
+        }
+
+        for (i, t) in self.triggers.iter().enumerate() {
+            v.finite(&format!("triggers[{i}].pos"), &t.pos);
+            v.extent(&format!("triggers[{i}].size"), &t.size);
+        }
+
+        if let Some(goals) = &self.goals {
+            v.finite("goals.point", &goals.point);
+        }
+
+        for (i, inst) in self.instances.iter().enumerate() {
+            let p = format!("instances[{i}]");
+            if self.prefab(&inst.prefab).is_none() {
+                v.out.push(Diagnostic::error(format!("{p}.prefab"), format!("unknown prefab `{}`", inst.prefab)));
+            }
+            v.finite(&format!("{p}.pos"), &inst.pos);
+            v.finite(&format!("{p}.rotY"), &[inst.rot_y]);
+            v.rotation(&format!("{p}.rotation"), inst.rotation.as_ref());
+            if let Some(scale) = &inst.overrides.scale {
+                v.extent(&format!("{p}.overrides.scale"), scale);
+            }
+        }
+
+        if self.player.spawn.iter().all(|c| c.is_finite()) {
+            let top = self.level.boxes.len();
+            for (i, b) in self.level.world_boxes().iter().enumerate() {
+                if box_contains(b, self.player.spawn) {
+                    let what = if i < top { format!("level.boxes[{i}]") } else { format!("grouped box #{}", i - top) };
+                    v.out.push(Diagnostic::error("player.spawn", format!("spawn point is inside {what}")));
+                }
+            }
+        }
+
+        v.out
+    }
+}
+
+struct Validator {
+    out: Vec<Diagnostic>,
+}
+
+impl Validator {
+    fn finite(&mut self, path: &str, values: &[f32]) -> bool {
+        if values.iter().all(|x| x.is_finite()) {
+            return true;
+        }
+        self.out.push(Diagnostic::error(path, "value is NaN or infinite"));
+        false
+    }
+
+    fn non_negative(&mut self, path: &str, value: f32) {
+        if self.finite(path, &[value]) && value < 0.0 {
+            self.out.push(Diagnostic::error(path, format!("must not be negative, got {value}")));
+        }
+    }
+
+    fn positive(&mut self, path: &str, value: f32) {
+        if self.finite(path, &[value]) && value <= 0.0 {
+            self.out.push(Diagnostic::error(path, format!("must be positive, got {value}")));
+        }
+    }
+
+    /// A spot light's direction and cone, whose angles must satisfy `0 <= inner <= outer < π/2`
+    fn spot(&mut self, path: &str, l: &Light) {
+        match l.direction {
+            None => self.out.push(Diagnostic::error(format!("{path}.direction"), "spot lights need a direction")),
+            Some(d) if self.finite(&format!("{path}.direction"), &d) && d.iter().all(|c| *c == 0.0) => {
+                self.out.push(Diagnostic::error(format!("{path}.direction"), "must not be zero"));
+            }
+            _ => {}
+        }
+        let inner = l.inner_angle.unwrap_or(DEFAULT_SPOT_INNER_ANGLE);
+        let outer = l.outer_angle.unwrap_or(DEFAULT_SPOT_OUTER_ANGLE);
+        if !self.finite(&format!("{path}.innerAngle"), &[inner]) || !self.finite(&format!("{path}.outerAngle"), &[outer]) {
+            return;
+        }
+        if !(0.0..std::f32::consts::FRAC_PI_2).contains(&outer) {
+            self.out.push(Diagnostic::error(format!("{path}.outerAngle"), format!("must be between 0 and π/2, got {outer}")));
+        } else if !(0.0..=outer).contains(&inner) {
+            self.out.push(Diagnostic::error(
+                format!("{path}.innerAngle"),
+                format!("must be between 0 and the outer angle {outer}, got {inner}"),
+            ));
+        }
+    }
+
+    fn rotation(&mut self, path: &str, rotation: Option<&Rotation>) {
+        match rotation {
+            Some(r) if !r.is_finite() => self.out.push(Diagnostic::error(path, "value is NaN or infinite")),
+            Some(Rotation::Quat(q)) if q.iter().all(|c| *c == 0.0) => {
+                self.out.push(Diagnostic::error(path, "quaternion must not be zero"));
+            }
+            _ => {}
+        }
+    }
+
+    fn box_def(&mut self, p: &str, b: &BoxDef) {
+        self.finite(&format!("{p}.pos"), &b.pos);
+        self.extent(&format!("{p}.size"), &b.size);
+        self.finite(&format!("{p}.rotY"), &[b.rot_y]);
+        self.rotation(&format!("{p}.rotation"), b.rotation.as_ref());
+        self.finite(&format!("{p}.color"), &b.color);
+        self.material(&format!("{p}.material"), b.material.as_ref());
+    }
+
+    fn mesh_def(&mut self, p: &str, m: &MeshDef) {
+        self.finite(&format!("{p}.pos"), &m.pos);
+        self.extent(&format!("{p}.scale"), &m.scale);
+        self.finite(&format!("{p}.rotY"), &[m.rot_y]);
+        self.rotation(&format!("{p}.rotation"), m.rotation.as_ref());
+        if m.file.trim().is_empty() {
+            self.out.push(Diagnostic::error(format!("{p}.file"), "mesh file is empty"));
+        }
+        self.material(&format!("{p}.material"), m.material.as_ref());
+    }
+
+    fn material(&mut self, p: &str, m: Option<&Material>) {
+        let Some(m) = m else { return };
+        if let Some(color) = &m.color {
+            self.finite(&format!("{p}.color"), color);
+        }
+        for (field, value) in [("metallic", m.metallic), ("roughness", m.roughness)] {
+            let path = format!("{p}.{field}");
+            if self.finite(&path, &[value]) && !(0.0..=1.0).contains(&value) {
+                self.out.push(Diagnostic::warning(path, "should be in 0..=1"));
+            }
+        }
+    }
+
+    fn extent(&mut self, path: &str, size: &[f32; 3]) {
+        if self.finite(path, size) && size.iter().any(|c| *c <= 0.0) {
+            self.out.push(Diagnostic::error(
+                path,
+                format!("all components must be positive, got {size:?}"),
+            ));
+        }
+    }
+}
+
+/// Point-in-box test matching the renderer: `size` holds half-extents and the box is rotated by its orientation
+fn box_contains(b: &BoxDef, p: [f32; 3]) -> bool {
+    let d = [p[0] - b.pos[0], p[1] - b.pos[1], p[2] - b.pos[2]];
+    let [x, y, z, w] = b.orientation();
+    let local = transform::rotate([-x, -y, -z, w], d);
+    local.iter().zip(b.size.iter()).all(|(l, h)| l.abs() < h.abs())
+}
@@ -0,0 +1,73 @@
+use kengaai_scene_fps::{FpsSceneBuilder, Material, Rotation};
+
+#[test]
+fn box_modifiers_change_the_box_just_added() {
+    let scene = FpsSceneBuilder::new("boxes")
+        .floor(5.0, 5.0)
+        .color([0.1, 0.1, 0.1])
+        .wall([-5.0, 0.0], [5.0, 0.0], 2.0)
+        .texture("brick.png")
+        .box_at([0.0, 1.0, 0.0], [1.0; 3])
+        .rot_y(0.5)
+        .rotation(Rotation::Euler([0.1, 0.0, 0.0]))
+        .color([1.0, 0.0, 0.0])
+        .material(Material { color: Some([1.0, 0.0, 0.0]), texture: None, metallic: 1.0, roughness: 0.2 })
+        .box_at([3.0, 1.0, 0.0], [1.0; 3])
+        .build_unchecked();
+    let b = &scene.level.boxes;
+    assert_eq!(b[0].color, [0.1, 0.1, 0.1]);
+    assert_eq!((b[1].texture.as_deref(), b[1].color), (Some("brick.png"), [0.5, 0.5, 0.5]));
+    assert_eq!((b[2].rot_y, b[2].rotation, b[2].color), (0.5, Some(Rotation::Euler([0.1, 0.0, 0.0])), [1.0, 0.0, 0.0]));
+    assert_eq!(b[2].material.as_ref().map(|m| m.metallic), Some(1.0));
+    // the last box keeps its defaults
+    assert_eq!((b[3].rot_y, b[3].rotation, b[3].texture.as_deref(), b[3].material.is_none()), (0.0, None, None, true));
+}
+
+#[test]
+#[should_panic(expected = "FpsSceneBuilder::color must follow box_at")]
+fn box_modifiers_need_a_box() {
+    FpsSceneBuilder::new("empty").color([1.0, 0.0, 0.0]);
+}
+
+#[test]
+#[should_panic(expected = "FpsSceneBuilder::texture must follow box_at")]
+fn box_modifiers_do_not_reach_past_a_light() {
+    FpsSceneBuilder::new("lit").box_at([0.0; 3], [1.0; 3]).point_light([0.0, 3.0, 0.0], [1.0; 3], 1.0).texture("crate.png");
+}
+
+#[test]
+#[should_panic(expected = "FpsSceneBuilder::rot_y must follow box_at")]
+fn box_modifiers_do_not_reach_past_a_group() {
+    FpsSceneBuilder::new("grouped").box_at([0.0; 3], [1.0; 3]).group(Default::default()).rot_y(1.0);
+}
+
+#[test]
+fn light_modifiers_change_the_light_just_added() {
+    let scene = FpsSceneBuilder::new("lights")
+        .point_light([0.0, 3.0, 0.0], [1.0; 3], 1.0)
+        .range(8.0)
+        .spot_light([0.0, 4.0, 0.0], [0.0, -1.0, 0.0], [1.0; 3], 2.0)
+        .cone(0.2, 0.4)
+        .shadows(1024)
+        .range(12.0)
+        .directional_light([0.0, -1.0, 0.0], [1.0; 3], 0.5)
+        .build_unchecked();
+    let l = &scene.lights;
+    assert_eq!((l[0].range, l[0].cast_shadows), (Some(8.0), false));
+    assert_eq!((l[1].inner_angle, l[1].outer_angle, l[1].range), (Some(0.2), Some(0.4), Some(12.0)));
+    assert_eq!((l[1].cast_shadows, l[1].shadow_resolution), (true, Some(1024)));
+    // the last light keeps its defaults
+    assert_eq!((l[2].range, l[2].cast_shadows, l[2].shadow_resolution), (None, false, None));
+}
+
+#[test]
+#[should_panic(expected = "FpsSceneBuilder::range must follow a *_light method")]
+fn light_modifiers_need_a_light() {
+    FpsSceneBuilder::new("dark").range(5.0);
+}
+
+#[test]
+#[should_panic(expected = "FpsSceneBuilder::shadows must follow a *_light method")]
+fn light_modifiers_do_not_reach_past_a_box() {
+    FpsSceneBuilder::new("lit").point_light([0.0, 3.0, 0.0], [1.0; 3], 1.0).box_at([0.0; 3], [1.0; 3]).shadows(512);
+}
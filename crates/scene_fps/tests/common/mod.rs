@@ -0,0 +1,16 @@
+//! Helpers shared by the scene test suites
+
+use std::fs;
+use std::path::PathBuf;
+
+/// The bundled JSON levels in `assets/levels`, sorted by name
+pub fn levels() -> Vec<PathBuf> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../assets/levels");
+    let mut out: Vec<PathBuf> = fs::read_dir(dir)
+        .expect("assets/levels")
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .collect();
+    out.sort();
+    out
+}
@@ -0,0 +1,120 @@
+use kengaai_scene_fps::{
+    load_scene, to_string_pretty, DiffOptions, EntityChange, FpsScene, FpsSceneBuilder, PatchError, ScenePatch,
+};
+use std::path::PathBuf;
+
+fn base() -> FpsScene {
+    FpsSceneBuilder::new("diff")
+        .floor(10.0, 10.0)
+        .box_at([0.0, 1.0, -4.0], [1.0, 1.0, 1.0])
+        .box_at([3.0, 1.0, -4.0], [1.0, 1.0, 1.0])
+        .box_at([6.0, 1.0, -4.0], [1.0, 1.0, 1.0])
+        .point_light([0.0, 4.0, 0.0], [1.0, 1.0, 1.0], 1.5)
+        .enemy("grunt", [3.0, 1.0, -6.0])
+        .trigger([0.0, 1.0, -8.0], [1.0, 1.0, 1.0], "collect_key:red")
+        .build_unchecked()
+}
+
+fn level(name: &str) -> FpsScene {
+    load_scene(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../assets/levels").join(name)).unwrap()
+}
+
+#[test]
+fn identical_scenes_have_no_diff() {
+    assert!(base().diff(&base()).is_empty());
+}
+
+#[test]
+fn float_noise_below_tolerance_is_ignored() {
+    let mut b = base();
+    b.level.boxes[1].pos[0] += 1e-5;
+    b.player.spawn[1] += 1e-5;
+    assert!(base().diff(&b).is_empty());
+    let strict = base().diff_with(&b, &DiffOptions { tolerance: 0.0 });
+    assert_eq!(strict.fields.len(), 1);
+    assert_eq!(strict.collection("level.boxes").unwrap().modified(), 1);
+}
+
+#[test]
+fn reports_added_removed_and_modified_entities() {
+    let a = base();
+    let mut b = base();
+    b.level.boxes.remove(1);
+    b.level.boxes[2].color = [1.0, 0.0, 0.0];
+    b.lights[0].intensity = 3.0;
+    b.triggers.push(b.triggers[0].clone());
+    b.triggers[1].on_enter = "start_level:two".to_string();
+    b.player.spawn = [1.0, 1.5, 1.0];
+
+    let d = a.diff(&b);
+    let boxes = d.collection("level.boxes").unwrap();
+    assert_eq!((boxes.added(), boxes.removed(), boxes.modified()), (0, 1, 1));
+    assert!(boxes.changes.iter().any(|c| matches!(c, EntityChange::Removed { index: 1, .. })), "{d}");
+    assert!(
+        boxes.changes.iter().any(|c| matches!(c, EntityChange::Modified { old_index: 3, new_index: 2, moved: false, .. })),
+        "{d}"
+    );
+    let Some(EntityChange::Modified { fields, .. }) = d.collection("lights").unwrap().changes.first() else {
+        panic!("{d}")
+    };
+    assert_eq!(fields[0].path, "intensity");
+    assert_eq!(d.collection("triggers").unwrap().added(), 1);
+    assert!(d.collection("enemies").is_none());
+    assert_eq!(d.fields[0].path, "player.spawn");
+    assert!(d.to_string().contains("~ player.spawn: [0.0,1.5,0.0] -> [1.0,1.5,1.0]"), "{d}");
+}
+
+#[test]
+fn inserting_in_the_middle_does_not_cascade() {
+    let a = base();
+    let mut b = base();
+    b.level.boxes.insert(1, b.level.boxes[1].clone());
+    b.level.boxes[1].pos = [0.0, 5.0, 0.0];
+    let d = a.diff(&b);
+    let boxes = d.collection("level.boxes").unwrap();
+    assert_eq!(boxes.changes.len(), 1, "{d}");
+    assert_eq!(boxes.added(), 1);
+}
+
+#[test]
+fn patch_applies_and_inverts() {
+    let a = base();
+    let mut b = base();
+    b.level.boxes.swap(0, 3);
+    b.level.boxes[2].size = [2.0, 2.0, 2.0];
+    b.level.boxes.remove(1);
+    b.enemies.clear();
+    b.lights.push(b.lights[0].clone());
+    b.goals = None;
+    b.render.clear_color = [0.0, 0.0, 0.0, 1.0];
+
+    let opts = DiffOptions { tolerance: 0.0 };
+    let patch = ScenePatch::between(&a, &b, &opts);
+    let patched = patch.apply(&a).unwrap();
+    assert_eq!(to_string_pretty(&patched), to_string_pretty(&b));
+    let undone = patch.inverse().apply(&patched).unwrap();
+    assert_eq!(to_string_pretty(&undone), to_string_pretty(&a));
+}
+
+#[test]
+fn patch_between_levels_round_trips_through_json() {
+    let a = level("kengaquest_level1.json");
+    let b = level("kengaquest_level2.json");
+    let patch = ScenePatch::between(&a, &b, &DiffOptions { tolerance: 0.0 });
+    let stored: ScenePatch = serde_json::from_str(&serde_json::to_string(&patch).unwrap()).unwrap();
+    assert_eq!(stored, patch);
+    assert_eq!(to_string_pretty(&stored.apply(&a).unwrap()), to_string_pretty(&b));
+    assert_eq!(to_string_pretty(&stored.inverse().apply(&b).unwrap()), to_string_pretty(&a));
+}
+
+#[test]
+fn patch_refuses_a_scene_it_was_not_made_for() {
+    let a = base();
+    let mut b = base();
+    b.lights[0].intensity = 3.0;
+    let patch = ScenePatch::between(&a, &b, &DiffOptions::default());
+    let mut other = base();
+    other.lights[0].intensity = 2.0;
+    let err = patch.apply(&other).unwrap_err();
+    assert!(matches!(&err, PatchError::Conflict { path, .. } if path == "lights[0].intensity"), "{err}");
+}
@@ -0,0 +1,87 @@
+use kengaai_scene_fps::{code_frame, load_scene_checked, locate, parse_scene, SceneError};
+use std::fs;
+
+const SCENE: &str = r#"{
+  "meta": { "name": "Уровень", "schema": "KengaFPSSceneV0", "version": "0.1.0" },
+  "render": { "clearColor": [0.1, 0.2, 0.3, 1.0] },
+  "player": { "spawn": [0.0, 1.5, 4.0], "yaw": 0.0, "pitch": 0.0, "move": { "speed": 4.5, "run": 7.5 } },
+  "level": {
+    "boxes": [
+      { "pos": [0.0, 0.0, 0.0], "size": [10.0, 0.5, 10.0], "color": [0.5, 0.5, 0.5] }
+    ]
+  }
+}
+"#;
+
+/// The line with the caret under line `n` of a code frame, without its gutter
+fn caret_line(frame: &str, n: usize) -> &str {
+    let lines: Vec<&str> = frame.lines().collect();
+    let at = lines.iter().position(|l| l.trim_start().starts_with(&format!("{n} |"))).unwrap();
+    lines[at + 1].split_once(" | ").unwrap().1
+}
+
+#[test]
+fn code_frame_shows_context_and_a_caret_under_the_column() {
+    let src = "one\ntwo\nthree\nfour\nfive\nsix\n";
+    assert_eq!(code_frame(src, 3, 2), "1 | one\n2 | two\n3 | three\n  |  ^\n4 | four\n5 | five\n");
+    // lines past the end clamp to the last one
+    assert!(code_frame(src, 40, 1).ends_with("6 | six\n  | ^\n"));
+    assert_eq!(code_frame("", 1, 1), "");
+    // tabs are kept so the caret lines up however they are displayed
+    assert_eq!(caret_line(&code_frame("\tx = 1\n", 1, 2), 1), "\t^");
+}
+
+#[test]
+fn caret_counts_characters_not_bytes() {
+    // the value comes after the Cyrillic name, whose letters take two bytes each
+    let line = r#"  "name": "Уровень", "yaw": "north""#;
+    let byte_col = line.find("\"north\"").unwrap() + 1;
+    let frame = code_frame(line, 1, byte_col);
+    let caret = caret_line(&frame, 1);
+    assert_eq!(caret.chars().count(), line[..byte_col - 1].chars().count() + 1);
+    assert_eq!(caret.trim_start(), "^");
+    // a column inside a multi-byte character stays under that character
+    let inside = line.find('У').unwrap() + 2;
+    assert_eq!(caret_line(&code_frame(line, 1, inside), 1).len(), line[..inside - 2].chars().count() + 1);
+}
+
+#[test]
+fn syntax_errors_point_at_the_broken_token() {
+    let src = SCENE.replace("\"yaw\": 0.0,", "\"yaw\": 0.0,,");
+    let err = parse_scene(&src).unwrap_err();
+    let SceneError::Syntax { location, .. } = &err else { panic!("{err:?}") };
+    assert_eq!(location.line, 4);
+    assert_eq!(&src.lines().nth(3).unwrap()[location.column - 1..][..1], ",");
+    assert!(err.report().contains("4 | "), "{}", err.report());
+}
+
+#[test]
+fn schema_errors_carry_the_json_path_after_non_ascii_text() {
+    let src = SCENE.replace("\"version\": \"0.1.0\"", "\"version\": 1");
+    let err = parse_scene(&src).unwrap_err();
+    let SceneError::Schema { location, .. } = &err else { panic!("{err:?}") };
+    assert_eq!((location.line, location.json_path.as_str()), (2, "meta.version"));
+    assert_eq!(err.json_path(), Some("meta.version"));
+    // the column counts bytes, but the caret under the `1` has to skip the Cyrillic name by characters
+    let line = src.lines().nth(1).unwrap();
+    assert!(line[..location.column - 1].ends_with("\"version\": "), "{line:?} at {}", location.column);
+    assert_eq!(caret_line(&location.snippet, 2).chars().count(), line[..location.column - 1].chars().count() + 1);
+}
+
+#[test]
+fn semantic_errors_locate_the_first_error_in_the_file() {
+    let dir = std::env::temp_dir().join(format!("kenga_errors_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("bad.json");
+    fs::write(&path, SCENE.replace("[10.0, 0.5, 10.0]", "[10.0, -0.5, 10.0]")).unwrap();
+    let err = load_scene_checked(&path).unwrap_err();
+    let SceneError::Semantic { diagnostics, location, .. } = &err else { panic!("{err:?}") };
+    assert_eq!(diagnostics[0].path, "level.boxes[0].size");
+    let location = location.as_deref().unwrap();
+    assert_eq!((location.file.as_deref(), location.line), (Some(path.as_path()), 7));
+    assert!(err.report().contains(&format!("--> {}:7:", path.display())), "{}", err.report());
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(locate(SCENE, "player.yaw", None).map(|l| l.line), Some(4));
+    assert!(locate(SCENE, "player.roll", None).is_none());
+}
@@ -0,0 +1,127 @@
+use kengaai_scene_fps::{
+    convert_scene, encode_scene, load_scene, load_scene_checked, parse_scene_as, save_scene, to_string_pretty, FpsSceneBuilder,
+    LoadOptions, SceneError, SceneFormat, BINARY_MAGIC,
+};
+use std::fs;
+use std::path::Path;
+
+mod common;
+use common::levels;
+
+#[test]
+fn every_level_round_trips_through_every_format() {
+    for path in levels() {
+        let scene = load_scene(&path).unwrap();
+        let json = to_string_pretty(&scene);
+        for format in SceneFormat::ALL {
+            let bytes = encode_scene(&scene, format).unwrap();
+            let (back, _) = parse_scene_as(&bytes, format, &LoadOptions::default())
+                .unwrap_or_else(|e| panic!("{} as {format}: {}", path.display(), e.report()));
+            assert_eq!(to_string_pretty(&back), json, "{} as {format}", path.display());
+            assert_eq!(SceneFormat::sniff(&bytes), format, "{} as {format}", path.display());
+        }
+    }
+}
+
+#[test]
+fn binary_is_smaller_than_json() {
+    for path in levels() {
+        let scene = load_scene(&path).unwrap();
+        let bin = encode_scene(&scene, SceneFormat::Binary).unwrap();
+        assert!(bin.starts_with(&BINARY_MAGIC));
+        assert!(bin.len() < to_string_pretty(&scene).len(), "{}", path.display());
+    }
+}
+
+#[test]
+fn load_detects_format_from_extension_and_header() {
+    let dir = std::env::temp_dir().join(format!("kenga_formats_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let scene = load_scene(&levels()[0]).unwrap();
+    let expected = to_string_pretty(&scene);
+
+    for format in SceneFormat::ALL {
+        let path = dir.join(format!("level.{}", format.extension()));
+        save_scene(&scene, &path).unwrap();
+        assert_eq!(to_string_pretty(&load_scene(&path).unwrap()), expected, "{format}");
+    }
+    // the binary header wins over a misleading extension
+    let misnamed = dir.join("shipped.json");
+    fs::copy(dir.join("level.kfsb"), &misnamed).unwrap();
+    assert_eq!(to_string_pretty(&load_scene(&misnamed).unwrap()), expected);
+    // unknown extensions fall back to sniffing the content
+    let unknown = dir.join("level.scene");
+    fs::copy(dir.join("level.ron"), &unknown).unwrap();
+    assert_eq!(to_string_pretty(&load_scene(&unknown).unwrap()), expected);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn converting_into_another_directory_keeps_includes_working() {
+    let dir = std::env::temp_dir().join(format!("kenga_convert_includes_{}", std::process::id()));
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::create_dir_all(dir.join("scenes")).unwrap();
+    fs::create_dir_all(dir.join("out/ron")).unwrap();
+    fs::write(dir.join("lib/props.json"), r#"{ "prefabs": { "crate": { "boxes": [{ "pos": [0.0, 0.5, 0.0], "size": [0.5, 0.5, 0.5] }] } } }"#)
+        .unwrap();
+    let mut scene = FpsSceneBuilder::new("converted").build_unchecked();
+    scene.includes = vec!["../lib/props.json".into()];
+    save_scene(&scene, dir.join("scenes/level.json")).unwrap();
+
+    let to = dir.join("out/ron/level.ron");
+    convert_scene(dir.join("scenes/level.json"), &to).unwrap();
+    let converted = load_scene(&to).unwrap();
+    assert_eq!(converted.includes, ["../../lib/props.json"]);
+    assert!(converted.prefab("crate").is_some());
+
+    // and back next to the library, where the path gets shorter
+    convert_scene(&to, dir.join("lib/level.json")).unwrap();
+    assert_eq!(load_scene(dir.join("lib/level.json")).unwrap().includes, ["props.json"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ron_syntax_errors_point_into_the_source() {
+    let src = "(\n  meta: (\n    schema: \"KengaFPSSceneV0\",\n    version: 0.1.0,\n  ),\n)\n";
+    let err = parse_scene_as(src.as_bytes(), SceneFormat::Ron, &LoadOptions::default()).unwrap_err();
+    let SceneError::Syntax { location, .. } = &err else { panic!("{err:?}") };
+    assert_eq!(location.line, 4);
+    assert!(location.snippet.contains("version: 0.1.0"));
+}
+
+#[test]
+fn toml_schema_errors_name_the_file_and_field_without_a_position() {
+    let scene = load_scene(&levels()[0]).unwrap();
+    let toml = String::from_utf8(encode_scene(&scene, SceneFormat::Toml).unwrap()).unwrap();
+    let broken = toml.replacen("yaw = ", "yaw = \"north\" # ", 1);
+    let dir = std::env::temp_dir().join(format!("kenga_format_errors_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("broken.toml");
+    fs::write(&path, &broken).unwrap();
+    let err = load_scene(&path).unwrap_err();
+    let SceneError::Format { file, format, message } = &err else { panic!("{err:?}") };
+    assert_eq!((file.as_deref(), *format), (Some(path.as_path()), SceneFormat::Toml));
+    assert!(message.starts_with("at `player.yaw`: invalid type"), "{message}");
+    // lines of the JSON rendering would be meaningless in the TOML file
+    assert!(err.location().is_none());
+
+    // semantic errors keep the file but have no JSON source to point into
+    let bad = toml.replacen("speed = 4.5", "speed = -4.5", 1);
+    assert_ne!(bad, toml);
+    fs::write(&path, bad).unwrap();
+    let err = load_scene_checked(&path).unwrap_err();
+    let SceneError::Semantic { file, location, .. } = &err else { panic!("{err:?}") };
+    assert_eq!((file.as_deref(), location.is_none()), (Some(path.as_path()), true));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unsupported_binary_version_is_rejected() {
+    let scene = load_scene(&levels()[0]).unwrap();
+    let mut bin = encode_scene(&scene, SceneFormat::Binary).unwrap();
+    bin[BINARY_MAGIC.len()] = 99;
+    let err = parse_scene_as(&bin, SceneFormat::Binary, &LoadOptions::default()).unwrap_err();
+    assert!(matches!(err, SceneError::Format { format: SceneFormat::Binary, .. }), "{err}");
+    assert_eq!(SceneFormat::from_extension(Path::new("x.KFSB")), Some(SceneFormat::Binary));
+}
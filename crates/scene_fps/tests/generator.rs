@@ -0,0 +1,44 @@
+use kengaai_scene_fps::{generate_level, has_errors, to_string_pretty, BoxDef, GeneratorParams};
+
+fn inside(b: &BoxDef, p: [f32; 3]) -> bool {
+    (0..3).all(|i| (p[i] - b.pos[i]).abs() < b.size[i])
+}
+
+#[test]
+fn same_seed_same_scene() {
+    let params = GeneratorParams::default();
+    assert_eq!(to_string_pretty(&generate_level(42, &params)), to_string_pretty(&generate_level(42, &params)));
+    assert_ne!(to_string_pretty(&generate_level(42, &params)), to_string_pretty(&generate_level(43, &params)));
+}
+
+#[test]
+fn generated_levels_are_valid_and_playable() {
+    for seed in 0..32 {
+        let params = GeneratorParams { rooms: 3 + (seed as usize % 8), ..Default::default() };
+        let scene = generate_level(seed, &params);
+        let diags = scene.validate();
+        assert!(!has_errors(&diags), "seed {seed}: {diags:?}");
+
+        let goal = scene.goals.as_ref().expect("goal").point;
+        assert!(!scene.level.boxes.iter().any(|b| inside(b, goal)), "seed {seed}: goal inside a box");
+        for e in &scene.enemies {
+            assert_eq!(e.patrol.first(), Some(&e.spawn));
+            for p in &e.patrol {
+                assert!(!scene.level.boxes.iter().any(|b| inside(b, *p)), "seed {seed}: patrol point {p:?} inside a box");
+            }
+        }
+        assert!(!scene.lights.is_empty() && !scene.triggers.is_empty());
+    }
+}
+
+#[test]
+fn parameters_shape_the_level() {
+    let sparse = generate_level(7, &GeneratorParams { enemy_density: 0.0, ..Default::default() });
+    assert!(sparse.enemies.is_empty());
+    let dense = generate_level(7, &GeneratorParams { enemy_density: 8.0, ..Default::default() });
+    assert!(dense.enemies.len() > 4);
+
+    let tall = generate_level(7, &GeneratorParams { ceiling_height: 9.0, ..Default::default() });
+    let top = tall.level.boxes.iter().map(|b| b.pos[1] + b.size[1]).fold(f32::MIN, f32::max);
+    assert_eq!(top, 9.5);
+}
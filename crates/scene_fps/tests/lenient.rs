@@ -0,0 +1,91 @@
+use kengaai_scene_fps::{parse_scene, parse_scene_lenient, RepairKind, RepairLimits, SceneError};
+
+const LLM_REPLY: &str = r#"Here is your level:
+
+```json
+{
+  // generated scene
+  "meta": { "schema": "KengaFPSSceneV0", "version": "0.1.0", "name": "llm_level" },
+  "render": { "clearColor": [0.1, 0.1, 0.1] },
+  "player": { "spawn": [0.0, 1.5, 0.0], "yaw": 0.0, "pitch": 0.0, "move": { "speed": 4.5, "run": 7.5 } },
+  "level": {
+    "boxes": [
+      { "pos": [0.0, -0.5, 0.0], "size": [20.0, 0.5, 20.0], "rotY": 0.0, "color": [0.3, 0.3, 0.3] },
+      { "pos": [4.0, 1.0, 0.0], "size": [1.0, 1.0, 1.0] },
+      { "pos": [9000.0, 1.0, 0.0], "size": [-1.0, 1.0, 1.0], "color": [2.0, 0.5, 0.5, 1.0], }, /* too far */
+      { "pos": [0.0, 1.0, 4.0], "size": [1.0, 1.0, 1.0], "rotY": 0.5, "color": [0.5, 0.5, 0.5] },
+    ],
+  },
+  "enemies": [
+    { "kind": "grunt", "spawn": [3.0, 1.0, -6.0] },
+    { "kind": "grunt", "spawn": [-3.0, 1.0, -6.0] },
+  ],
+  "triggers": [{ "pos": [0.0, 1.0, -8.0], "size": [1.0, 1.0, 1.0], "onEnter": "spawn_wave:grunt:3" }],
+  "goals": { "type": "extract", "point": [0.0, 0.5, -9.0] }
+}
+```
+Enjoy!"#;
+
+#[test]
+fn strict_parser_rejects_the_reply() {
+    assert!(matches!(parse_scene(LLM_REPLY), Err(SceneError::Syntax { .. })));
+}
+
+#[test]
+fn repairs_llm_output_and_reports_every_fix() {
+    let limits = RepairLimits { max_boxes: 3, max_enemies: 1, ..Default::default() };
+    let (scene, report) = parse_scene_lenient(LLM_REPLY, &limits).unwrap();
+
+    assert_eq!(scene.meta.name, "llm_level");
+    assert_eq!(scene.level.boxes.len(), 3);
+    assert_eq!(scene.enemies.len(), 1);
+    assert_eq!(scene.render.clear_color, [0.1, 0.1, 0.1, 1.0]);
+
+    let b1 = &scene.level.boxes[1];
+    // fields the canonical writer may omit take their serde defaults and are not repairs
+    assert_eq!((b1.rot_y, b1.color), (0.0, [0.0, 0.0, 0.0]));
+    assert!(!report.to_string().contains("level.boxes[1]"), "{report}");
+    let b2 = &scene.level.boxes[2];
+    assert_eq!(b2.pos[0], limits.max_coord);
+    assert_eq!(b2.size[0], limits.min_half_extent);
+    assert_eq!(b2.color, [1.0, 0.5, 0.5]);
+
+    let text = report.to_string();
+    for expected in [
+        "removed markdown code fence",
+        "removed 2 comment(s)",
+        "removed 4 trailing comma(s)",
+        "render.clearColor: had 3 components, expected 4",
+        "level.boxes[2].color: had 4 components, expected 3",
+        "level.boxes: kept the first 3 of 4",
+        "enemies: kept the first 1 of 2",
+        "level.boxes[2].pos: [9000.0, 1.0, 0.0] -> [1000.0, 1.0, 0.0]",
+    ] {
+        assert!(text.contains(expected), "missing `{expected}` in:\n{text}");
+    }
+    assert_eq!(report.count(RepairKind::Truncate), 2);
+    assert_eq!(report.count(RepairKind::Clamp), 3);
+}
+
+#[test]
+fn clean_scenes_need_no_repairs() {
+    let src = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/../../assets/levels/kengaquest_level1.json")).unwrap();
+    let (_, report) = parse_scene_lenient(&src, &RepairLimits::default()).unwrap();
+    assert!(report.is_empty(), "{report}");
+}
+
+#[test]
+fn fills_a_missing_schema_and_keeps_strings_intact() {
+    let src = r#"{"meta": {"name": "x"}, "player": {}, "level": {"boxes": []},
+        "triggers": [{"pos": [0, 1, 0], "size": [1, 1, 1], "onEnter": "start_level:a//b,]"}]}"#;
+    let (scene, report) = parse_scene_lenient(src, &RepairLimits::default()).unwrap();
+    assert_eq!(scene.meta.schema, "KengaFPSSceneV0");
+    assert_eq!(scene.triggers[0].on_enter, "start_level:a//b,]");
+    assert_eq!(report.count(RepairKind::Cleanup), 0, "{report}");
+}
+
+#[test]
+fn unrepairable_input_still_fails_with_a_location() {
+    let err = parse_scene_lenient("```json\n{ \"meta\": [1, 2 }\n```", &RepairLimits::default()).unwrap_err();
+    assert!(err.location().is_some(), "{err}");
+}
@@ -0,0 +1,118 @@
+use kengaai_scene_fps::{
+    detect_version, parse_scene_with, schema_tag, to_string_pretty, FpsSceneBuilder, LoadOptions, Migration,
+    MigrationError, MigrationReport, Migrator, SceneError, CURRENT_SCHEMA_VERSION,
+};
+use serde_json::{json, Value};
+
+fn doc(version: u32) -> Value {
+    json!({ "meta": { "schema": schema_tag(version), "version": "0.1.0", "name": "m" }, "level": { "walls": [] } })
+}
+
+/// V0 renames `level.walls` to `level.boxes`; V1 adds a `render` section. Each step checks that it
+/// sees the document its predecessor left behind.
+fn steps() -> Vec<Migration> {
+    vec![
+        Migration {
+            from: 1,
+            description: "add render defaults",
+            apply: |doc| {
+                if doc["meta"]["schema"] != "KengaFPSSceneV1" || doc["level"].get("boxes").is_none() {
+                    return Err(format!("ran out of order on {doc}"));
+                }
+                doc["render"] = json!({ "clearColor": [0.0, 0.0, 0.0, 1.0] });
+                Ok(())
+            },
+        },
+        Migration {
+            from: 0,
+            description: "rename level.walls to level.boxes",
+            apply: |doc| {
+                let level = doc["level"].as_object_mut().ok_or("level is not an object")?;
+                let walls = level.remove("walls").ok_or("level.walls is missing")?;
+                level.insert("boxes".into(), walls);
+                Ok(())
+            },
+        },
+    ]
+}
+
+#[test]
+fn versions_are_read_from_the_schema_tag() {
+    assert_eq!(detect_version(&doc(0)), Ok(0));
+    assert_eq!(detect_version(&doc(12)), Ok(12));
+    assert_eq!(detect_version(&json!({ "meta": {} })), Err(MigrationError::MissingSchema));
+    assert_eq!(detect_version(&json!({ "meta": { "schema": 1 } })), Err(MigrationError::MissingSchema));
+    for tag in ["OtherSceneV1", "KengaFPSSceneV", "KengaFPSSceneVx"] {
+        assert_eq!(detect_version(&json!({ "meta": { "schema": tag } })), Err(MigrationError::UnknownSchema(tag.into())));
+    }
+}
+
+#[test]
+fn steps_run_in_generation_order_and_are_reported() {
+    let mut d = doc(0);
+    let report = Migrator::with_steps(steps(), 2).migrate(&mut d).unwrap();
+    assert_eq!(
+        report,
+        MigrationReport {
+            from: 0,
+            to: 2,
+            applied: vec!["V0 -> V1: rename level.walls to level.boxes".into(), "V1 -> V2: add render defaults".into()],
+        }
+    );
+    assert_eq!(d["meta"]["schema"], "KengaFPSSceneV2");
+    assert_eq!(d["level"], json!({ "boxes": [] }));
+    assert!(d.get("render").is_some());
+
+    // a document part way along the chain only runs the remaining steps
+    let mut d = doc(1);
+    d["level"] = json!({ "boxes": [] });
+    let report = Migrator::with_steps(steps(), 2).migrate(&mut d).unwrap();
+    assert_eq!((report.from, report.to, report.applied.len()), (1, 2, 1));
+
+    // and a current one runs none
+    let mut d = doc(2);
+    let report = Migrator::with_steps(steps(), 2).migrate(&mut d).unwrap();
+    assert!(report.is_empty());
+    assert_eq!(d, doc(2));
+}
+
+#[test]
+fn gaps_and_failing_steps_stop_the_chain() {
+    let mut only_first = steps();
+    only_first.retain(|m| m.from == 0);
+    let mut d = doc(0);
+    assert_eq!(Migrator::with_steps(only_first, 2).migrate(&mut d), Err(MigrationError::MissingStep(1)));
+
+    let mut d = json!({ "meta": { "schema": "KengaFPSSceneV0" }, "level": [] });
+    let err = Migrator::with_steps(steps(), 2).migrate(&mut d).unwrap_err();
+    assert_eq!(err, MigrationError::StepFailed { from: 0, message: "level is not an object".into() });
+    assert_eq!(err.to_string(), "migration V0 -> V1 failed: level is not an object");
+}
+
+#[test]
+fn future_versions_pass_through_unless_rejected() {
+    let mut d = doc(5);
+    let report = Migrator::with_steps(steps(), 2).migrate(&mut d).unwrap();
+    assert_eq!((report.from, report.to), (5, 5));
+    assert!(report.is_empty());
+    assert_eq!(d, doc(5));
+
+    let err = Migrator::with_steps(steps(), 2).reject_future_versions(true).migrate(&mut d).unwrap_err();
+    assert_eq!(err, MigrationError::FutureVersion { found: 5, supported: 2 });
+}
+
+#[test]
+fn the_loader_flag_rejects_future_scenes() {
+    let current = to_string_pretty(&FpsSceneBuilder::new("m").build_unchecked());
+    let (_, report) = parse_scene_with(&current, &LoadOptions { reject_future_versions: true, ..Default::default() }).unwrap();
+    assert!(report.is_empty());
+
+    let future = current.replace(&schema_tag(CURRENT_SCHEMA_VERSION), &schema_tag(CURRENT_SCHEMA_VERSION + 1));
+    assert!(parse_scene_with(&future, &LoadOptions::default()).is_ok());
+    let err = parse_scene_with(&future, &LoadOptions { reject_future_versions: true, ..Default::default() }).unwrap_err();
+    let SceneError::Migration { source, .. } = &err else { panic!("{err:?}") };
+    assert_eq!(
+        *source,
+        MigrationError::FutureVersion { found: CURRENT_SCHEMA_VERSION + 1, supported: CURRENT_SCHEMA_VERSION }
+    );
+}
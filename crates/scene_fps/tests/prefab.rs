@@ -0,0 +1,168 @@
+use kengaai_scene_fps::{
+    load_scene, quat_mul, save_scene, to_string_pretty, FpsScene, FpsSceneBuilder, Prefab, PrefabError, PrefabInstance,
+    PrefabOverrides, Rotation, SceneError,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/prefabs")
+}
+
+/// Save a scene including `includes` (absolute paths) into a fresh temp dir and load it back
+fn load_with_includes(tag: &str, includes: &[PathBuf], own: &[&str]) -> Result<FpsScene, SceneError> {
+    let dir = std::env::temp_dir().join(format!("kenga_prefab_{tag}_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut scene = FpsSceneBuilder::new(tag).build_unchecked();
+    scene.includes = includes.iter().map(|p| p.display().to_string()).collect();
+    for name in own {
+        scene.prefabs.insert(name.to_string(), Prefab::default());
+    }
+    let path = dir.join("scene.json");
+    save_scene(&scene, &path).unwrap();
+    let loaded = load_scene(&path);
+    fs::remove_dir_all(&dir).unwrap();
+    loaded
+}
+
+fn place(prefab: &str, pos: [f32; 3], overrides: PrefabOverrides) -> PrefabInstance {
+    PrefabInstance { prefab: prefab.into(), pos, rot_y: 0.0, rotation: None, overrides }
+}
+
+fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+    a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-5)
+}
+
+#[test]
+fn includes_reached_twice_are_loaded_once() {
+    let path = fixtures().join("diamond/scene.json");
+    let scene = load_scene(&path).unwrap();
+    assert_eq!(scene.included_prefabs.keys().collect::<Vec<_>>(), ["crate", "lamp", "pillar"]);
+    assert_eq!(scene.prefabs.keys().collect::<Vec<_>>(), ["marker"]);
+    assert!(scene.prefab("crate").is_some() && scene.prefab("marker").is_some());
+    assert!(scene.validate().is_empty(), "{:?}", scene.validate());
+}
+
+#[test]
+fn saving_keeps_includes_and_leaves_library_prefabs_out() {
+    let path = fixtures().join("diamond/scene.json");
+    let scene = load_scene(&path).unwrap();
+    assert_eq!(scene.includes, ["walls.json"]);
+    assert_eq!(to_string_pretty(&scene), fs::read_to_string(&path).unwrap());
+}
+
+#[test]
+fn include_cycles_name_the_files_involved() {
+    let first = fixtures().join("cycle/first.json");
+    let err = load_with_includes("cycle", std::slice::from_ref(&first), &[]).unwrap_err();
+    let SceneError::Prefab { source: PrefabError::IncludeCycle { chain }, .. } = &err else { panic!("{err:?}") };
+    let names: Vec<_> = chain.iter().map(|p| p.file_name().unwrap().to_str().unwrap()).collect();
+    assert_eq!(names, ["first.json", "second.json", "first.json"]);
+    assert!(chain.iter().all(|p| p.is_absolute()));
+}
+
+#[test]
+fn a_prefab_name_may_only_be_defined_once() {
+    let dup = fixtures().join("duplicate");
+    let err = load_with_includes("dup", &[dup.join("left.json"), dup.join("right.json")], &[]).unwrap_err();
+    let SceneError::Prefab { file, source } = &err else { panic!("{err:?}") };
+    assert_eq!(*source, PrefabError::Duplicate { name: "crate".into() });
+    assert_eq!(file.as_deref().and_then(Path::file_name).unwrap(), "right.json");
+
+    // an inline prefab may not share its name with one from a library either
+    let err = load_with_includes("shadow", &[dup.join("left.json")], &["crate"]).unwrap_err();
+    assert!(matches!(err, SceneError::Prefab { source: PrefabError::Duplicate { .. }, .. }), "{err:?}");
+}
+
+#[test]
+fn flatten_applies_transforms_and_overrides() {
+    let scene = load_scene(fixtures().join("diamond/scene.json")).unwrap();
+    let flat = scene.flatten().unwrap();
+    assert!(!flat.has_prefabs() && flat.includes.is_empty() && flat.prefabs.is_empty() && flat.included_prefabs.is_empty());
+
+    // floor, pillar and its nested crate, marker
+    assert_eq!(flat.level.boxes.len(), 4);
+    let pillar = &flat.level.boxes[1];
+    assert!(close(pillar.pos, [4.0, 1.0, 0.0]), "{:?}", pillar.pos);
+    assert_eq!(pillar.color, [0.2, 0.8, 0.2]);
+    // the crate sits one unit along the pillar's rotated X axis and takes the outer color
+    let crate_box = &flat.level.boxes[2];
+    assert!(close(crate_box.pos, [4.0, 0.5, 1.0]), "{:?}", crate_box.pos);
+    assert_eq!(crate_box.color, [0.2, 0.8, 0.2]);
+    assert_eq!(crate_box.texture.as_deref(), Some("crate.png"));
+    assert!(close(flat.level.boxes[3].pos, [0.0, 0.1, -3.0]));
+
+    // the lamp is scaled by two and its trigger runs the instance's action
+    assert!(close(flat.lights[0].position, [-4.0, 4.0, 0.0]), "{:?}", flat.lights[0].position);
+    assert_eq!(flat.triggers[0].on_enter, "start_level:level2");
+    assert!(close(flat.triggers[0].size, [2.0; 3]));
+}
+
+#[test]
+fn outer_overrides_win_over_nested_ones() {
+    let mut scene = FpsSceneBuilder::new("nested").build_unchecked();
+    let inner: Prefab = serde_json::from_str(
+        r#"{ "boxes": [{ "pos": [0.0, 0.0, 0.0], "size": [1.0, 1.0, 1.0], "color": [1.0, 0.0, 0.0] }] }"#,
+    )
+    .unwrap();
+    let outer = Prefab {
+        instances: vec![place("inner", [0.0; 3], PrefabOverrides { color: Some([0.0, 1.0, 0.0]), texture: Some("a.png".into()), ..Default::default() })],
+        ..Default::default()
+    };
+    scene.prefabs.insert("inner".into(), inner);
+    scene.prefabs.insert("outer".into(), outer);
+    scene.instances = vec![
+        place("outer", [0.0; 3], PrefabOverrides { color: Some([0.0, 0.0, 1.0]), ..Default::default() }),
+        place("outer", [2.0, 0.0, 0.0], PrefabOverrides::default()),
+    ];
+    let flat = scene.flatten().unwrap();
+    assert_eq!(flat.level.boxes[0].color, [0.0, 0.0, 1.0]);
+    assert_eq!(flat.level.boxes[0].texture.as_deref(), Some("a.png"));
+    assert_eq!(flat.level.boxes[1].color, [0.0, 1.0, 0.0]);
+}
+
+#[test]
+fn unknown_and_self_referencing_prefabs_fail_to_flatten() {
+    let mut scene = FpsSceneBuilder::new("broken").build_unchecked();
+    scene.instances = vec![place("nowhere", [0.0; 3], PrefabOverrides::default())];
+    assert_eq!(scene.flatten().unwrap_err(), PrefabError::Unknown { path: "instances[0]".into(), name: "nowhere".into() });
+
+    let a = Prefab { instances: vec![place("b", [0.0; 3], PrefabOverrides::default())], ..Default::default() };
+    let b = Prefab { instances: vec![place("a", [0.0; 3], PrefabOverrides::default())], ..Default::default() };
+    scene.prefabs.insert("a".into(), a);
+    scene.prefabs.insert("b".into(), b);
+    scene.instances = vec![place("a", [0.0; 3], PrefabOverrides::default())];
+    assert_eq!(scene.flatten().unwrap_err(), PrefabError::Cycle { chain: vec!["a".into(), "b".into(), "a".into()] });
+}
+
+#[test]
+fn instances_may_carry_a_full_rotation() {
+    let mut scene = FpsSceneBuilder::new("tilted").build_unchecked();
+    let beam: Prefab = serde_json::from_str(
+        r#"{
+            "boxes": [{ "pos": [0.0, 1.0, 0.0], "size": [1.0, 2.0, 3.0], "rotY": 0.5 }],
+            "lights": [{ "kind": "spot", "position": [0.0, 1.0, 0.0], "direction": [0.0, -1.0, 0.0], "color": [1.0, 1.0, 1.0], "intensity": 1.0 }],
+            "triggers": [{ "pos": [0.0, 0.0, 0.0], "size": [1.0, 2.0, 3.0], "onEnter": "win" }]
+        }"#,
+    )
+    .unwrap();
+    scene.prefabs.insert("beam".into(), beam);
+    // pitched a quarter turn about X, so the prefab's Y axis ends up along Z
+    let mut inst = place("beam", [5.0, 0.0, 0.0], PrefabOverrides::default());
+    inst.rotation = Some(Rotation::Euler([std::f32::consts::FRAC_PI_2, 0.0, 0.0]));
+    scene.instances = vec![inst.clone()];
+    let flat = scene.flatten().unwrap();
+
+    let b = &flat.level.boxes[0];
+    assert!(close(b.pos, [5.0, 0.0, 1.0]), "{:?}", b.pos);
+    assert_eq!(b.rot_y, 0.0);
+    let expected = quat_mul(inst.orientation(), Rotation::Euler([0.0, 0.5, 0.0]).to_quat());
+    let Some(Rotation::Quat(q)) = b.rotation else { panic!("{:?}", b.rotation) };
+    assert!(q.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-5), "{q:?}");
+    assert!(close(flat.lights[0].direction.unwrap(), [0.0, 0.0, -1.0]), "{:?}", flat.lights[0].direction);
+    assert!(close(flat.triggers[0].size, [1.0, 3.0, 2.0]), "{:?}", flat.triggers[0].size);
+
+    // the field is only written when set
+    assert!(serde_json::to_string(&inst).unwrap().contains("\"rotation\""));
+    assert!(!serde_json::to_string(&place("beam", [0.0; 3], PrefabOverrides::default())).unwrap().contains("rotation"));
+}
@@ -0,0 +1,147 @@
+use kengaai_scene_fps::{
+    parse_scene, to_string_pretty, Behavior, BoxDef, ChaseParams, Enemy, FpsScene, FpsSceneBuilder, GoalKind, Light,
+    LightKind, Material, PatrolParams, Rotation, Trigger,
+};
+use proptest::prelude::*;
+use std::fs;
+
+mod common;
+use common::levels;
+
+#[test]
+fn levels_are_stored_canonically() {
+    for path in levels() {
+        let src = fs::read_to_string(&path).unwrap();
+        let scene = parse_scene(&src).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+        assert_eq!(to_string_pretty(&scene), src, "{} is not canonical", path.display());
+    }
+}
+
+fn float() -> impl Strategy<Value = f32> {
+    prop_oneof![
+        Just(0.0f32),
+        Just(1.0f32),
+        -100.0f32..100.0,
+        prop::num::f32::NORMAL | prop::num::f32::SUBNORMAL,
+    ]
+}
+
+fn vec3() -> impl Strategy<Value = [f32; 3]> {
+    [float(), float(), float()]
+}
+
+fn name() -> impl Strategy<Value = String> {
+    "[a-z_]{1,8}"
+}
+
+fn rotation() -> impl Strategy<Value = Rotation> {
+    prop_oneof![[float(), float(), float(), float()].prop_map(Rotation::Quat), vec3().prop_map(Rotation::Euler)]
+}
+
+fn material() -> impl Strategy<Value = Material> {
+    (prop::option::of(vec3()), prop::option::of(name()), float(), float())
+        .prop_map(|(color, texture, metallic, roughness)| Material { color, texture, metallic, roughness })
+}
+
+fn boxes() -> impl Strategy<Value = Vec<BoxDef>> {
+    prop::collection::vec(
+        (vec3(), vec3(), float(), prop::option::of(rotation()), vec3(), prop::option::of(name()), prop::option::of(material()))
+            .prop_map(|(pos, size, rot_y, rotation, color, texture, material)| BoxDef {
+                pos,
+                size,
+                rot_y,
+                rotation,
+                color,
+                texture,
+                material,
+            }),
+        0..6,
+    )
+}
+
+fn lights() -> impl Strategy<Value = Vec<Light>> {
+    prop::collection::vec(
+        (
+            prop_oneof![Just(LightKind::Point), Just(LightKind::Directional), Just(LightKind::Spot)],
+            vec3(),
+            vec3(),
+            float(),
+            prop::option::of(float()),
+            prop::option::of(vec3()),
+            (prop::option::of(float()), prop::option::of(float())),
+            any::<bool>(),
+            prop::option::of(1u32..8192),
+        )
+            .prop_map(
+                |(kind, position, color, intensity, range, direction, (inner_angle, outer_angle), cast_shadows, shadow_resolution)| Light {
+                    kind,
+                    position,
+                    color,
+                    intensity,
+                    range,
+                    direction,
+                    inner_angle,
+                    outer_angle,
+                    cast_shadows,
+                    shadow_resolution,
+                },
+            ),
+        0..4,
+    )
+}
+
+fn enemies() -> impl Strategy<Value = Vec<Enemy>> {
+    let behavior = prop_oneof![
+        Just(None),
+        (prop::option::of(float()), any::<bool>()).prop_map(|(speed, ping_pong)| {
+            Some(Behavior::Patrol(PatrolParams { speed, wait_time: None, ping_pong }))
+        }),
+        prop::option::of(float()).prop_map(|detection_range| {
+            Some(Behavior::Chase(ChaseParams { detection_range, ..Default::default() }))
+        }),
+    ];
+    prop::collection::vec(
+        (name(), vec3(), prop::collection::vec(vec3(), 0..3), behavior).prop_map(|(kind, spawn, patrol, behavior)| {
+            Enemy { kind, spawn, patrol, physics: None, behavior }
+        }),
+        0..4,
+    )
+}
+
+fn triggers() -> impl Strategy<Value = Vec<Trigger>> {
+    prop::collection::vec(
+        (vec3(), vec3(), "[a-z_]{1,8}(:[a-z0-9]{1,4})?").prop_map(|(pos, size, on_enter)| Trigger { pos, size, on_enter }),
+        0..4,
+    )
+}
+
+fn scene() -> impl Strategy<Value = FpsScene> {
+    (name(), vec3(), float(), boxes(), lights(), enemies(), triggers(), prop::option::of(vec3())).prop_map(
+        |(name, spawn, yaw, boxes, lights, enemies, triggers, goal)| {
+            let mut b = FpsSceneBuilder::new(name).spawn(spawn, yaw);
+            if let Some(point) = goal {
+                b = b.goal(GoalKind::Collect, point);
+            }
+            let mut scene = b.build_unchecked();
+            scene.level.boxes = boxes;
+            scene.lights = lights;
+            scene.enemies = enemies;
+            scene.triggers = triggers;
+            scene
+        },
+    )
+}
+
+proptest! {
+    #[test]
+    fn save_load_save_is_stable(scene in scene()) {
+        let first = to_string_pretty(&scene);
+        let loaded = parse_scene(&first).unwrap();
+        prop_assert_eq!(&to_string_pretty(&loaded), &first);
+        // floats survive bit for bit, apart from -0.0 which may be dropped as a default
+        for (a, b) in scene.level.boxes.iter().zip(&loaded.level.boxes) {
+            prop_assert_eq!(a.pos.map(|x| x + 0.0), b.pos.map(|x| x + 0.0));
+            prop_assert_eq!(a.size.map(|x| x + 0.0), b.size.map(|x| x + 0.0));
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use jsonschema::JSONSchema;
+use kengaai_scene_fps::scene_json_schema;
+use serde_json::Value;
+use std::fs;
+
+mod common;
+use common::levels;
+
+fn compile() -> JSONSchema {
+    JSONSchema::compile(&scene_json_schema()).expect("generated schema compiles")
+}
+
+#[test]
+fn every_level_matches_schema() {
+    let schema = compile();
+    let levels = levels();
+    assert!(!levels.is_empty());
+    for path in levels {
+        let doc: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let msgs: Vec<String> = match schema.validate(&doc) {
+            Ok(()) => continue,
+            Err(errors) => errors.map(|e| format!("{}: {}", e.instance_path, e)).collect(),
+        };
+        panic!("{} does not match the schema:\n{}", path.display(), msgs.join("\n"));
+    }
+}
+
+#[test]
+fn schema_rejects_unknown_enum_values() {
+    let schema = compile();
+    let path = levels().into_iter().find(|p| p.ends_with("kengaquest_level1.json")).unwrap();
+    let mut doc: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+    doc["lights"][0]["kind"] = "area".into();
+    assert!(!schema.is_valid(&doc));
+}
+
+#[test]
+fn schema_uses_serde_names() {
+    let schema = scene_json_schema();
+    let defs = &schema["definitions"];
+    assert!(defs["Render"]["properties"]["clearColor"].is_object());
+    assert!(defs["BoxDef"]["properties"]["rotY"].is_object());
+    assert!(defs["Player"]["properties"]["move"].is_object());
+    assert!(defs["Trigger"]["properties"]["onEnter"].is_object());
+    assert!(schema["properties"]["lights"].is_object());
+}
@@ -0,0 +1,108 @@
+use kengaai_scene_fps::{
+    has_errors, parse_scene, quat_y, rotate, to_string_pretty, BoxDef, FpsSceneBuilder, Group, Rotation,
+};
+
+fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+    a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < 1e-5)
+}
+
+fn unit_box(pos: [f32; 3]) -> BoxDef {
+    BoxDef { pos, size: [1.0; 3], rot_y: 0.0, rotation: None, color: [1.0; 3], texture: None, material: None }
+}
+
+#[test]
+fn rot_y_turns_x_toward_z_and_matches_euler_yaw() {
+    let a = 0.7f32;
+    assert!(close(rotate(quat_y(a), [1.0, 0.0, 0.0]), [a.cos(), 0.0, a.sin()]));
+    let euler = Rotation::Euler([0.0, a, 0.0]).to_quat();
+    assert!(close(rotate(euler, [1.0, 2.0, 3.0]), rotate(quat_y(a), [1.0, 2.0, 3.0])));
+    // pitch tips +Y toward +Z, then yaw turns +Z toward -X
+    let q = Rotation::Euler([std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2, 0.0]).to_quat();
+    assert!(close(rotate(q, [0.0, 1.0, 0.0]), [-1.0, 0.0, 0.0]), "{:?}", rotate(q, [0.0, 1.0, 0.0]));
+    assert!(close(rotate(q, [0.0, 0.0, 1.0]), [0.0, -1.0, 0.0]), "{:?}", rotate(q, [0.0, 0.0, 1.0]));
+    // roll is applied first: it tips +X toward +Y before the pitch
+    let q = Rotation::Euler([std::f32::consts::FRAC_PI_2, 0.0, std::f32::consts::FRAC_PI_2]).to_quat();
+    assert!(close(rotate(q, [1.0, 0.0, 0.0]), [0.0, 0.0, 1.0]), "{:?}", rotate(q, [1.0, 0.0, 0.0]));
+}
+
+#[test]
+fn nested_groups_compose_parent_transforms() {
+    let inner = Group { pos: [0.0, 0.0, 2.0], rot_y: 0.5, boxes: vec![unit_box([1.0, 0.0, 0.0])], ..Default::default() };
+    let outer = Group { pos: [10.0, 0.0, 0.0], rot_y: std::f32::consts::FRAC_PI_2, groups: vec![inner], ..Default::default() };
+    let scene = FpsSceneBuilder::new("groups").box_at([0.0, -1.0, 0.0], [5.0, 0.5, 5.0]).group(outer).build_unchecked();
+
+    let boxes = scene.level.world_boxes();
+    assert_eq!(boxes.len(), 2);
+    assert!(close(boxes[0].pos, [0.0, -1.0, 0.0]));
+    // the outer quarter turn maps local +Z to world -X, and the inner box is one unit along the inner X
+    let b = &boxes[1];
+    let expected_inner = [10.0 - 2.0, 0.0, 0.0];
+    let off = rotate(quat_y(std::f32::consts::FRAC_PI_2 + 0.5), [1.0, 0.0, 0.0]);
+    assert!(close(b.pos, [expected_inner[0] + off[0], off[1], off[2]]), "{:?}", b.pos);
+    // yaw-only chains stay expressible as rotY
+    assert_eq!(b.rotation, None);
+    assert!((b.rot_y - (std::f32::consts::FRAC_PI_2 + 0.5)).abs() < 1e-6);
+
+    let flat = scene.flatten().unwrap();
+    assert!(flat.level.groups.is_empty());
+    assert_eq!(flat.level.boxes.len(), 2);
+    assert!(close(flat.level.boxes[1].pos, b.pos));
+}
+
+#[test]
+fn tilted_groups_give_children_full_rotations() {
+    let ramp = Group {
+        rotation: Some(Rotation::Euler([0.3, 0.0, 0.0])),
+        boxes: vec![BoxDef { rot_y: 0.2, ..unit_box([0.0, 0.0, 0.0]) }],
+        ..Default::default()
+    };
+    let scene = FpsSceneBuilder::new("ramp").group(ramp).build_unchecked();
+    let b = &scene.level.world_boxes()[0];
+    let q = match b.rotation {
+        Some(Rotation::Quat(q)) => q,
+        other => panic!("expected a quaternion, got {other:?}"),
+    };
+    let expected = rotate(Rotation::Euler([0.3, 0.0, 0.0]).to_quat(), rotate(quat_y(0.2), [1.0, 0.0, 0.0]));
+    assert!(close(rotate(q, [1.0, 0.0, 0.0]), expected));
+}
+
+#[test]
+fn rotations_load_from_arrays_and_round_trip() {
+    let scene = FpsSceneBuilder::new("rot")
+        .box_at([0.0, 0.0, 0.0], [1.0; 3])
+        .rotation(Rotation::Quat([0.0, 0.0, 0.38268343, 0.9238795]))
+        .box_at([4.0, 0.0, 0.0], [1.0; 3])
+        .rotation(Rotation::Euler([0.25, 0.0, 0.0]))
+        .group(Group { name: "beams".into(), pos: [0.0, 3.0, 0.0], boxes: vec![unit_box([1.0, 0.0, 0.0])], ..Default::default() })
+        .build()
+        .unwrap();
+    let json = to_string_pretty(&scene);
+    assert!(json.contains("\"rotation\": [0.0, 0.0, 0.38268343, 0.9238795]"), "{json}");
+    assert!(json.contains("\"rotation\": [0.25, 0.0, 0.0]"), "{json}");
+
+    let back = parse_scene(&json).unwrap();
+    assert_eq!(back.level.boxes[0].rotation, Some(Rotation::Quat([0.0, 0.0, 0.38268343, 0.9238795])));
+    assert_eq!(back.level.boxes[1].rotation, Some(Rotation::Euler([0.25, 0.0, 0.0])));
+    assert_eq!(back.level.groups[0].name, "beams");
+    assert_eq!(to_string_pretty(&back), json);
+}
+
+#[test]
+fn validation_checks_grouped_entities_and_rotations() {
+    let bad = Group {
+        rotation: Some(Rotation::Quat([0.0; 4])),
+        boxes: vec![BoxDef { size: [1.0, 0.0, 1.0], ..unit_box([0.0, 20.0, 0.0]) }],
+        ..Default::default()
+    };
+    let scene = FpsSceneBuilder::new("bad").group(bad).build_unchecked();
+    let diags = scene.validate();
+    assert!(has_errors(&diags));
+    let paths: Vec<&str> = diags.iter().map(|d| d.path.as_str()).collect();
+    assert!(paths.contains(&"level.groups[0].rotation"), "{paths:?}");
+    assert!(paths.contains(&"level.groups[0].boxes[0].size"), "{paths:?}");
+
+    // a spawn point inside a grouped box is reported too
+    let around_spawn = Group { boxes: vec![unit_box([0.0, 0.0, 0.0])], pos: [0.0, 1.0, 0.0], ..Default::default() };
+    let scene = FpsSceneBuilder::new("spawn").spawn([0.0, 1.5, 0.0], 0.0).group(around_spawn).build_unchecked();
+    assert!(scene.validate().iter().any(|d| d.path == "player.spawn"));
+}
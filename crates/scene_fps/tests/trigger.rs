@@ -0,0 +1,94 @@
+use kengaai_scene_fps::{
+    ActionArg, ActionParseError, ActionRegistry, ArgType, FpsSceneBuilder, TriggerAction, TriggerActionError,
+};
+
+#[test]
+fn built_in_verbs_round_trip_through_display() {
+    for src in ["spawn_wave:grunt:3", "collect_key:red", "start_level:level2"] {
+        let action: TriggerAction = src.parse().unwrap();
+        assert_eq!(action.to_string(), src);
+    }
+    assert_eq!(
+        "spawn_wave:grunt:3".parse::<TriggerAction>(),
+        Ok(TriggerAction::SpawnWave { enemy: "grunt".into(), count: 3 })
+    );
+    // surrounding whitespace is ignored, the canonical form drops it
+    assert_eq!(" collect_key:red ".parse::<TriggerAction>().unwrap().to_string(), "collect_key:red");
+}
+
+#[test]
+fn colons_in_string_arguments_survive_a_round_trip() {
+    let registry = {
+        let mut r = ActionRegistry::new();
+        r.register("say", &[ArgType::Str, ArgType::Float]).unwrap();
+        r
+    };
+    let actions = [
+        TriggerAction::SpawnWave { enemy: "boss:phase2".into(), count: 1 },
+        TriggerAction::StartLevel { level: r"levels\a:b".into() },
+        TriggerAction::Custom { verb: "say".into(), args: vec![ActionArg::Str("hi: there".into()), ActionArg::Float(0.5)] },
+    ];
+    for action in actions {
+        let text = action.to_string();
+        assert_eq!(registry.parse(&text), Ok(action), "{text}");
+    }
+    assert_eq!(TriggerAction::CollectKey { color: "a:b".into() }.to_string(), r"collect_key:a\:b");
+}
+
+#[test]
+fn register_rejects_duplicate_and_invalid_verbs() {
+    let mut registry = ActionRegistry::new();
+    registry.register("open_door", &[ArgType::Str]).unwrap();
+    assert_eq!(registry.register("open_door", &[]), Err(ActionParseError::Duplicate("open_door".into())));
+    assert_eq!(registry.register("spawn_wave", &[]), Err(ActionParseError::Duplicate("spawn_wave".into())));
+    for verb in ["", "open:door", r"open\door"] {
+        assert_eq!(registry.register(verb, &[]), Err(ActionParseError::InvalidVerb(verb.into())));
+    }
+    assert_eq!(registry.signature("open_door"), Some(&[ArgType::Str][..]));
+}
+
+#[test]
+fn custom_verbs_parse_as_custom_actions() {
+    let mut registry = ActionRegistry::new();
+    registry.register("shake", &[ArgType::Float, ArgType::Int, ArgType::Bool]).unwrap();
+    let action = registry.parse("shake:0.25:3:true").unwrap();
+    assert_eq!(
+        action,
+        TriggerAction::Custom { verb: "shake".into(), args: vec![ActionArg::Float(0.25), ActionArg::Int(3), ActionArg::Bool(true)] }
+    );
+    assert_eq!(action.verb(), "shake");
+    assert_eq!(action.to_string(), "shake:0.25:3:true");
+    // only the registry that knows the verb accepts it
+    assert!(matches!("shake:0.25:3:true".parse::<TriggerAction>(), Err(ActionParseError::UnknownVerb { .. })));
+}
+
+#[test]
+fn bad_actions_report_the_trigger_index() {
+    let scene = FpsSceneBuilder::new("triggers")
+        .trigger([0.0; 3], [1.0; 3], "collect_key:red")
+        .trigger([4.0, 0.0, 0.0], [1.0; 3], "spawn_wave:grunt")
+        .build_unchecked();
+    let err = scene.trigger_actions(&ActionRegistry::new()).unwrap_err();
+    assert_eq!(
+        err,
+        TriggerActionError { index: 1, source: ActionParseError::Arity { verb: "spawn_wave".into(), expected: 2, found: 1 } }
+    );
+    assert_eq!(err.to_string(), "triggers[1].onEnter: `spawn_wave` takes 2 argument(s), got 1");
+
+    let mut scene = scene;
+    scene.triggers[1].on_enter = "spawn_wave:grunt:-2".into();
+    let err = scene.trigger_actions(&ActionRegistry::new()).unwrap_err();
+    assert_eq!(err.index, 1);
+    assert_eq!(
+        err.source,
+        ActionParseError::BadArg { verb: "spawn_wave".into(), index: 1, expected: ArgType::Int, value: "-2".into() }
+    );
+    scene.triggers[0].on_enter = "collect_key:".into();
+    let err = scene.trigger_actions(&ActionRegistry::new()).unwrap_err();
+    assert_eq!(err.index, 0);
+    assert!(matches!(err.source, ActionParseError::BadArg { expected: ArgType::Str, .. }), "{err}");
+
+    scene.triggers[0].on_enter = "collect_key:red".into();
+    scene.triggers[1].on_enter = "spawn_wave:grunt:2".into();
+    assert_eq!(scene.trigger_actions(&ActionRegistry::new()).unwrap().len(), 2);
+}
@@ -0,0 +1,182 @@
+use kengaai_scene_fps::{
+    has_errors, parse_scene, to_string_pretty, Bloom, Diagnostic, FpsScene, FpsSceneBuilder, Light, Material, PostProcess,
+    Severity, Shading, Tonemap, WeaponKind, MAX_LIGHTS,
+};
+
+fn paths(scene: &FpsScene) -> Vec<String> {
+    scene.validate().into_iter().map(|d| d.path).collect()
+}
+
+#[test]
+fn a_well_formed_scene_has_no_findings() {
+    let scene = FpsSceneBuilder::new("clean")
+        .spawn([0.0, 1.0, 0.0], 0.0)
+        .floor(10.0, 10.0)
+        .weapon("pistol", WeaponKind::Hitscan, 10.0, 2.0)
+        .point_light([0.0, 3.0, 0.0], [1.0; 3], 1.0)
+        .trigger([4.0, 1.0, 0.0], [1.0; 3], "win")
+        .build_unchecked();
+    assert_eq!(scene.validate(), Vec::<Diagnostic>::new());
+}
+
+#[test]
+fn findings_name_the_offending_field() {
+    let mut scene = FpsSceneBuilder::new("bad")
+        .spawn([0.0, 1.0, 0.0], 0.0)
+        .box_at([5.0, 0.0, 0.0], [1.0, -1.0, 1.0])
+        .box_at([5.0, 0.0, 4.0], [1.0; 3])
+        .weapon("pistol", WeaponKind::Hitscan, -1.0, 0.0)
+        .point_light([0.0, f32::NAN, 0.0], [1.0; 3], 1.0)
+        .trigger([0.0; 3], [1.0, 0.0, 1.0], "win")
+        .build_unchecked();
+    scene.level.boxes[1].color = [f32::INFINITY, 0.0, 0.0];
+    assert_eq!(
+        paths(&scene),
+        [
+            "weapons[0].damage",
+            "weapons[0].rate",
+            "level.boxes[0].size",
+            "level.boxes[1].color",
+            "lights[0].position",
+            "triggers[0].size"
+        ]
+    );
+    let diags = scene.validate();
+    assert!(diags.iter().all(Diagnostic::is_error), "{diags:?}");
+    assert_eq!(diags[2].to_string(), "error: level.boxes[0].size: all components must be positive, got [1.0, -1.0, 1.0]");
+}
+
+#[test]
+fn spawning_inside_a_box_is_an_error() {
+    let scene = FpsSceneBuilder::new("stuck").spawn([0.0, 1.0, 0.0], 0.0).box_at([0.0, 1.0, 0.0], [0.5; 3]).build_unchecked();
+    let diags = scene.validate();
+    assert_eq!(diags.len(), 1, "{diags:?}");
+    assert_eq!((diags[0].path.as_str(), diags[0].message.as_str()), ("player.spawn", "spawn point is inside level.boxes[0]"));
+    assert!(FpsSceneBuilder::new("stuck").spawn([0.0, 1.0, 0.0], 0.0).box_at([0.0, 1.0, 0.0], [0.5; 3]).build().is_err());
+}
+
+#[test]
+fn questionable_values_are_warnings_only() {
+    let mut scene = FpsSceneBuilder::new("odd")
+        .clear_color([1.5, 0.0, 0.0, 1.0])
+        .point_light([0.0, 3.0, 0.0], [1.0; 3], 1.0)
+        .build_unchecked();
+    // one light more than the renderer uses
+    scene.lights = vec![scene.lights[0].clone(); MAX_LIGHTS + 1];
+    let diags = scene.validate();
+    assert_eq!(paths(&scene), ["render.clearColor", "lights"]);
+    assert!(diags.iter().all(|d| d.severity == Severity::Warning));
+    assert!(!has_errors(&diags));
+}
+
+#[test]
+fn unknown_schema_tags_are_rejected() {
+    let mut scene = FpsSceneBuilder::new("future").build_unchecked();
+    scene.meta.schema = "KengaFPSSceneV99".into();
+    let diags = scene.validate();
+    assert_eq!(paths(&scene), ["meta.schema"]);
+    assert!(has_errors(&diags));
+}
+
+#[test]
+fn shading_and_box_materials_are_optional_fields() {
+    let plain = FpsSceneBuilder::new("plain").box_at([0.0; 3], [1.0; 3]).build().unwrap();
+    let json = to_string_pretty(&plain);
+    assert!(!json.contains("shading") && !json.contains("material"), "{json}");
+
+    let pbr = FpsSceneBuilder::new("pbr")
+        .shading(Shading::Pbr)
+        .box_at([0.0, 5.0, 0.0], [1.0; 3])
+        .material(Material { color: Some([0.9, 0.6, 0.2]), texture: None, metallic: 1.0, roughness: 1.5 })
+        .build_unchecked();
+    let json = to_string_pretty(&pbr);
+    assert!(json.contains("\"shading\": \"pbr\""), "{json}");
+    let back = parse_scene(&json).unwrap();
+    assert_eq!(back.render.shading, Shading::Pbr);
+    assert_eq!(back.level.boxes[0].material.as_ref().map(|m| m.metallic), Some(1.0));
+    let diags = back.validate();
+    assert!(diags.iter().any(|d| d.path == "level.boxes[0].material.roughness"), "{diags:?}");
+}
+
+#[test]
+fn shadow_settings_are_opt_in_per_light() {
+    let scene = FpsSceneBuilder::new("shadows")
+        .directional_light([0.0, -1.0, 0.0], [1.0; 3], 1.0)
+        .shadows(2048)
+        .directional_light([1.0, -1.0, 0.0], [1.0; 3], 1.0)
+        .shadows(8192)
+        .point_light([0.0, 3.0, 0.0], [1.0; 3], 1.0)
+        .build_unchecked();
+    let json = to_string_pretty(&scene);
+    assert!(json.contains("\"castShadows\": true") && json.contains("\"shadowResolution\": 2048"), "{json}");
+    let back = parse_scene(&json).unwrap();
+    assert!(!back.lights[2].cast_shadows);
+    assert_eq!(back.lights[2].shadow_resolution, None);
+
+    let found = paths(&back);
+    // the second directional caster is over the limit and asks for too large a map
+    assert!(found.contains(&"lights[1].castShadows".to_string()), "{found:?}");
+    assert!(found.contains(&"lights[1].shadowResolution".to_string()), "{found:?}");
+    assert!(!found.iter().any(|p| p.starts_with("lights[0]")), "{found:?}");
+}
+
+#[test]
+fn spot_lights_need_a_direction_and_a_sane_cone() {
+    let scene = FpsSceneBuilder::new("spots")
+        .point_light([0.0, 3.0, 0.0], [1.0; 3], 1.0)
+        .range(6.0)
+        .spot_light([0.0, 3.0, 0.0], [0.0, -1.0, 0.0], [1.0; 3], 4.0)
+        .cone(0.2, 0.4)
+        .spot_light([2.0, 3.0, 0.0], [0.0, -1.0, 0.0], [1.0; 3], 4.0)
+        .build()
+        .unwrap();
+    let json = to_string_pretty(&scene);
+    assert!(json.contains("\"kind\": \"spot\"") && json.contains("\"range\": 6.0") && json.contains("\"outerAngle\": 0.4"), "{json}");
+    // unset cone angles and ranges stay out of the file
+    assert_eq!(json.matches("innerAngle").count(), 1, "{json}");
+    assert_eq!(json.matches("range").count(), 1, "{json}");
+    let back = parse_scene(&json).unwrap();
+    assert_eq!(back.lights[1].direction, Some([0.0, -1.0, 0.0]));
+    assert_eq!(back.lights[2].outer_angle, None);
+
+    let mut bad = back.clone();
+    bad.lights[0].range = Some(0.0);
+    bad.lights[1].direction = None;
+    bad.lights[1].cast_shadows = true;
+    bad.lights[2].inner_angle = Some(0.8);
+    bad.lights.push(Light { direction: Some([0.0; 3]), outer_angle: Some(2.0), ..back.lights[2].clone() });
+    assert_eq!(
+        paths(&bad),
+        [
+            "lights[0].range",
+            "lights[1].direction",
+            "lights[2].innerAngle",
+            "lights[3].direction",
+            "lights[3].outerAngle",
+            "lights[1].castShadows"
+        ]
+    );
+}
+
+#[test]
+fn post_process_section_keeps_only_what_was_set() {
+    let json = to_string_pretty(&FpsSceneBuilder::new("plain").build_unchecked());
+    assert!(!json.contains("postProcess"), "{json}");
+
+    let post = PostProcess {
+        tonemap: Tonemap::Reinhard,
+        bloom: Some(Bloom { threshold: Some(0.8), ..Default::default() }),
+        vignette: 1.5,
+        ..Default::default()
+    };
+    let scene = FpsSceneBuilder::new("post").post_process(post.clone()).build_unchecked();
+    let json = to_string_pretty(&scene);
+    assert!(json.contains("\"tonemap\": \"reinhard\"") && json.contains("\"threshold\": 0.8"), "{json}");
+    assert!(!json.contains("exposure") && !json.contains("intensity"), "{json}");
+    let back = parse_scene(&json).unwrap();
+    assert_eq!(back.render.post_process, Some(post));
+    assert_eq!(paths(&back), ["render.postProcess.vignette"]);
+
+    let json = json.replace("\"tonemap\": \"reinhard\"", "\"tonemap\": \"filmic\"");
+    assert!(parse_scene(&json).is_err());
+}
@@ -1,8 +1,8 @@
 use anyhow::Result;
 use kengaai_fps::{FpsController, FpsRenderer};
-use kengaai_scene_fps::load_scene;
-use log::{error, info, warn};
-use std::env;
+use kengaai_scene_fps::{FpsScene, FpsSceneBuilder};
+use log::{error, info};
+use std::f32::consts::PI;
 use std::time::Instant;
 use winit::{
     event::{DeviceEvent, ElementState, Event, MouseButton, WindowEvent},
@@ -24,7 +24,7 @@ fn main() -> Result<()> {
     check_system_requirements()?;
     
     // Создание минимального тестового уровня
-    let scene = create_test_scene();
+    let scene = create_test_scene()?;
     
     info!("Creating test window...");
     let event_loop = EventLoop::new().expect("event loop");
@@ -172,54 +172,16 @@ fn check_system_requirements() -> Result<()> {
 }
 
 /// Создает минимальную тестовую сцену
-fn create_test_scene() -> kengaai_scene_fps::FpsScene {
-    use kengaai_scene_fps::*;
-    
-    FpsScene {
-        meta: Meta {
-            schema: "KengaFPSSceneV0".to_string(),
-            version: "0.1.0".to_string(),
-            name: "gpu_test".to_string(),
-        },
-        render: Render {
-            clear_color: [0.1, 0.2, 0.3, 1.0],
-        },
-        player: Player {
-            spawn: [0.0, 1.5, 4.0],
-            yaw: 3.1415,
-            pitch: 0.0,
-            r#move: Move {
-                speed: 4.5,
-                run: 7.5,
-            },
-        },
-        weapons: vec![],
-        level: Level {
-            boxes: vec![
-                BoxDef {
-                    pos: [0.0, 0.0, 0.0],
-                    size: [5.0, 0.5, 5.0],
-                    rot_y: 0.0,
-                    color: [0.3, 0.3, 0.4],
-                    texture: None,
-                },
-                BoxDef {
-                    pos: [0.0, 1.0, 0.0],
-                    size: [1.0, 1.0, 1.0],
-                    rot_y: 0.0,
-                    color: [1.0, 0.2, 0.2],
-                    texture: None,
-                }
-            ],
-            meshes: vec![],
-        },
-        lights: vec![],
-        particles: vec![],
-        enemies: vec![],
-        sounds: vec![],
-        triggers: vec![],
-        goals: None,
-    }
+fn create_test_scene() -> Result<FpsScene> {
+    let scene = FpsSceneBuilder::new("gpu_test")
+        .clear_color([0.1, 0.2, 0.3, 1.0])
+        .spawn([0.0, 1.5, 4.0], PI)
+        .floor(5.0, 5.0)
+        .color([0.3, 0.3, 0.4])
+        .box_at([0.0, 1.0, 0.0], [1.0, 1.0, 1.0])
+        .color([1.0, 0.2, 0.2])
+        .build()?;
+    Ok(scene)
 }
 
 /// Показывает рекомендации по апгрейду оборудования
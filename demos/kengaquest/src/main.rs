@@ -1,7 +1,7 @@
 use anyhow::Result;
 use kengaai_fps::{FpsController, FpsRenderer};
-use kengaai_scene_fps::load_scene;
-use log::{error, info};
+use kengaai_scene_fps::{has_errors, load_scene};
+use log::{error, info, warn};
 use std::env;
 use std::time::Instant;
 use std::path::Path;
@@ -20,7 +20,14 @@ fn main() -> Result<()> {
     let level_path = args.get(1).cloned().unwrap_or_else(|| default_level.to_string());
     
     info!("Loading level: {}", level_path);
-    let scene = load_scene(&level_path)?;
+    let scene = load_scene(&level_path)?.flatten()?;
+    let diags = scene.validate();
+    for d in &diags {
+        warn!("{}", d);
+    }
+    if has_errors(&diags) {
+        anyhow::bail!("level {} failed validation", level_path);
+    }
     
     // Get the directory of the scene file for relative texture paths
     let scene_dir = Path::new(&level_path).parent().unwrap_or_else(|| Path::new("."));
@@ -39,7 +46,7 @@ fn main() -> Result<()> {
     let mut renderer = FpsRenderer::new(window, &scene)?;
     
     // Load textures for boxes that have them
-    for box_def in &scene.level.boxes {
+    for box_def in &scene.level.world_boxes() {
         if let Some(ref texture_path) = box_def.texture {
             // Try to load the texture from the scene directory
             let full_path = scene_dir.join(texture_path);
@@ -53,6 +60,18 @@ fn main() -> Result<()> {
         }
     }
     
+    // Load mesh files and the textures their materials name
+    for mesh_def in &scene.level.world_meshes() {
+        if let Err(e) = renderer.load_mesh_file(mesh_def.file.clone(), scene_dir.join(&mesh_def.file)) {
+            error!("Failed to load mesh {}: {:#}", mesh_def.file, e);
+        }
+        if let Some(texture_path) = mesh_def.material.as_ref().and_then(|m| m.texture.as_ref()) {
+            if let Err(e) = renderer.load_texture_from_file(texture_path.clone(), scene_dir.join(texture_path)) {
+                error!("Failed to load texture {}: {}", texture_path, e);
+            }
+        }
+    }
+    
     // Load floor texture if it exists
     let floor_texture_path = scene_dir.join("floor.png");
     if floor_texture_path.exists() {
@@ -112,6 +131,7 @@ fn main() -> Result<()> {
                 
                 ctrl.step(&mut renderer.camera, dt);
                 renderer.update_camera();
+                renderer.particles_mut().step(dt);
                 
                 if let Err(e) = renderer.render() {
                     error!("render: {e:?}");
@@ -26,8 +26,8 @@ fn main() -> Result<()> {
             s
         },
         Err(e) => {
-            error!("✗ Ошибка загрузки уровня: {:?}", e);
-            return Err(e);
+            error!("✗ Ошибка загрузки уровня:\n{}", e.report());
+            return Err(e.into());
         }
     };
     
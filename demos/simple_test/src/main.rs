@@ -1,7 +1,8 @@
 use anyhow::Result;
 use kengaai_fps::{FpsController, FpsRenderer};
-use kengaai_scene_fps::{FpsScene, Render, Player, Move, Level, BoxDef, MeshDef};
+use kengaai_scene_fps::FpsSceneBuilder;
 use log::info;
+use std::f32::consts::PI;
 use std::time::Instant;
 use winit::{
     event::{DeviceEvent, ElementState, Event, MouseButton, WindowEvent},
@@ -13,51 +14,12 @@ fn main() -> Result<()> {
     env_logger::init();
     
     // Создаем минимальную сцену для тестирования
-    let scene = FpsScene {
-        meta: kengaai_scene_fps::Meta {
-            schema: "KengaFPSSceneV0".to_string(),
-            version: "0.1.0".to_string(),
-            name: "test_scene".to_string(),
-        },
-        render: Render {
-            clear_color: [0.1, 0.2, 0.3, 1.0],
-        },
-        player: Player {
-            spawn: [0.0, 1.5, 4.0],
-            yaw: 3.1415,
-            pitch: 0.0,
-            r#move: Move {
-                speed: 4.5,
-                run: 7.5,
-            },
-        },
-        weapons: vec![],
-        level: Level {
-            boxes: vec![
-                BoxDef {
-                    pos: [0.0, 0.0, 0.0],
-                    size: [10.0, 0.5, 10.0],
-                    rot_y: 0.0,
-                    color: [0.5, 0.5, 0.5],
-                    texture: None,
-                },
-                BoxDef {
-                    pos: [0.0, 1.0, -2.0],
-                    size: [1.0, 1.0, 1.0],
-                    rot_y: 0.0,
-                    color: [1.0, 0.0, 0.0],
-                    texture: None,
-                },
-            ],
-            meshes: vec![],
-        },
-        lights: vec![],
-        particles: vec![],
-        enemies: vec![],
-        sounds: vec![],
-        triggers: vec![],
-        goals: None,
-    };
+    let scene = FpsSceneBuilder::new("test_scene")
+        .clear_color([0.1, 0.2, 0.3, 1.0])
+        .spawn([0.0, 1.5, 4.0], PI)
+        .floor(10.0, 10.0)
+        .box_at([0.0, 1.0, -2.0], [1.0, 1.0, 1.0]).color([1.0, 0.0, 0.0])
+        .build()?;
     
     info!("Создана тестовая сцена");
     